@@ -1,88 +1,202 @@
 //! app
-use std::{
-  collections::HashMap,
-  sync::{Arc, Mutex},
-  time::{Duration, Instant},
-};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use crate::{
   components::{
-    article, comment, migration,
+    article, avatar, ban, comment, forbidden_word, migration, page, report,
     ui::{self, handler::ui_page},
     user,
   },
   config::EnvConfig,
   error::AppError,
+  helpers::{
+    alias::PageAliases,
+    avatar::{AvatarCache, AvatarProvider},
+    ban::Bans,
+    bayes::BayesClassifier,
+    emoji::EmojiPresets,
+    forbidden::WordFilter,
+    header::parse_trusted_proxies,
+    highlight::Highlighter,
+    limiter::{Policy, Quota, RateLimiter},
+    link::LinkPolicy,
+    markdown::{sanitizer, MarkdownOptions, Renderer},
+    page::PageSettings,
+    pow::ProofOfWork,
+    region::ClientInfo,
+    spam,
+    store::Store,
+  },
   repository::RepositoryManager,
+  schema,
 };
 
 use actix_cors::Cors;
 use actix_web::{
-  middleware,
+  middleware, rt,
   web::{self, ServiceConfig},
   App, HttpResponse, HttpServer,
 };
+use instant_akismet::AkismetClient;
+use ipnet::IpNet;
 use sea_orm::{Database, DatabaseConnection};
-use tracing::info;
+use tracing::{info, warn};
 
-#[derive(Debug)]
-pub struct RateLimiter {
-  qps: u64,
-  counter: Mutex<HashMap<String, (usize, Instant)>>,
-}
-
-impl RateLimiter {
-  fn new(qps: u64) -> Self {
-    RateLimiter {
-      qps,
-      counter: Mutex::new(HashMap::new()),
-    }
-  }
-  pub fn check_and_update(&self, client_ip: &str, count: usize) -> bool {
-    let mut counter = self.counter.lock().unwrap();
-    counter.retain(|_, &mut (_, timestamp)| timestamp.elapsed() < Duration::from_secs(self.qps));
-    match counter.get_mut(client_ip) {
-      Some((cnt, timestamp)) => {
-        if *cnt >= count {
-          false
-        } else {
-          *cnt += 1;
-          *timestamp = Instant::now();
-          true
-        }
-      }
-      None => {
-        counter.insert(client_ip.to_string(), (1, Instant::now()));
-        true
-      }
-    }
-  }
-}
+#[cfg(feature = "leancloud")]
+use crate::repository::LeanCloud;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
   pub repo: RepositoryManager,
+  pub store: Store,
   pub rate_limiter: Arc<RateLimiter>,
+  pub pow: Arc<ProofOfWork>,
   pub conn: DatabaseConnection,
   pub jwt_token: String,
   pub levels: Option<String>,
   pub comment_audit: bool,
+  pub trust_threshold: u64,
+  pub report_threshold: u64,
+  pub mention_limit: usize,
   pub login: String,
-  pub forbidden_words: Vec<String>,
+  pub word_filter: Arc<WordFilter>,
+  pub aliases: Arc<PageAliases>,
+  pub bans: Arc<Bans>,
+  pub page_settings: Arc<PageSettings>,
+  pub link_policy: Arc<LinkPolicy>,
+  pub renderer: Arc<Renderer>,
+  pub client_info: Arc<ClientInfo>,
+  pub avatar: Arc<AvatarProvider>,
+  pub avatar_cache: Option<Arc<AvatarCache>>,
+  pub akismet: Option<Arc<AkismetClient>>,
+  pub bayes: Arc<BayesClassifier>,
+  pub spam_classifier: String,
+  pub bayes_threshold: f64,
+  pub trusted_proxies: Vec<IpNet>,
+  /// Query parameters kept in page urls
+  pub page_query: Vec<String>,
+}
+
+/// Bumped in the store whenever an administrator changes the bans, page aliases, page
+/// settings or forbidden words, which every instance keeps in memory
+const CACHE_VERSION: &str = "version:cache";
+/// How long the version outlives the last change
+const CACHE_VERSION_TTL: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+/// How often an instance checks whether another one changed the cached data
+const CACHE_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+impl AppState {
+  async fn load_caches(&self) -> Result<(), AppError> {
+    self.bans.reload(&self.conn).await?;
+    self.aliases.reload(&self.conn).await?;
+    self.page_settings.reload(&self.conn).await?;
+    self.word_filter.reload(&self.conn).await
+  }
+
+  /// Reload the data kept in memory after an administrator changed it. The other instances
+  /// sharing the store follow within `CACHE_SYNC_INTERVAL`
+  pub async fn reload_caches(&self) -> Result<(), AppError> {
+    self.load_caches().await?;
+    self.store.incr(CACHE_VERSION, CACHE_VERSION_TTL).await?;
+    Ok(())
+  }
+
+  /// Reload when the version in the store differs from the one seen last time
+  async fn sync_caches(&self, seen: &mut Option<u64>) -> Result<(), AppError> {
+    let version = self
+      .store
+      .get(CACHE_VERSION)
+      .await?
+      .and_then(|version| version.parse().ok())
+      .unwrap_or(0);
+    if *seen != Some(version) {
+      self.load_caches().await?;
+      *seen = Some(version);
+    }
+    Ok(())
+  }
+}
+
+/// A state with every optional feature off and comments, users and counters in memory
+#[cfg(test)]
+pub async fn test_state() -> AppState {
+  let conn = Database::connect("sqlite::memory:").await.unwrap();
+  schema::sync(&conn).await.unwrap();
+  let store = Store::connect("memory", &conn).await.unwrap();
+  AppState {
+    repo: RepositoryManager::memory(),
+    store: store.clone(),
+    rate_limiter: Arc::new(RateLimiter::new(
+      HashMap::new(),
+      Duration::from_secs(600),
+      store.clone(),
+    )),
+    pow: Arc::new(ProofOfWork::new("test".to_string(), 0, 0, store)),
+    jwt_token: "test".to_string(),
+    levels: None,
+    comment_audit: false,
+    trust_threshold: 0,
+    report_threshold: 0,
+    mention_limit: 5,
+    login: String::new(),
+    word_filter: Arc::new(WordFilter::load(&conn, &[]).await.unwrap()),
+    aliases: Arc::new(PageAliases::load(&conn).await.unwrap()),
+    bans: Arc::new(Bans::load(&conn).await.unwrap()),
+    page_settings: Arc::new(PageSettings::load(&conn).await.unwrap()),
+    link_policy: Arc::new(LinkPolicy::new(0, &[])),
+    renderer: Arc::new(Renderer::new(
+      "https://example.com",
+      None,
+      sanitizer(&[], &[], &[]),
+      MarkdownOptions::new(&[], true, true, false),
+      EmojiPresets::default(),
+      None,
+    )),
+    client_info: Arc::new(ClientInfo::load(None, true, true)),
+    avatar: Arc::new(AvatarProvider::new(
+      "gravatar",
+      None,
+      "mp".to_string(),
+      None,
+    )),
+    avatar_cache: None,
+    akismet: None,
+    bayes: Arc::new(BayesClassifier::load(&conn, 10).await.unwrap()),
+    spam_classifier: "bayes".to_string(),
+    bayes_threshold: 0.9,
+    trusted_proxies: vec![],
+    page_query: vec![],
+    conn,
+  }
 }
 
 async fn health_check() -> HttpResponse {
   HttpResponse::Ok().json(serde_json::json!({"status": "OK"}))
 }
 
+async fn highlight_css(state: web::Data<AppState>) -> HttpResponse {
+  match state.renderer.highlight_css() {
+    Some(css) => HttpResponse::Ok()
+      .content_type("text/css; charset=utf-8")
+      .body(css.to_string()),
+    None => HttpResponse::NotFound().finish(),
+  }
+}
+
 pub fn config_app(cfg: &mut ServiceConfig) {
   cfg.service(
     web::scope("/api")
       .configure(article::config)
+      .configure(avatar::config)
+      .configure(ban::config)
       .configure(comment::config)
+      .configure(forbidden_word::config)
       .configure(user::config)
       .configure(migration::config)
-      .route("/health", web::get().to(health_check)),
+      .configure(page::config)
+      .configure(report::config)
+      .route("/health", web::get().to(health_check))
+      .route("/highlight.css", web::get().to(highlight_css)),
   );
   cfg.route("/ui", web::get().to(ui_page));
   cfg.service(web::scope("/ui").configure(ui::config));
@@ -90,6 +204,20 @@ pub fn config_app(cfg: &mut ServiceConfig) {
   cfg.route("/", web::get().to(health_check));
 }
 
+fn build_quotas(policies: [(Policy, String); 8]) -> HashMap<Policy, Quota> {
+  let mut quotas = HashMap::new();
+  for (policy, value) in policies {
+    match Quota::parse(&value) {
+      Some(Some(quota)) => {
+        quotas.insert(policy, quota);
+      }
+      Some(None) => {}
+      None => warn!("Ignoring invalid rate limit {value} for {policy:?}"),
+    }
+  }
+  quotas
+}
+
 pub async fn start() -> Result<(), AppError> {
   let EnvConfig {
     workers,
@@ -98,28 +226,234 @@ pub async fn start() -> Result<(), AppError> {
     database_url,
     jwt_token,
     levels,
+    site_url,
+    server_url,
     akismet_key,
+    akismet_url,
+    spam_classifier,
+    bayes_threshold,
+    bayes_min_documents,
     ipqps,
+    rate_limit_login,
+    rate_limit_register,
+    rate_limit_like,
+    rate_limit_counter,
+    rate_limit_preview,
+    rate_limit_report,
+    rate_limit_avatar,
     comment_audit,
+    trust_threshold,
+    report_threshold,
+    mention_limit,
+    ip_database,
+    avatar: avatar_provider,
+    avatar_proxy,
+    avatar_default,
+    avatar_cache,
+    avatar_cache_ttl,
+    avatar_cache_size,
+    disable_region,
+    disable_useragent,
     login,
     forbidden_words,
+    max_links,
+    blocked_domains,
+    link_redirect,
+    markdown_extensions,
+    markdown_html,
+    markdown_image,
+    emoji,
+    math_renderer,
+    highlight,
+    sanitize_tags,
+    sanitize_attributes,
+    sanitize_url_schemes,
+    pow_difficulty,
+    pow_max_difficulty,
+    trusted_proxies,
+    page_query,
+    store,
+    #[cfg(feature = "leancloud")]
+    lean_id,
+    #[cfg(feature = "leancloud")]
+    lean_key,
+    #[cfg(feature = "leancloud")]
+    lean_master_key,
+    #[cfg(feature = "leancloud")]
+    lean_server,
     ..
   } = EnvConfig::load_env()?;
-  let conn = Database::connect(database_url).await?;
+  // `memory` keeps comments, users and counters in memory, the remaining tables live in
+  // an in-memory SQLite database
+  let in_memory = database_url == "memory";
+  let conn = if in_memory {
+    warn!("DATABASE_URL is memory, data is lost on restart");
+    Database::connect("sqlite::memory:").await?
+  } else {
+    Database::connect(database_url).await?
+  };
   conn.ping().await?;
-  if akismet_key != "false" {
+  schema::sync(&conn).await?;
+  let store = Store::connect(&store, &conn).await?;
+  let bayes = BayesClassifier::load(&conn, bayes_min_documents).await?;
+  let word_filter = WordFilter::load(&conn, &forbidden_words).await?;
+  let aliases = PageAliases::load(&conn).await?;
+  let page_settings = PageSettings::load(&conn).await?;
+  let bans = Bans::load(&conn).await?;
+  let avatar_cache = match avatar_cache {
+    Some(dir) if !dir.is_empty() => Some(AvatarCache::new(
+      &dir,
+      Duration::from_secs(avatar_cache_ttl),
+      avatar_cache_size * 1024 * 1024,
+    )?),
+    _ => None,
+  };
+  let avatar_url = match (&avatar_cache, &server_url) {
+    (Some(_), Some(server_url)) => Some(format!("{}/api/avatar", server_url.trim_end_matches('/'))),
+    (Some(_), None) => {
+      warn!("AVATAR_CACHE requires SERVER_URL, avatars are linked from the provider");
+      None
+    }
+    _ => None,
+  };
+  let redirect_url = match (link_redirect, server_url) {
+    (true, Some(server_url)) => Some(format!("{}/ui/redirect", server_url.trim_end_matches('/'))),
+    (true, None) => {
+      warn!("LINK_REDIRECT requires SERVER_URL, external links are not redirected");
+      None
+    }
+    _ => None,
+  };
+  let math = match math_renderer.as_str() {
+    "mathml" => true,
+    "false" => false,
+    renderer => {
+      warn!("Unknown MATH_RENDERER {renderer}, math is not rendered");
+      false
+    }
+  };
+  let highlighter = match highlight.as_str() {
+    "false" => None,
+    theme => Highlighter::new(theme),
+  };
+  let renderer = Renderer::new(
+    &site_url,
+    redirect_url,
+    sanitizer(&sanitize_tags, &sanitize_attributes, &sanitize_url_schemes),
+    MarkdownOptions::new(&markdown_extensions, markdown_html, markdown_image, math),
+    EmojiPresets::load(&emoji).await,
+    highlighter,
+  );
+  let akismet = spam::create_client(site_url, akismet_key, akismet_url).await?;
+  if akismet.is_some() {
     info!("The anti-spam system has been activated")
   }
+  let pow = ProofOfWork::new(
+    jwt_token.clone(),
+    pow_difficulty,
+    pow_max_difficulty,
+    store.clone(),
+  );
+  if pow.enabled() {
+    info!("The proof-of-work challenge has been activated")
+  }
+  // IPQPS is the minimum interval in seconds between two comments from the same client
+  let quotas = build_quotas([
+    (Policy::Comment, format!("1/{ipqps}")),
+    (Policy::Login, rate_limit_login),
+    (Policy::Register, rate_limit_register),
+    (Policy::Like, rate_limit_like),
+    (Policy::Counter, rate_limit_counter),
+    (Policy::Preview, rate_limit_preview),
+    (Policy::Report, rate_limit_report),
+    (Policy::Avatar, rate_limit_avatar),
+  ]);
+  let rate_limiter = Arc::new(RateLimiter::new(
+    quotas,
+    Duration::from_secs(ipqps.max(60) * 10),
+    store.clone(),
+  ));
+  let evicting_store = store.clone();
+  rt::spawn(async move {
+    let mut interval = rt::time::interval(Duration::from_secs(60));
+    loop {
+      interval.tick().await;
+      evicting_store.evict().await.ok();
+    }
+  });
+  let repo = if in_memory {
+    RepositoryManager::memory()
+  } else {
+    RepositoryManager::new(conn.clone())
+  };
+  // Urls in memory and in LeanStorage are left as they are
+  let normalize = !in_memory;
+  // Comments, users and counters move to LeanStorage, DATABASE_URL keeps the other tables
+  #[cfg(feature = "leancloud")]
+  let (repo, normalize) = match lean_id.filter(|id| !id.is_empty()) {
+    Some(lean_id) => {
+      let lean = LeanCloud::connect(
+        lean_id,
+        lean_key.unwrap_or_default(),
+        lean_master_key,
+        lean_server,
+        conn.clone(),
+      )
+      .await?;
+      (RepositoryManager::leancloud(lean), false)
+    }
+    None => (repo, normalize),
+  };
+  if normalize {
+    migration::service::normalize_urls(&conn, &page_query).await?;
+  }
   let state = AppState {
-    repo: RepositoryManager::new(conn.clone()),
+    repo,
     conn,
+    pow: Arc::new(pow),
+    store,
     jwt_token,
     levels,
     login,
     comment_audit,
-    forbidden_words,
-    rate_limiter: Arc::new(RateLimiter::new(ipqps)),
+    trust_threshold,
+    report_threshold,
+    mention_limit,
+    word_filter: Arc::new(word_filter),
+    aliases: Arc::new(aliases),
+    page_settings: Arc::new(page_settings),
+    bans: Arc::new(bans),
+    link_policy: Arc::new(LinkPolicy::new(max_links, &blocked_domains)),
+    renderer: Arc::new(renderer),
+    avatar: Arc::new(AvatarProvider::new(
+      &avatar_provider,
+      avatar_proxy,
+      avatar_default,
+      avatar_url,
+    )),
+    avatar_cache: avatar_cache.map(Arc::new),
+    client_info: Arc::new(ClientInfo::load(
+      ip_database.as_deref(),
+      disable_region,
+      disable_useragent,
+    )),
+    akismet: akismet.map(Arc::new),
+    bayes: Arc::new(bayes),
+    spam_classifier,
+    bayes_threshold,
+    trusted_proxies: parse_trusted_proxies(&trusted_proxies),
+    page_query,
+    rate_limiter,
   };
+  let synced = state.clone();
+  rt::spawn(async move {
+    let mut interval = rt::time::interval(CACHE_SYNC_INTERVAL);
+    let mut seen = None;
+    loop {
+      interval.tick().await;
+      synced.sync_caches(&mut seen).await.ok();
+    }
+  });
   HttpServer::new(move || {
     App::new()
       .wrap(middleware::Logger::default())
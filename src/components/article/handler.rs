@@ -1,13 +1,21 @@
 use crate::prelude::*;
 use crate::{
   app::AppState,
-  components::article::{model::*, service},
+  components::{
+    article::{model::*, service},
+    user::model::verify_user,
+  },
+  helpers::{
+    ban::Ban,
+    header::{extract_ip, extract_token},
+    limiter::Policy,
+  },
 };
 
 use actix_web::{
   get, post,
   web::{Data, Json, Query},
-  HttpResponse,
+  HttpRequest, HttpResponse,
 };
 
 #[get("/article")]
@@ -21,6 +29,7 @@ async fn get_article(data: Data<AppState>, query: Query<GetArticleQuery>) -> Htt
 
 #[post("/article")]
 async fn update_article(
+  req: HttpRequest,
   data: Data<AppState>,
   query: Query<UpdateArticleQuery>,
   body: Json<UpdateArticleBody>,
@@ -31,8 +40,34 @@ async fn update_article(
     r#type,
   }) = body;
   let Query(UpdateArticleQuery { lang }) = query;
-  match service::update_article(&data, action, path, r#type).await {
-    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), Some(&lang))),
-    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, Some(&lang))),
+  let limit = data
+    .rate_limiter
+    .check(Policy::Counter, &extract_ip(&req, &data.trusted_proxies))
+    .await;
+  if !limit.allowed {
+    return limit
+      .response()
+      .json(Response::<()>::error(Code::FrequencyLimited, Some(&lang)));
+  }
+  let ban = match extract_token(&req) {
+    Ok(token) if r#type != "time" => verify_user(&token, &data.jwt_token, &data.repo)
+      .await
+      .ok()
+      .and_then(|user| data.bans.check_user(&user)),
+    _ => None,
+  };
+  let result = match ban {
+    Some(Ban::Outright) => Err(Code::Forbidden),
+    // A shadowbanned vote looks accepted but is not counted
+    Some(Ban::Shadow) => service::get_counters(&data, &path).await,
+    None => service::update_article(&data, action, path, r#type).await,
+  };
+  match result {
+    Ok(data) => limit
+      .response()
+      .json(Response::success(Some(data), Some(&lang))),
+    Err(err) => limit
+      .response()
+      .json(Response::<()>::error(err, Some(&lang))),
   }
 }
@@ -1,8 +1,27 @@
-use sea_orm::{ActiveModelTrait, IntoActiveModel, Set};
+use sea_orm::{ActiveModelTrait, IntoActiveModel};
 use serde_json::{json, Value};
 
 use crate::prelude::*;
-use crate::{app::AppState, components::article::model::*, entities::wl_counter};
+use crate::{app::AppState, entities::wl_counter, helpers::url};
+
+async fn get_counter(state: &AppState, path: &str) -> Result<Option<wl_counter::Model>, Code> {
+  let counter = state
+    .repo
+    .counter()
+    .get_counter(
+      &state
+        .aliases
+        .resolve(url::normalize(path, &state.page_query)),
+    )
+    .await
+    .map_err(AppError::from)?;
+  Ok(counter)
+}
+
+/// The counters of a page, left as they are
+pub async fn get_counters(state: &AppState, path: &str) -> Result<Vec<wl_counter::Model>, Code> {
+  Ok(get_counter(state, path).await?.into_iter().collect())
+}
 
 pub async fn get_article(
   state: &AppState,
@@ -12,15 +31,13 @@ pub async fn get_article(
   let mut data = vec![];
   if r#type == "time" {
     for path in path.split(',') {
-      if has_counter(&CounterQueryBy::Url(path), &state.conn).await? {
-        let counter = get_counter(&CounterQueryBy::Url(path), &state.conn).await?;
-        data.push(json!({"time": counter.time}));
-      } else {
-        data.push(json!({"time": 0}));
+      match get_counter(state, path).await? {
+        Some(counter) => data.push(json!({"time": counter.time})),
+        None => data.push(json!({"time": 0})),
       }
     }
   } else {
-    let model = get_counter(&CounterQueryBy::Url(&path), &state.conn).await?;
+    let model = get_counter(state, &path).await?.ok_or(Code::Error)?;
     data.push(json!({
       "reaction0": model.reaction0,
       "reaction1": model.reaction1,
@@ -41,18 +58,16 @@ pub async fn update_article(
 ) -> Result<Vec<wl_counter::Model>, Code> {
   let mut data = vec![];
   if r#type == "time" {
-    let counter = if has_counter(&CounterQueryBy::Url(&path), &state.conn).await? {
-      get_counter(&CounterQueryBy::Url(&path), &state.conn).await?
-    } else {
-      create_counter(path, &state.conn).await?
-    };
-    let mut active_counter = counter.into_active_model();
-    active_counter.time = Set(Some(
-      active_counter.time.take().unwrap_or(Some(0)).unwrap_or(0) + 1,
-    ));
     data.push(
-      active_counter
-        .update(&state.conn)
+      state
+        .repo
+        .counter()
+        .update_counter(
+          &state
+            .aliases
+            .resolve(url::normalize(&path, &state.page_query)),
+          1,
+        )
         .await
         .map_err(AppError::from)?,
     )
@@ -130,12 +145,14 @@ pub async fn update_article(
       }
       counter
     }
-    let mut counter = get_counter(&CounterQueryBy::Url(&path), &state.conn).await?;
+    let mut counter = get_counter(state, &path).await?.ok_or(Code::Error)?;
     counter = set_reaction_value(counter, &r#type, action);
+    // Every column is written back, the reaction was changed on the model itself
     data.push(
-      counter
-        .into_active_model()
-        .update(&state.conn)
+      state
+        .repo
+        .counter()
+        .save_counter(counter.into_active_model().reset_all())
         .await
         .map_err(AppError::from)?,
     );
@@ -0,0 +1,42 @@
+use actix_web::{
+  get,
+  http::{
+    header::{CacheControl, CacheDirective},
+    StatusCode,
+  },
+  web::{Data, Path},
+  HttpRequest, HttpResponse,
+};
+
+use crate::{
+  app::AppState,
+  components::avatar::service,
+  helpers::{avatar::is_hash, header::extract_ip},
+};
+
+#[get("/avatar/{hash}")]
+pub async fn get_avatar(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<String>,
+) -> HttpResponse {
+  let hash = path.into_inner().to_lowercase();
+  if !is_hash(&hash) {
+    return HttpResponse::BadRequest().finish();
+  }
+  let client_ip = extract_ip(&req, &state.trusted_proxies);
+  match service::get_avatar(&state, &hash, &client_ip).await {
+    Ok(Some((bytes, content_type, max_age))) => HttpResponse::Ok()
+      .content_type(content_type)
+      .insert_header(CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(max_age),
+      ]))
+      .body(bytes),
+    Ok(None) => HttpResponse::NotFound().finish(),
+    Err(limit) => limit
+      .response()
+      .status(StatusCode::TOO_MANY_REQUESTS)
+      .finish(),
+  }
+}
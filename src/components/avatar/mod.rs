@@ -0,0 +1,8 @@
+mod handler;
+mod service;
+
+use actix_web::web::ServiceConfig;
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::get_avatar);
+}
@@ -0,0 +1,33 @@
+use crate::{
+  app::AppState,
+  helpers::limiter::{Policy, RateLimit},
+};
+
+/// The avatar image, its content type and how long clients may cache it. Downloads from
+/// the provider count against the client's `avatar` rate limit, an expired avatar is
+/// served stale while the client is limited or the provider cannot be reached
+pub async fn get_avatar(
+  state: &AppState,
+  hash: &str,
+  client_ip: &str,
+) -> Result<Option<(Vec<u8>, String, u32)>, RateLimit> {
+  let Some(cache) = state.avatar_cache.as_ref() else {
+    return Ok(None);
+  };
+  let max_age = cache.ttl().as_secs() as u32;
+  let cached = cache.read(hash).await;
+  if let Some((bytes, content_type, false)) = cached {
+    return Ok(Some((bytes, content_type, max_age)));
+  }
+  let limit = state.rate_limiter.check(Policy::Avatar, client_ip).await;
+  let fetched = if limit.allowed {
+    cache.fetch(&state.avatar, hash).await
+  } else {
+    None
+  };
+  match fetched.or(cached.map(|(bytes, content_type, _)| (bytes, content_type))) {
+    Some((bytes, content_type)) => Ok(Some((bytes, content_type, max_age))),
+    None if !limit.allowed => Err(limit),
+    None => Ok(None),
+  }
+}
@@ -0,0 +1,81 @@
+use actix_web::{
+  delete, get, post, put,
+  web::{Data, Json, Path, Query},
+  HttpRequest, HttpResponse,
+};
+
+use crate::{
+  app::AppState,
+  components::ban::{model::*, service},
+  entities::Id,
+  helpers::header::extract_token,
+  response::{Code, Response},
+};
+
+#[get("/ban")]
+async fn get_bans(req: HttpRequest, state: Data<AppState>, query: Query<BanQuery>) -> HttpResponse {
+  let Query(BanQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::get_bans(&state, token).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[post("/ban")]
+async fn create_ban(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<BanQuery>,
+  body: Json<BanBody>,
+) -> HttpResponse {
+  let Query(BanQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::create_ban(&state, token, body.into_inner()).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[put("/ban/{id}")]
+async fn update_ban(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<Id>,
+  query: Query<BanQuery>,
+  body: Json<BanBody>,
+) -> HttpResponse {
+  let Query(BanQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::update_ban(&state, token, path.into_inner(), body.into_inner()).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[delete("/ban/{id}")]
+async fn delete_ban(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<Id>,
+  query: Query<BanQuery>,
+) -> HttpResponse {
+  let Query(BanQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::delete_ban(&state, token, path.into_inner()).await {
+    Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
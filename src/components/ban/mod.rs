@@ -0,0 +1,12 @@
+mod handler;
+mod model;
+mod service;
+
+use actix_web::web::ServiceConfig;
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::get_bans);
+  cfg.service(handler::create_ban);
+  cfg.service(handler::update_ban);
+  cfg.service(handler::delete_ban);
+}
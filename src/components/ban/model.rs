@@ -0,0 +1,16 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct BanQuery {
+  pub lang: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BanBody {
+  // user, mail or nick
+  pub kind: String,
+  /// Id of the user, or the mail or nick
+  pub value: String,
+  #[serde(default)]
+  pub shadow: bool,
+}
@@ -0,0 +1,100 @@
+use helpers::time::utc_now;
+use sea_orm::{ActiveModelTrait, EntityTrait, QueryOrder, Set};
+use serde_json::{json, Value};
+
+use crate::{
+  app::AppState,
+  components::{
+    ban::model::*,
+    user::model::{get_user, verify_admin, UserQueryBy},
+  },
+  entities::{wl_ban, Id},
+  error::AppError,
+  helpers::ban::BAN_KINDS,
+  response::Code,
+};
+
+/// Check a ban and trim its value. Users are named by id, administrators cannot be banned
+async fn validate(state: &AppState, mut body: BanBody) -> Result<BanBody, Code> {
+  body.value = body.value.trim().to_string();
+  if body.value.is_empty() || !BAN_KINDS.contains(&body.kind.as_str()) {
+    return Err(Code::Error);
+  }
+  if body.kind == "user" {
+    let id = body.value.parse::<Id>().map_err(|_| Code::Error)?;
+    let user = get_user(UserQueryBy::Id(id), &state.repo).await?;
+    if user.user_type == "administrator" {
+      return Err(Code::Forbidden);
+    }
+  }
+  Ok(body)
+}
+
+pub async fn get_bans(state: &AppState, token: String) -> Result<Value, Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  let bans = wl_ban::Entity::find()
+    .order_by_asc(wl_ban::Column::Id)
+    .all(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  Ok(json!(bans))
+}
+
+pub async fn create_ban(state: &AppState, token: String, body: BanBody) -> Result<Value, Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  let body = validate(state, body).await?;
+  let ban = wl_ban::ActiveModel {
+    kind: Set(body.kind),
+    value: Set(body.value),
+    shadow: Set(body.shadow),
+    created_at: Set(Some(utc_now())),
+    updated_at: Set(Some(utc_now())),
+    ..Default::default()
+  }
+  .insert(&state.conn)
+  .await
+  .map_err(AppError::from)?;
+  state.reload_caches().await?;
+  Ok(json!(ban))
+}
+
+pub async fn update_ban(
+  state: &AppState,
+  token: String,
+  id: Id,
+  body: BanBody,
+) -> Result<Value, Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  let body = validate(state, body).await?;
+  if wl_ban::Entity::find_by_id(id)
+    .one(&state.conn)
+    .await
+    .map_err(AppError::from)?
+    .is_none()
+  {
+    return Err(Code::Error);
+  }
+  let ban = wl_ban::ActiveModel {
+    id: Set(id),
+    kind: Set(body.kind),
+    value: Set(body.value),
+    shadow: Set(body.shadow),
+    updated_at: Set(Some(utc_now())),
+    ..Default::default()
+  }
+  .update(&state.conn)
+  .await
+  .map_err(AppError::from)?;
+  state.reload_caches().await?;
+  Ok(json!(ban))
+}
+
+pub async fn delete_ban(state: &AppState, token: String, id: Id) -> Result<(), Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  wl_ban::Entity::delete_by_id(id)
+    .exec(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  state.reload_caches().await?;
+  Ok(())
+}
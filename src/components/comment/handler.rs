@@ -9,12 +9,21 @@ use crate::{
   app::AppState,
   components::{
     comment::{model::*, service},
-    user::model::is_admin_user,
+    user::model::{is_admin_user, verify_user},
   },
   config::EnvConfig,
+  entities::Id,
   error::AppError,
-  helpers::header::{extract_ip, extract_token},
+  helpers::{
+    ban::Ban,
+    header::{extract_ip, extract_token},
+    limiter::{Policy, RateLimit},
+    link::is_safe_link,
+    url,
+  },
+  repository::CommentFilter,
   response::{Code, Response},
+  traits::logging::LoggingResultErr,
 };
 
 #[get("/comment")]
@@ -33,6 +42,8 @@ async fn get_comment_info(
     owner,
     status,
     keyword,
+    url,
+    ..
   }) = query.clone();
   if let Some(path) = path {
     let fields = query.validate_by_path();
@@ -47,6 +58,7 @@ async fn get_comment_info(
       page_size.unwrap(),
       sort_by.unwrap(),
       token,
+      extract_ip(&req, &state.trusted_proxies),
     )
     .await
     {
@@ -59,12 +71,19 @@ async fn get_comment_info(
       tracing::error!("{:?}", fields.err().unwrap());
       return HttpResponse::Ok().json(Response::<()>::error(Code::Error, Some(&lang)));
     }
+    let (after, before) = match query.date_range() {
+      Ok(range) => range,
+      Err(field) => {
+        tracing::error!("Invalid {field} date");
+        return HttpResponse::Ok().json(Response::<()>::error(Code::Error, Some(&lang)));
+      }
+    };
     let token = extract_token(&req).unwrap();
     let email = match jwt::verify::<String>(&token, &state.jwt_token).map_err(AppError::from) {
       Ok(token_data) => token_data.claims.data,
       Err(err) => return HttpResponse::Ok().json(Response::<()>::error(err.into(), Some(&lang))),
     };
-    let is = match is_admin_user(&email, &state.conn).await {
+    let is = match is_admin_user(&email, &state.repo).await {
       Ok(value) => value,
       Err(err) => return HttpResponse::Ok().json(Response::<()>::error(err, Some(&lang))),
     };
@@ -76,7 +95,17 @@ async fn get_comment_info(
       owner.unwrap(),
       email,
       keyword.unwrap(),
-      status.unwrap(),
+      CommentFilter {
+        url: url.filter(|path| !path.trim().is_empty()).map(|path| {
+          state
+            .aliases
+            .resolve(url::normalize(&path, &state.page_query))
+        }),
+        status,
+        inserted_after: after,
+        inserted_before: before,
+        ..Default::default()
+      },
       page,
     )
     .await
@@ -105,23 +134,29 @@ async fn create_comment(
     pid,
     rid,
     at,
+    altcha,
   }) = body;
+  let url = state
+    .aliases
+    .resolve(url::normalize(&url, &state.page_query));
+  let setting = state.page_settings.get(&url);
   let mut user_type = UserType::Anonymous;
   let mut is_admin = false;
-  let client_ip = extract_ip(&req);
-  let pass = if let Ok(token) = extract_token(&req) {
+  let client_ip = extract_ip(&req, &state.trusted_proxies);
+  let limit = if let Ok(token) = extract_token(&req) {
     match jwt::verify::<String>(&token, &state.jwt_token) {
       Ok(verified_token) => {
-        if is_admin_user(&verified_token.claims.data, &state.conn)
-          .await
-          .unwrap()
-        {
+        let admin = match is_admin_user(&verified_token.claims.data, &state.repo).await {
+          Ok(admin) => admin,
+          Err(err) => return HttpResponse::Ok().json(Response::<()>::error(err, Some(&lang))),
+        };
+        if admin {
           is_admin = true;
           user_type = UserType::Administrator(verified_token.claims.data);
-          true
+          RateLimit::unlimited()
         } else {
           user_type = UserType::Guest(verified_token.claims.data);
-          state.rate_limiter.check_and_update(&client_ip, 1)
+          state.rate_limiter.check(Policy::Comment, &client_ip).await
         }
       }
       Err(err) => {
@@ -130,13 +165,26 @@ async fn create_comment(
       }
     }
   } else {
-    if &state.login == "force" {
+    if &state.login == "force" || setting.as_ref().is_some_and(|setting| setting.force_login) {
       return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, Some(&lang)));
     }
-    state.rate_limiter.check_and_update(&client_ip, 1)
+    let limit = state.rate_limiter.check(Policy::Comment, &client_ip).await;
+    // A solution is spent once verified, so only a request the limiter admits spends it
+    if limit.allowed && state.pow.enabled() {
+      let solved = match altcha {
+        Some(payload) => state.pow.verify(&payload).await,
+        None => false,
+      };
+      if !solved {
+        return HttpResponse::Ok().json(Response::<()>::error(Code::CaptchaFailed, Some(&lang)));
+      }
+    }
+    limit
   };
-  if !pass {
-    return HttpResponse::Ok().json(Response::<()>::error(Code::FrequencyLimited, Some(&lang)));
+  if !limit.allowed {
+    return limit
+      .response()
+      .json(Response::<()>::error(Code::FrequencyLimited, Some(&lang)));
   }
   if !is_admin {
     let EnvConfig {
@@ -146,13 +194,34 @@ async fn create_comment(
       tracing::info!("Comment IP {client_ip} is in disallowIPList");
       return HttpResponse::Ok().json(Response::<()>::error(Code::Forbidden, Some(&lang)));
     }
+    if let Err(err) = service::check_open(&state, &url, setting.as_ref()).await {
+      return HttpResponse::Ok().json(Response::<()>::error(err, Some(&lang)));
+    }
   }
-  if is_duplicate(&url, &mail, &nick, &link, &comment, &state.conn)
+  if !is_safe_link(&link) {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::InvalidLink, Some(&lang)));
+  }
+  // Remembered by `is_duplicate` and forgotten again when the comment is not saved, so
+  // that sending it again is no duplicate
+  let submission = (!is_admin).then(|| submission_key(&url, &mail, &nick, &link, &comment));
+  if !is_admin {
+    match is_duplicate(
+      &url,
+      &mail,
+      &nick,
+      &link,
+      &comment,
+      &state.store,
+      &state.repo,
+    )
     .await
-    .unwrap()
-    && !is_admin
-  {
-    return HttpResponse::Ok().json(Response::<()>::error(Code::DuplicateContent, Some(&lang)));
+    {
+      Ok(false) => {}
+      Ok(true) => {
+        return HttpResponse::Ok().json(Response::<()>::error(Code::DuplicateContent, Some(&lang)))
+      }
+      Err(err) => return HttpResponse::Ok().json(Response::<()>::error(err.into(), Some(&lang))),
+    }
   }
   match service::create_comment(
     &state,
@@ -165,22 +234,89 @@ async fn create_comment(
     pid,
     rid,
     at,
-    client_ip,
+    client_ip.clone(),
     user_type,
     lang.clone(),
   )
   .await
   {
-    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), Some(&lang))),
-    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, Some(&lang))),
+    Ok(data) => {
+      if data["status"] == "spam" {
+        state.rate_limiter.record_spam(&client_ip).await;
+      }
+      limit
+        .response()
+        .json(Response::success(Some(data), Some(&lang)))
+    }
+    Err(err) => {
+      if let Some(key) = submission {
+        state.store.remove(&key).await.log_err().ok();
+      }
+      limit
+        .response()
+        .json(Response::<()>::error(err, Some(&lang)))
+    }
+  }
+}
+
+#[post("/comment/preview")]
+async fn preview_comment(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<CreateCommentQuery>,
+  body: Json<PreviewCommentBody>,
+) -> HttpResponse {
+  let Query(CreateCommentQuery { lang }) = query;
+  let Json(PreviewCommentBody { comment }) = body;
+  let mut is_admin = false;
+  if let Ok(token) = extract_token(&req) {
+    match jwt::verify::<String>(&token, &state.jwt_token) {
+      Ok(verified_token) => {
+        is_admin = is_admin_user(&verified_token.claims.data, &state.repo)
+          .await
+          .unwrap_or(false);
+      }
+      Err(err) => {
+        tracing::error!("{}", err);
+        return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, Some(&lang)));
+      }
+    }
   }
+  let limit = if is_admin {
+    RateLimit::unlimited()
+  } else {
+    state
+      .rate_limiter
+      .check(Policy::Preview, &extract_ip(&req, &state.trusted_proxies))
+      .await
+  };
+  if !limit.allowed {
+    return limit
+      .response()
+      .json(Response::<()>::error(Code::FrequencyLimited, Some(&lang)));
+  }
+  match service::preview_comment(&state, comment, is_admin) {
+    Ok(data) => limit
+      .response()
+      .json(Response::success(Some(data), Some(&lang))),
+    Err(err) => limit
+      .response()
+      .json(Response::<()>::error(err, Some(&lang))),
+  }
+}
+
+/// The challenge is returned as-is so that the ALTCHA widget can consume it directly
+#[get("/comment/challenge")]
+async fn get_challenge(req: HttpRequest, state: Data<AppState>) -> HttpResponse {
+  let client_ip = extract_ip(&req, &state.trusted_proxies);
+  HttpResponse::Ok().json(service::get_challenge(&state, client_ip).await)
 }
 
 #[delete("/comment/{id}")]
 pub async fn delete_comment(
   req: HttpRequest,
   state: Data<AppState>,
-  path: Path<u32>,
+  path: Path<Id>,
 ) -> HttpResponse {
   let id = path.into_inner();
   if let Ok(token) = extract_token(&req) {
@@ -200,7 +336,7 @@ pub async fn delete_comment(
 async fn update_comment(
   req: HttpRequest,
   state: Data<AppState>,
-  path: Path<u32>,
+  path: Path<Id>,
   body: Json<UpdateCommentBody>,
 ) -> HttpResponse {
   let actix_web::web::Json(UpdateCommentBody {
@@ -214,27 +350,34 @@ async fn update_comment(
     url,
     sticky,
   }) = body;
-  let id: u32 = path.into_inner();
-  if like.is_some() {
-    match service::update_comment(
-      &state,
-      String::new(),
-      id,
-      status,
-      like,
-      comment,
-      link,
-      mail,
-      nick,
-      ua,
-      url,
-      sticky,
-    )
-    .await
-    {
-      Ok(data) => return HttpResponse::Ok().json(Response::success(Some(data), None)),
-      Err(err) => return HttpResponse::Ok().json(Response::<()>::error(err, None)),
+  let id: Id = path.into_inner();
+  if let Some(like) = like {
+    let limit = state
+      .rate_limiter
+      .check(Policy::Like, &extract_ip(&req, &state.trusted_proxies))
+      .await;
+    if !limit.allowed {
+      return limit
+        .response()
+        .json(Response::<()>::error(Code::FrequencyLimited, None));
     }
+    let ban = match extract_token(&req) {
+      Ok(token) => verify_user(&token, &state.jwt_token, &state.repo)
+        .await
+        .ok()
+        .and_then(|user| state.bans.check_user(&user)),
+      Err(_) => None,
+    };
+    if ban == Some(Ban::Outright) {
+      return limit
+        .response()
+        .json(Response::<()>::error(Code::Forbidden, None));
+    }
+    // A shadowbanned like looks counted but is not stored
+    return match service::like_comment(&state, id, like, ban != Some(Ban::Shadow)).await {
+      Ok(data) => limit.response().json(Response::success(Some(data), None)),
+      Err(err) => limit.response().json(Response::<()>::error(err, None)),
+    };
   }
   if let Ok(token) = extract_token(&req) {
     match jwt::verify::<String>(&token, &state.jwt_token) {
@@ -243,7 +386,6 @@ async fn update_comment(
         data.claims.data,
         id,
         status,
-        like,
         comment,
         link,
         mail,
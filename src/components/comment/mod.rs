@@ -1,12 +1,14 @@
 mod handler;
 pub mod model;
-mod service;
+pub mod service;
 
 use actix_web::web::ServiceConfig;
 
 pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::get_challenge);
   cfg.service(handler::get_comment_info);
   cfg.service(handler::create_comment);
+  cfg.service(handler::preview_comment);
   cfg.service(handler::delete_comment);
   cfg.service(handler::update_comment);
 }
@@ -1,76 +1,169 @@
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use std::time::Duration;
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use sea_orm::Set;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::{
-  entities::wl_comment,
+  entities::{wl_comment, Id, Sticky},
   error::AppError,
-  helpers::{avatar::get_avatar, markdown::render_md_to_html, ua},
+  helpers::{
+    avatar::AvatarProvider,
+    link::is_safe_link,
+    markdown::Renderer,
+    mention::{self, Mention},
+    region::ClientInfo,
+    store::Store,
+  },
+  repository::{CommentFilter, CommentOrder, RepositoryManager},
 };
 
+const DUPLICATE_TTL: Duration = Duration::from_secs(600);
+
 #[derive(Clone)]
 pub enum CommentQueryBy {
-  Id(u32),
-}
-
-pub async fn has_comment(
-  query_by: CommentQueryBy,
-  conn: &DatabaseConnection,
-) -> Result<bool, AppError> {
-  let mut query = wl_comment::Entity::find();
-  match query_by {
-    CommentQueryBy::Id(id) => query = query.filter(wl_comment::Column::Id.eq(id)),
-  }
-  let res = query.one(conn).await.map_err(AppError::from)?;
-  Ok(res.is_some())
+  Id(Id),
 }
 
 pub async fn get_comment(
   query_by: CommentQueryBy,
-  conn: &DatabaseConnection,
+  repo: &RepositoryManager,
 ) -> Result<wl_comment::Model, AppError> {
-  if !has_comment(query_by.to_owned(), conn).await? {
-    return Err(AppError::Error);
-  }
-  let mut query = wl_comment::Entity::find();
   match query_by {
-    CommentQueryBy::Id(id) => query = query.filter(wl_comment::Column::Id.eq(id)),
+    CommentQueryBy::Id(id) => repo.comment().get_comment(id).await,
   }
-  query
-    .one(conn)
-    .await
-    .map_err(AppError::from)?
-    .ok_or(AppError::Error)
+  .map_err(AppError::from)?
+  .ok_or(AppError::Error)
 }
 
-pub async fn is_anonymous(comment_id: u32, conn: &DatabaseConnection) -> Result<bool, AppError> {
-  let res = wl_comment::Entity::find_by_id(comment_id)
-    .filter(wl_comment::Column::UserId.is_not_null())
-    .filter(wl_comment::Column::UserId.ne(""))
-    .one(conn)
+pub async fn is_anonymous(comment_id: Id, repo: &RepositoryManager) -> Result<bool, AppError> {
+  let res = repo
+    .comment()
+    .get_comment(comment_id)
     .await
-    .map_err(AppError::from)?;
+    .map_err(AppError::from)?
+    .and_then(|comment| comment.user_id)
+    .filter(|user_id| *user_id != 0);
   Ok(res.is_none())
 }
 
+/// Key under which the shared store remembers a recent submission
+pub fn submission_key(url: &str, mail: &str, nick: &str, link: &str, comment: &str) -> String {
+  let digest =
+    helpers::hash::md5(format!("{url:?}:{mail:?}:{nick:?}:{link:?}:{comment:?}").as_bytes());
+  format!("duplicate:{digest}")
+}
+
+/// A comment is a duplicate when the same content was submitted within the last minutes,
+/// which the shared store catches across replicas, or is already saved on the page. The
+/// submission is remembered under [`submission_key`] and has to be removed again when the
+/// comment is not saved
 pub async fn is_duplicate(
-  url: &String,
-  mail: &String,
-  nick: &String,
-  link: &String,
-  comment: &String,
-  conn: &DatabaseConnection,
+  url: &str,
+  mail: &str,
+  nick: &str,
+  link: &str,
+  comment: &str,
+  store: &Store,
+  repo: &RepositoryManager,
 ) -> Result<bool, AppError> {
-  let res = wl_comment::Entity::find()
-    .filter(wl_comment::Column::Url.eq(url))
-    .filter(wl_comment::Column::Mail.eq(mail))
-    .filter(wl_comment::Column::Nick.eq(nick))
-    .filter(wl_comment::Column::Link.eq(link))
-    .filter(wl_comment::Column::Comment.eq(comment))
-    .all(conn)
+  let key = submission_key(url, mail, nick, link, comment);
+  if !store.set_once(&key, DUPLICATE_TTL).await? {
+    return Ok(true);
+  }
+  let saved = repo
+    .comment()
+    .count_comments(CommentFilter {
+      url: Some(url.to_string()),
+      mail: Some(mail.to_string()),
+      nick: Some(nick.to_string()),
+      link: Some(link.to_string()),
+      comment: Some(comment.to_string()),
+      ..Default::default()
+    })
+    .await;
+  match saved {
+    Ok(count) => Ok(count > 0),
+    Err(err) => {
+      store.remove(&key).await?;
+      Err(AppError::from(err))
+    }
+  }
+}
+
+/// Resolve `@token` to a registered user, by email or display name, or else to the
+/// latest approved commenter on the page. Mentions by nick link to that person's latest
+/// comment on the page, or to their homepage, mentions by email are only notified. With
+/// `before` only comments inserted before it count
+pub async fn resolve_mention(
+  token: &str,
+  url: &str,
+  before: Option<DateTime<Utc>>,
+  repo: &RepositoryManager,
+) -> Result<Option<Mention>, AppError> {
+  let is_email = token.contains('@');
+  let user = if is_email {
+    repo.user().get_user_by_email(token).await
+  } else {
+    repo.user().get_user_by_display_name(token).await
+  }
+  .map_err(AppError::from)?;
+  let mut filter = CommentFilter {
+    url: Some(url.to_string()),
+    status: Some("approved".to_string()),
+    inserted_before: before,
+    ..Default::default()
+  };
+  match &user {
+    Some(user) => filter.user_id = Some(user.id as i32),
+    None if is_email => filter.mail = Some(token.to_string()),
+    None => filter.nick = Some(token.to_string()),
+  };
+  let comment = repo
+    .comment()
+    .first_comment(filter, CommentOrder::IdDesc)
     .await
     .map_err(AppError::from)?;
-  Ok(!res.is_empty())
+  let mention = match (user, comment) {
+    (Some(user), comment) => {
+      let homepage = user.url.filter(|url| !url.is_empty() && is_safe_link(url));
+      Mention {
+        token: token.to_string(),
+        nick: user.display_name,
+        email: user.email,
+        href: comment
+          .map(|comment| format!("#{}", comment.id))
+          .or(homepage),
+      }
+    }
+    (None, Some(comment)) => Mention {
+      token: token.to_string(),
+      nick: comment.nick.unwrap_or_default(),
+      email: comment.mail.unwrap_or_default(),
+      href: Some(format!("#{}", comment.id)),
+    },
+    (None, None) => return Ok(None),
+  };
+  Ok(Some(mention))
+}
+
+/// The first `limit` mentions written in a stored comment, resolved as they were when it
+/// was posted, against the comments before it
+pub async fn comment_mentions(
+  comment: &wl_comment::Model,
+  limit: usize,
+  repo: &RepositoryManager,
+) -> Result<Vec<Mention>, AppError> {
+  let url = comment.url.as_deref().unwrap_or_default();
+  let mut mentions = vec![];
+  for token in mention::parse(comment.comment.as_deref().unwrap_or_default())
+    .iter()
+    .take(limit)
+  {
+    mentions.extend(resolve_mention(token, url, comment.inserted_at, repo).await?);
+  }
+  Ok(mentions)
 }
 
 #[derive(Serialize, Debug)]
@@ -81,12 +174,14 @@ pub struct DataEntry {
   pub mail: Option<String>,
   pub nick: Option<String>,
   pub user_id: Option<i32>,
-  pub browser: String,
-  pub os: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub browser: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub os: Option<String>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub r#type: Option<String>,
   #[serde(rename = "objectId")]
-  pub object_id: u32,
+  pub object_id: Id,
   pub ip: Option<String>,
   pub orig: Option<String>,
   pub url: Option<String>,
@@ -97,11 +192,17 @@ pub struct DataEntry {
   pub avatar: String,
   pub level: Option<usize>,
   pub label: Option<String>,
-  pub sticky: Option<i8>,
+  pub sticky: Option<Sticky>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub addr: Option<String>,
   pub children: Vec<DataEntry>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub reply_user: Option<Value>,
+  /// Highlighted excerpt when the comment was found by a search
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub snippet: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub score: Option<f64>,
 }
 
 fn is_strictly_increasing(nums: &[usize]) -> bool {
@@ -133,17 +234,26 @@ pub fn get_level(count: usize, levels: &str) -> usize {
   0
 }
 
-pub fn build_data_entry(comment: wl_comment::Model, level: Option<usize>) -> DataEntry {
-  let (browser, os) = ua::parse(comment.ua.unwrap_or("".to_owned()));
+/// The comment is stored as written, its mentions are linked for rendering
+pub fn build_data_entry(
+  comment: wl_comment::Model,
+  level: Option<usize>,
+  mentions: &[Mention],
+  renderer: &Renderer,
+  client_info: &ClientInfo,
+  avatar: &AvatarProvider,
+) -> DataEntry {
+  let (browser, os) = client_info.user_agent(comment.ua.as_deref()).unzip();
+  let addr = client_info.region(comment.ip.as_deref());
   let safe_html = if let Some(ref comment_text) = comment.comment {
-    Some(ammonia::clean(&render_md_to_html(comment_text)))
+    Some(renderer.render(&mention::link(comment_text, mentions)))
   } else {
     Some("".to_string())
   };
   DataEntry {
     status: comment.status,
     like: comment.like,
-    link: comment.link,
+    link: comment.link.filter(|link| is_safe_link(link)),
     mail: None,
     nick: comment.nick,
     user_id: comment.user_id,
@@ -158,13 +268,15 @@ pub fn build_data_entry(comment: wl_comment::Model, level: Option<usize>) -> Dat
     pid: comment.pid,
     rid: comment.rid,
     comment: safe_html,
-    avatar: get_avatar(&comment.mail.unwrap_or("default".to_owned())),
+    avatar: avatar.get_avatar(&comment.mail.unwrap_or_default()),
     level,
     label: None,
     sticky: comment.sticky,
-    addr: None,
+    addr,
     children: vec![],
     reply_user: None,
+    snippet: None,
+    score: None,
   }
 }
 
@@ -180,6 +292,29 @@ pub struct GetCommentQuery {
   pub owner: Option<String>,
   pub status: Option<String>,
   pub keyword: Option<String>,
+  /// Page the comments were left on
+  pub url: Option<String>,
+  /// Inserted on or after this day or time
+  pub after: Option<String>,
+  /// Inserted on or before this day, or before this time
+  pub before: Option<String>,
+}
+
+/// Inclusive start and exclusive end of a time span, either may be open
+pub type DateRange = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// `YYYY-MM-DD` or RFC 3339, a day ends at the following midnight when `end` is set
+fn parse_date(value: &str, end: bool) -> Option<DateTime<Utc>> {
+  if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+    return Some(time.to_utc());
+  }
+  let day = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+  let day = if end {
+    day.checked_add_days(Days::new(1))?
+  } else {
+    day
+  };
+  Some(day.and_hms_opt(0, 0, 0)?.and_utc())
 }
 
 impl GetCommentQuery {
@@ -221,6 +356,17 @@ impl GetCommentQuery {
       Err(missing_fields)
     }
   }
+  /// Bounds of `after` and `before`, empty values are unset
+  pub fn date_range(&self) -> Result<DateRange, &'static str> {
+    let parse = |value: &Option<String>, end, field| match value.as_deref() {
+      None | Some("") => Ok(None),
+      Some(value) => parse_date(value, end).map(Some).ok_or(field),
+    };
+    Ok((
+      parse(&self.after, false, "after")?,
+      parse(&self.before, true, "before")?,
+    ))
+  }
 }
 
 pub fn create_comment_model(
@@ -255,15 +401,6 @@ pub fn create_comment_model(
   }
 }
 
-pub fn has_forbidden_word(comment: &str, forbidden_words: &Vec<String>) -> bool {
-  for word in forbidden_words {
-    if comment.contains(word) {
-      return true;
-    }
-  }
-  false
-}
-
 pub enum UserType {
   Anonymous,
   Guest(String),
@@ -294,6 +431,13 @@ pub struct CreateCommentBody {
   pub rid: Option<i32>,
   // at
   pub at: Option<String>,
+  // base64 encoded proof-of-work solution
+  pub altcha: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PreviewCommentBody {
+  pub comment: String,
 }
 
 #[derive(Deserialize)]
@@ -306,5 +450,5 @@ pub struct UpdateCommentBody {
   pub nick: Option<String>,
   pub ua: Option<String>,
   pub url: Option<String>,
-  pub sticky: Option<i8>,
+  pub sticky: Option<Sticky>,
 }
@@ -1,32 +1,68 @@
+use std::time::Duration;
+
 use actix_web::rt::spawn;
-use helpers::{
-  jwt,
-  time::{self, utc_now},
-};
+use helpers::time::{self, utc_now};
 use instant_akismet::CheckResult;
-use sea_orm::{
-  ActiveModelTrait, ColumnTrait, EntityTrait, Order, PaginatorTrait, QueryFilter, QueryOrder, Set,
-};
+use sea_orm::{ActiveValue::Unchanged, ColumnTrait, EntityTrait, QueryFilter, Set};
 use serde_json::{json, Value};
 
 use crate::{
   app::AppState,
   components::{
     comment::model::*,
-    user::model::{get_user, is_admin_user, UserQueryBy},
+    user::model::{get_trust, get_user, verify_user, UserQueryBy},
   },
-  entities::wl_comment,
+  entities::{wl_comment, wl_page_setting, wl_report, wl_users, Id, Sticky},
   error::AppError,
   helpers::{
-    avatar::get_avatar,
+    ban::Ban,
+    bayes::{tokenize, Class},
     email::{send_email_notification, CommentNotification, NotifyType},
-    markdown::render_md_to_html,
-    spam::check_comment,
-    ua,
+    forbidden::Action,
+    link::is_safe_link,
+    mention,
+    pow::Challenge,
+    snippet,
+    spam::{check_comment, submit_comment},
+    url,
   },
+  repository::{CommentFilter, CommentOrder},
   response::Code,
 };
 
+/// Comment count of a commenter, cached for a minute so that levels stay cheap to compute
+async fn get_comment_count(
+  state: &AppState,
+  nick: &Option<String>,
+  mail: &Option<String>,
+) -> Result<u64, AppError> {
+  let key = format!(
+    "comment_count:{}",
+    helpers::hash::md5(format!("{nick:?}:{mail:?}").as_bytes())
+  );
+  if let Ok(Some(count)) = state.store.get(&key).await {
+    if let Ok(count) = count.parse() {
+      return Ok(count);
+    }
+  }
+  let count = state
+    .repo
+    .comment()
+    .count_comments(CommentFilter {
+      nick: nick.clone(),
+      mail: mail.clone(),
+      ..Default::default()
+    })
+    .await
+    .map_err(AppError::from)?;
+  state
+    .store
+    .set(&key, &count.to_string(), Duration::from_secs(60))
+    .await
+    .ok();
+  Ok(count)
+}
+
 pub async fn get_comment_info(
   state: &AppState,
   path: String,
@@ -34,45 +70,65 @@ pub async fn get_comment_info(
   page_size: i32,
   sort_by: String,
   token: Result<String, AppError>,
+  ip: String,
 ) -> Result<Value, Code> {
-  let (sort_col, sort_ord) = match sort_by.as_str() {
-    "insertedAt_asc" => (wl_comment::Column::InsertedAt, Order::Asc),
-    "like_desc" => (wl_comment::Column::Like, Order::Desc),
-    _ => (wl_comment::Column::InsertedAt, Order::Desc),
+  let path = state
+    .aliases
+    .resolve(url::normalize(&path, &state.page_query));
+  let order = match sort_by.as_str() {
+    "insertedAt_asc" => CommentOrder::InsertedAtAsc,
+    "like_desc" => CommentOrder::LikeDesc,
+    _ => CommentOrder::InsertedAtDesc,
   };
-  let mut select = wl_comment::Entity::find()
-    .filter(wl_comment::Column::Url.contains(&path))
-    .filter(wl_comment::Column::Pid.is_null())
-    .filter(wl_comment::Column::Status.is_not_in(["waiting", "spam"]));
-  let mut is_admin = false;
-  if let Ok(token) = token {
-    if let Ok(email) = jwt::verify::<String>(&token, &state.jwt_token).map(|t| t.claims.data) {
-      if is_admin_user(&email, &state.conn).await.unwrap_or(false) {
-        is_admin = true;
-        select = wl_comment::Entity::find()
-          .filter(wl_comment::Column::Url.contains(&path))
-          .filter(wl_comment::Column::Pid.is_null());
-      }
-    }
-  }
-  let paginator = select
-    .order_by(sort_col, sort_ord)
-    .paginate(&state.conn, page_size as u64);
-  let total_pages = paginator.num_pages().await.map_err(AppError::from)?;
-  let parrent_comments = paginator
-    .fetch_page((page - 1) as u64)
+  let viewer = match token {
+    Ok(token) => verify_user(&token, &state.jwt_token, &state.repo)
+      .await
+      .ok(),
+    Err(_) => None,
+  };
+  let is_admin = viewer
+    .as_ref()
+    .is_some_and(|viewer| viewer.user_type == "administrator");
+  let shadowbanned = if is_admin {
+    vec![]
+  } else {
+    shadowbanned_comments(state, &path, viewer.as_ref(), &ip).await?
+  };
+  // Administrators also see comments waiting for review and spam
+  let exclude_status = if is_admin {
+    vec![]
+  } else {
+    vec!["waiting".to_string(), "spam".to_string()]
+  };
+  let page_data = state
+    .repo
+    .comment()
+    .paginate_comments(
+      CommentFilter {
+        url: Some(path.clone()),
+        pid: Some(None),
+        exclude_status: exclude_status.clone(),
+        ..Default::default()
+      },
+      order,
+      page_size as u64,
+      (page - 1) as u64,
+    )
     .await
     .map_err(AppError::from)?;
+  let total_pages = page_data.total_pages;
+  let mut parrent_comments = page_data.items;
   // Get comment count for articles
-  let mut count = paginator.num_items().await.map_err(AppError::from)?;
+  let mut count = page_data.total_items;
+  let shadowbanned_parents = shadowbanned.iter().filter(|comment| comment.pid.is_none());
+  count += shadowbanned_parents.clone().count() as u64;
+  if page == 1 {
+    parrent_comments.extend(shadowbanned_parents.cloned());
+    parrent_comments.sort_by(|a, b| order.compare(a, b));
+  }
   let mut data = vec![];
   for parrent_comment in parrent_comments {
-    let c = wl_comment::Entity::find()
-      .filter(wl_comment::Column::Nick.eq(parrent_comment.clone().nick))
-      .filter(wl_comment::Column::Mail.eq(parrent_comment.clone().mail))
-      .count(&state.conn)
-      .await
-      .map_err(AppError::from)?;
+    let c = get_comment_count(state, &parrent_comment.nick, &parrent_comment.mail).await?;
     // let level;
     // if let Some(levels) = &state.levels {
     //   level = Some(get_level(c as usize, levels));
@@ -85,10 +141,17 @@ pub async fn get_comment_info(
       .as_ref()
       .map(|levels| get_level(c as usize, levels));
 
-    let mut parrent_data = build_data_entry(parrent_comment.clone(), level);
+    let mut parrent_data = build_data_entry(
+      parrent_comment.clone(),
+      level,
+      &comment_mentions(&parrent_comment, state.mention_limit, &state.repo).await?,
+      &state.renderer,
+      &state.client_info,
+      &state.avatar,
+    );
 
     if let Some(user_id) = parrent_data.user_id {
-      if let Ok(user) = get_user(UserQueryBy::Id(user_id as u32), &state.conn).await {
+      if let Ok(user) = get_user(UserQueryBy::Id(user_id as Id), &state.repo).await {
         parrent_data.label = user.label;
         parrent_data.r#type = Some(user.user_type);
       }
@@ -98,30 +161,38 @@ pub async fn get_comment_info(
       parrent_data.mail = parrent_comment.mail.clone();
       parrent_data.ip = parrent_comment.ip.clone();
     }
-    let mut subcomments = wl_comment::Entity::find()
-      .filter(wl_comment::Column::Url.contains(path.clone()))
-      .filter(wl_comment::Column::Pid.eq(parrent_comment.id))
-      .filter(wl_comment::Column::Status.is_not_in(["waiting", "spam"]))
-      .order_by(wl_comment::Column::InsertedAt, Order::Asc)
-      .all(&state.conn)
+    let mut subcomments = state
+      .repo
+      .comment()
+      .get_comments(
+        CommentFilter {
+          url: Some(path.clone()),
+          pid: Some(Some(parrent_comment.id as i32)),
+          exclude_status: exclude_status.clone(),
+          ..Default::default()
+        },
+        CommentOrder::InsertedAtAsc,
+      )
       .await
       .map_err(AppError::from)?;
-    if is_admin {
-      subcomments = wl_comment::Entity::find()
-        .filter(wl_comment::Column::Url.contains(path.clone()))
-        .filter(wl_comment::Column::Pid.eq(parrent_comment.id))
-        .order_by(wl_comment::Column::InsertedAt, Order::Asc)
-        .all(&state.conn)
-        .await
-        .map_err(AppError::from)?;
-    }
+    subcomments.extend(
+      shadowbanned
+        .iter()
+        .filter(|comment| comment.pid == Some(parrent_comment.id as i32))
+        .cloned(),
+    );
+    subcomments.sort_by(|a, b| CommentOrder::InsertedAtAsc.compare(a, b));
     count += subcomments.len() as u64;
     for subcomment in subcomments {
-      let c = wl_comment::Entity::find()
-        .filter(wl_comment::Column::Url.eq(parrent_comment.clone().url))
-        .filter(wl_comment::Column::Nick.eq(parrent_comment.clone().nick))
-        .filter(wl_comment::Column::Mail.eq(parrent_comment.clone().mail))
-        .count(&state.conn)
+      let c = state
+        .repo
+        .comment()
+        .count_comments(CommentFilter {
+          url: parrent_comment.url.clone(),
+          nick: parrent_comment.nick.clone(),
+          mail: parrent_comment.mail.clone(),
+          ..Default::default()
+        })
         .await
         .map_err(AppError::from)?;
       // let level;
@@ -135,9 +206,16 @@ pub async fn get_comment_info(
         .as_ref()
         .map(|levels| get_level(c as usize, levels));
 
-      let mut subcomment_data = build_data_entry(subcomment.clone(), level);
+      let mut subcomment_data = build_data_entry(
+        subcomment.clone(),
+        level,
+        &comment_mentions(&subcomment, state.mention_limit, &state.repo).await?,
+        &state.renderer,
+        &state.client_info,
+        &state.avatar,
+      );
       if let Some(user_id) = subcomment_data.user_id {
-        let user = get_user(UserQueryBy::Id(user_id as u32), &state.conn).await?;
+        let user = get_user(UserQueryBy::Id(user_id as Id), &state.repo).await?;
         subcomment_data.label = user.label;
         subcomment_data.r#type = Some(user.user_type);
       }
@@ -146,8 +224,8 @@ pub async fn get_comment_info(
         subcomment_data.ip = subcomment_data.ip.clone();
       }
       subcomment_data.reply_user = Some(json!({
-        "avatar": get_avatar(&parrent_comment.mail.clone().unwrap_or("default".to_owned())),
-        "link": parrent_comment.link,
+        "avatar": state.avatar.get_avatar(&parrent_comment.mail.clone().unwrap_or("default".to_owned())),
+        "link": parrent_comment.link.clone().filter(|link| is_safe_link(link)),
         "nick": parrent_comment.nick,
       }));
       parrent_data.children.push(subcomment_data)
@@ -163,43 +241,104 @@ pub async fn get_comment_info(
   }))
 }
 
+/// Spam on a page that a shadowbanned viewer posted themselves, so that they still see it
+/// published. Viewers are recognized by their account or by the IP they commented from
+async fn shadowbanned_comments(
+  state: &AppState,
+  url: &str,
+  viewer: Option<&wl_users::Model>,
+  ip: &str,
+) -> Result<Vec<wl_comment::Model>, Code> {
+  if !state.bans.has_shadowbans() {
+    return Ok(vec![]);
+  }
+  let spam = state
+    .repo
+    .comment()
+    .get_comments(
+      CommentFilter {
+        url: Some(url.to_string()),
+        status: Some("spam".to_string()),
+        ..Default::default()
+      },
+      CommentOrder::InsertedAtAsc,
+    )
+    .await
+    .map_err(AppError::from)?;
+  let viewer_id = viewer.map(|viewer| viewer.id as i32);
+  Ok(
+    spam
+      .into_iter()
+      .filter(|comment| {
+        (viewer_id.is_some() && comment.user_id == viewer_id)
+          || (!ip.is_empty() && comment.ip.as_deref() == Some(ip))
+      })
+      .filter(|comment| {
+        state.bans.check(
+          comment.user_id,
+          comment.mail.as_deref(),
+          comment.nick.as_deref(),
+        ) == Some(Ban::Shadow)
+      })
+      .collect(),
+  )
+}
+
+/// Comments for the admin console matching `filter`, only the admin's own with the `mine`
+/// owner. A keyword runs a full-text search ranked by relevance, otherwise comments are
+/// listed in the order they were created
 pub async fn get_comment_info_by_admin(
   state: &AppState,
   owner: String,
   email: String,
   keyword: String,
-  status: String,
+  filter: CommentFilter,
   page: i32,
 ) -> Result<Value, Code> {
-  let mut comments = vec![];
+  let mut hits = vec![];
   let mut total_pages = 0;
-  if owner.clone() == "mine" {
-    let paginator = wl_comment::Entity::find()
-      .filter(wl_comment::Column::Mail.eq(email))
-      .filter(wl_comment::Column::Status.eq(status))
-      .filter(wl_comment::Column::Comment.contains(keyword))
-      .paginate(&state.conn, 10);
-    total_pages = paginator.num_pages().await.map_err(AppError::from)?;
-    comments = paginator
-      .fetch_page((page - 1) as u64)
-      .await
-      .map_err(AppError::from)?;
-  } else if owner == "all" {
-    let paginator = wl_comment::Entity::find()
-      .filter(wl_comment::Column::Status.eq(status))
-      .filter(wl_comment::Column::Comment.contains(keyword))
-      .paginate(&state.conn, 10);
-    total_pages = paginator.num_pages().await.map_err(AppError::from)?;
-    comments = paginator
-      .fetch_page((page - 1) as u64)
-      .await
-      .map_err(AppError::from)?;
+  if owner == "mine" || owner == "all" {
+    let filter = CommentFilter {
+      mail: (owner == "mine").then_some(email),
+      ..filter
+    };
+    let page_data = if keyword.trim().is_empty() {
+      state
+        .repo
+        .comment()
+        .paginate_comments(filter, CommentOrder::IdAsc, 10, (page - 1) as u64)
+        .await
+        .map_err(AppError::from)?
+        .map(|comment| (comment, None))
+    } else {
+      state
+        .repo
+        .comment()
+        .search_comments(&keyword, filter, 10, (page - 1) as u64)
+        .await
+        .map_err(AppError::from)?
+        .map(|hit| (hit.comment, Some(hit.score)))
+    };
+    total_pages = page_data.total_pages;
+    hits = page_data.items;
   }
   let mut data = vec![];
-  for comment in comments.iter() {
-    let mut data_entry = build_data_entry(comment.clone(), None);
+  for (comment, score) in hits {
+    let snippet =
+      score.map(|_| snippet::highlight(comment.comment.as_deref().unwrap_or_default(), &keyword));
+    let mentions = comment_mentions(&comment, state.mention_limit, &state.repo).await?;
+    let mut data_entry = build_data_entry(
+      comment,
+      None,
+      &mentions,
+      &state.renderer,
+      &state.client_info,
+      &state.avatar,
+    );
+    data_entry.snippet = snippet;
+    data_entry.score = score;
     if let Some(user_id) = data_entry.user_id {
-      if let Ok(user) = get_user(UserQueryBy::Id(user_id as u32), &state.conn).await {
+      if let Ok(user) = get_user(UserQueryBy::Id(user_id as Id), &state.repo).await {
         data_entry.label = user.label;
         data_entry.r#type = Some(user.user_type);
       }
@@ -216,27 +355,232 @@ pub async fn get_comment_info_by_admin(
   }))
 }
 
-pub async fn create_comment<'a>(
+/// Combine the local classifier and Akismet according to `SPAM_CLASSIFIER`
+async fn is_spam(
   state: &AppState,
-  comment: String,
-  link: String,
+  nick: String,
   mail: String,
+  link: String,
+  ip: String,
+  comment: String,
+) -> Result<bool, AppError> {
+  let use_bayes = state.spam_classifier != "akismet";
+  let use_akismet = state.spam_classifier != "bayes";
+  if use_bayes {
+    let tokens = tokenize(&nick, &mail, &link, &comment);
+    if let Some(score) = state.bayes.score(&tokens) {
+      tracing::info!("Comment spam score is {score:.3}");
+      if score >= state.bayes_threshold {
+        return Ok(true);
+      }
+    }
+  }
+  if let Some(akismet) = state.akismet.as_ref().filter(|_| use_akismet) {
+    return Ok(!matches!(
+      check_comment(akismet, nick, mail, ip, comment).await?,
+      CheckResult::Ham
+    ));
+  }
+  Ok(false)
+}
+
+/// Whether a new comment waits for review. The trust level an administrator set on the
+/// user comes first, then a logged-in commenter with `TRUST_THRESHOLD` approved comments
+/// and no spam skips the audit. Anonymous commenters never do, anyone can post with their
+/// mail and nick
+async fn needs_review(
+  state: &AppState,
+  audit: bool,
+  user_type: &UserType,
+  user: Option<&wl_users::Model>,
+) -> Result<bool, Code> {
+  if let UserType::Administrator(_) = user_type {
+    return Ok(false);
+  }
+  if let Some(user) = user {
+    match get_trust(&state.conn, user.id).await?.as_deref() {
+      Some("trusted") => return Ok(false),
+      Some("moderated") => return Ok(true),
+      _ => {}
+    }
+  }
+  if !audit || state.trust_threshold == 0 {
+    return Ok(audit);
+  }
+  let Some(user) = user else {
+    return Ok(true);
+  };
+  let mut filter = CommentFilter {
+    user_id: Some(user.id as i32),
+    ..Default::default()
+  };
+  let comments = state.repo.comment();
+  filter.status = Some("spam".to_string());
+  if comments
+    .count_comments(filter.clone())
+    .await
+    .map_err(AppError::from)?
+    > 0
+  {
+    return Ok(true);
+  }
+  filter.status = Some("approved".to_string());
+  let approved = comments
+    .count_comments(filter)
+    .await
+    .map_err(AppError::from)?;
+  Ok(approved < state.trust_threshold)
+}
+
+/// Status of a new comment that is not posted by an administrator
+async fn moderate(
+  state: &AppState,
+  verdict: Option<Action>,
   nick: String,
+  mail: String,
+  link: String,
+  ip: String,
+  comment: String,
+) -> Result<String, Code> {
+  let status = if verdict == Some(Action::Hold) {
+    "waiting"
+  } else if verdict == Some(Action::Spam)
+    || state.link_policy.is_spam(&comment, &link)
+    || is_spam(state, nick, mail, link, ip, comment).await?
+  {
+    "spam"
+  } else {
+    "approved"
+  };
+  Ok(status.to_string())
+}
+
+/// Render a comment the way it would be stored, with forbidden words masked
+pub fn preview_comment(
+  state: &AppState,
+  mut comment: String,
+  is_admin: bool,
+) -> Result<Value, Code> {
+  if !is_admin && state.word_filter.apply(&mut [&mut comment]) == Some(Action::Reject) {
+    return Err(Code::ForbiddenWord);
+  }
+  Ok(json!({
+    "comment": state.renderer.render(&comment),
+  }))
+}
+
+/// Refuse comments on a page that was locked, or whose first comment is older than the
+/// days the page stays open for
+pub async fn check_open(
+  state: &AppState,
+  url: &str,
+  setting: Option<&wl_page_setting::Model>,
+) -> Result<(), Code> {
+  let Some(setting) = setting else {
+    return Ok(());
+  };
+  if setting.locked {
+    return Err(Code::CommentClosed);
+  }
+  if let Some(days) = setting.close_after {
+    let first = state
+      .repo
+      .comment()
+      .first_comment(
+        CommentFilter {
+          url: Some(url.to_string()),
+          exclude_status: vec!["spam".to_string()],
+          ..Default::default()
+        },
+        CommentOrder::InsertedAtAsc,
+      )
+      .await
+      .map_err(AppError::from)?;
+    let opened = first.and_then(|comment| comment.inserted_at);
+    if opened.is_some_and(|opened| utc_now() - opened >= chrono::Duration::days(days.into())) {
+      return Err(Code::CommentClosed);
+    }
+  }
+  Ok(())
+}
+
+pub async fn create_comment<'a>(
+  state: &AppState,
+  mut comment: String,
+  mut link: String,
+  mut mail: String,
+  mut nick: String,
   ua: String,
   url: String,
   pid: Option<i32>,
   rid: Option<i32>,
-  _at: Option<String>,
+  at: Option<String>,
   ip: String,
   user_type: UserType,
   lang: String,
 ) -> Result<Value, Code> {
-  let html_output = render_md_to_html(&comment);
-  let mut avatar = get_avatar("anonymous");
+  let user = match &user_type {
+    UserType::Anonymous => None,
+    UserType::Guest(email) | UserType::Administrator(email) => {
+      get_user(UserQueryBy::Email(email.clone()), &state.repo)
+        .await
+        .ok()
+    }
+  };
+  let ban = match &user {
+    Some(user) => state.bans.check_user(user),
+    None => state.bans.check(None, Some(&mail), Some(&nick)),
+  };
+  if ban == Some(Ban::Outright) {
+    return Err(Code::Forbidden);
+  }
+  let verdict = match user_type {
+    UserType::Administrator(_) => None,
+    _ => state
+      .word_filter
+      .apply(&mut [&mut nick, &mut mail, &mut link, &mut comment]),
+  };
+  if verdict == Some(Action::Reject) {
+    return Err(Code::ForbiddenWord);
+  }
+  // A comment under review is held the way a forbidden word set to hold does, a more
+  // severe forbidden word still wins
+  let audit = state
+    .page_settings
+    .get(&url)
+    .and_then(|setting| setting.audit)
+    .unwrap_or(state.comment_audit);
+  let verdict = if needs_review(state, audit, &user_type, user.as_ref()).await? {
+    verdict.max(Some(Action::Hold))
+  } else {
+    verdict
+  };
+  // A shadowbanned commenter gets no review, their comments go straight to spam
+  let verdict = if ban == Some(Ban::Shadow) {
+    Some(Action::Spam)
+  } else {
+    verdict
+  };
+  // `at` is the nick being replied to, it may already be linked in the comment
+  let mut tokens = mention::parse(&comment);
+  if let Some(at) = at.filter(|at| !at.is_empty()) {
+    if !tokens.iter().any(|token| token.eq_ignore_ascii_case(&at)) {
+      tokens.insert(0, at);
+    }
+  }
+  let mut mentions = Vec::new();
+  for token in tokens.iter().take(state.mention_limit) {
+    if let Some(mention) = resolve_mention(token, &url, None, &state.repo).await? {
+      mentions.push(mention);
+    }
+  }
+  // The comment is stored as written, mentions are linked whenever it is rendered
+  let html_output = state.renderer.render(&mention::link(&comment, &mentions));
+  let mut avatar = state.avatar.get_avatar(&mail);
   let mut new_comment = create_comment_model(
     None,
     comment.clone(),
-    link,
+    link.clone(),
     mail.clone(),
     nick.clone(),
     ua.clone(),
@@ -245,70 +589,26 @@ pub async fn create_comment<'a>(
     pid,
     rid,
   );
-  let (browser, os) = ua::parse(ua);
   let mut data = json!({
-    "addr":"",
-    "browser": browser,
-    "os": os,
     "comment": html_output,
   });
-  match user_type {
-    UserType::Anonymous => {
-      new_comment.status = Set(if state.comment_audit {
-        "waiting".to_string()
-      } else if has_forbidden_word(&comment, &state.forbidden_words) {
-        "spam".to_string()
-      } else {
-        if matches!(
-          check_comment(nick, mail, ip, comment).await?,
-          CheckResult::Ham
-        ) {
-          "approved".to_string()
-        } else {
-          "spam".to_string()
-        }
-      });
-    }
-    UserType::Guest(email) => {
-      let user = get_user(UserQueryBy::Email(email), &state.conn).await;
-      if let Ok(user) = user {
-        new_comment.user_id = Set(Some(user.id as i32));
-        new_comment.status = Set(if state.comment_audit {
-          "waiting".to_string()
-        } else if has_forbidden_word(&comment, &state.forbidden_words) {
-          "spam".to_string()
-        } else {
-          if matches!(
-            check_comment(nick, mail, ip, comment).await?,
-            CheckResult::Ham
-          ) {
-            "approved".to_string()
-          } else {
-            "spam".to_string()
-          }
-        });
-        data["label"] = json!(user.label);
-        data["mail"] = json!(user.email);
-        data["type"] = json!(user.user_type);
-        data["user_id"] = json!(user.id);
-        avatar = get_avatar(&user.email);
-      }
-    }
-    UserType::Administrator(email) => {
-      let user = get_user(UserQueryBy::Email(email), &state.conn).await;
-      if let Ok(user) = user {
-        new_comment.user_id = Set(Some(user.id as i32));
-        new_comment.status = Set("approved".to_string());
-        data["label"] = json!(user.label);
-        data["mail"] = json!(user.email);
-        data["type"] = json!(user.user_type);
-        data["user_id"] = json!(user.id);
-        avatar = get_avatar(&user.email);
-      }
-    }
+  state.client_info.describe(&mut data, Some(&ip), Some(&ua));
+  if let Some(user) = user {
+    new_comment.user_id = Set(Some(user.id as i32));
+    data["label"] = json!(user.label);
+    data["mail"] = json!(user.email);
+    data["type"] = json!(user.user_type);
+    data["user_id"] = json!(user.id);
+    avatar = state.avatar.get_avatar(&user.email);
   }
-  let comment = new_comment
-    .insert(&state.conn)
+  new_comment.status = Set(match user_type {
+    UserType::Administrator(_) => "approved".to_string(),
+    _ => moderate(state, verdict, nick, mail, link, ip, comment).await?,
+  });
+  let comment = state
+    .repo
+    .comment()
+    .create_comment(new_comment)
     .await
     .map_err(AppError::from)?;
   data["avatar"] = json!(avatar);
@@ -327,6 +627,33 @@ pub async fn create_comment<'a>(
   if let Some(rid) = rid {
     data["rid"] = json!(rid);
   };
+  if ban == Some(Ban::Shadow) {
+    data["status"] = json!("approved");
+    return Ok(data);
+  }
+  if comment.status == "approved" {
+    let mut notified = vec![comment.mail.clone().unwrap_or_default().to_lowercase()];
+    for mention in mentions {
+      let email = mention.email.to_lowercase();
+      if notified.contains(&email) || email.parse::<lettre::Address>().is_err() {
+        continue;
+      }
+      notified.push(email);
+      let comment = comment.clone();
+      let lang = lang.clone();
+      spawn(async move {
+        send_email_notification(CommentNotification {
+          sender_name: comment.nick.unwrap_or_default(),
+          sender_email: comment.mail.unwrap_or_default(),
+          comment_id: comment.id,
+          comment: comment.comment.unwrap_or_default(),
+          url: comment.url.unwrap_or_default(),
+          notify_type: NotifyType::Mention(mention.email),
+          lang: Some(&lang),
+        });
+      });
+    }
+  }
   spawn(async move {
     send_email_notification(CommentNotification {
       sender_name: comment.nick.unwrap(),
@@ -341,61 +668,123 @@ pub async fn create_comment<'a>(
   Ok(data)
 }
 
-pub async fn delete_comment(state: &AppState, id: u32, email: String) -> Result<(), Code> {
-  let user = get_user(UserQueryBy::Email(email.clone()), &state.conn).await?;
+pub async fn delete_comment(state: &AppState, id: Id, email: String) -> Result<(), Code> {
+  let user = get_user(UserQueryBy::Email(email.clone()), &state.repo).await?;
   let pass = if user.user_type == "administrator" {
     true
   } else {
-    wl_comment::Entity::find()
-      .filter(wl_comment::Column::Id.eq(id))
-      .filter(wl_comment::Column::UserId.eq(user.id))
-      .one(&state.conn)
+    state
+      .repo
+      .comment()
+      .get_comment(id)
       .await
       .map_err(AppError::from)?
-      .is_some()
+      .is_some_and(|comment| comment.user_id == Some(user.id as i32))
   };
   if !pass {
     return Err(Code::Forbidden);
   }
-  match wl_comment::Entity::delete_by_id(id).exec(&state.conn).await {
-    Ok(_) => Ok(()),
-    Err(_) => Err(Code::Error),
+  if state.repo.comment().delete_comment(id).await.is_err() {
+    return Err(Code::Error);
   }
+  // Reports of the comment have nothing left to point at
+  wl_report::Entity::delete_many()
+    .filter(wl_report::Column::CommentId.eq(id))
+    .exec(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  Ok(())
 }
 
 pub async fn update_comment(
   state: &AppState,
   email: String,
-  id: u32,
+  id: Id,
   status: Option<String>,
-  like: Option<bool>,
-  comment: Option<String>,
-  link: Option<String>,
-  mail: Option<String>,
-  nick: Option<String>,
+  mut comment: Option<String>,
+  mut link: Option<String>,
+  mut mail: Option<String>,
+  mut nick: Option<String>,
   ua: Option<String>,
   url: Option<String>,
-  sticky: Option<i8>,
+  sticky: Option<Sticky>,
 ) -> Result<Value, Code> {
   let mut active_comment = wl_comment::ActiveModel {
     id: Set(id),
     updated_at: Set(Some(time::utc_now())),
     ..Default::default()
   };
-  let user = get_user(UserQueryBy::Email(email), &state.conn).await?;
-  let comment_opt = wl_comment::Entity::find()
-    .filter(wl_comment::Column::Id.eq(id))
-    .filter(wl_comment::Column::UserId.eq(user.id))
-    .one(&state.conn)
+  let user = get_user(UserQueryBy::Email(email.clone()), &state.repo).await?;
+  let is_admin = user.user_type == "administrator";
+  let previous = match state
+    .repo
+    .comment()
+    .get_comment(id)
     .await
-    .map_err(AppError::from)?;
-  if comment_opt.is_none() {
-    return Err(Code::Forbidden);
+    .map_err(AppError::from)?
+  {
+    Some(comment) if is_admin || comment.user_id == Some(user.id as i32) => comment,
+    _ => return Err(Code::Forbidden),
+  };
+  if link.as_deref().is_some_and(|link| !is_safe_link(link)) {
+    return Err(Code::InvalidLink);
   }
-  if let Some(like) = like {
-    let comment = get_comment(CommentQueryBy::Id(id), &state.conn).await?;
-    active_comment.like = Set(Some(comment.like.unwrap_or(0) + if like { 1 } else { -1 }));
+  // Only administrators moderate and pin, an edit by the author is moderated like a new
+  // comment so that a held or rejected comment cannot be edited into approval
+  let (mut status, sticky) = if is_admin {
+    (status, sticky)
+  } else {
+    (None, None)
+  };
+  if !is_admin {
+    let ban = state.bans.check_user(&user);
+    if ban == Some(Ban::Outright) {
+      return Err(Code::Forbidden);
+    }
+    let mut fields = [&mut nick, &mut mail, &mut link, &mut comment]
+      .into_iter()
+      .filter_map(Option::as_mut)
+      .collect::<Vec<_>>();
+    let verdict = state.word_filter.apply(&mut fields);
+    if verdict == Some(Action::Reject) {
+      return Err(Code::ForbiddenWord);
+    }
+    if comment.is_some() || link.is_some() || mail.is_some() || nick.is_some() {
+      let audit = state
+        .page_settings
+        .get(previous.url.as_deref().unwrap_or_default())
+        .and_then(|setting| setting.audit)
+        .unwrap_or(state.comment_audit);
+      let user_type = UserType::Guest(email);
+      let verdict = if ban == Some(Ban::Shadow) {
+        Some(Action::Spam)
+      } else if needs_review(state, audit, &user_type, Some(&user)).await? {
+        verdict.max(Some(Action::Hold))
+      } else {
+        verdict
+      };
+      let edited = |value: &Option<String>, previous: &Option<String>| {
+        value
+          .clone()
+          .or_else(|| previous.clone())
+          .unwrap_or_default()
+      };
+      status = Some(
+        moderate(
+          state,
+          verdict,
+          edited(&nick, &previous.nick),
+          edited(&mail, &previous.mail),
+          edited(&link, &previous.link),
+          previous.ip.clone().unwrap_or_default(),
+          edited(&comment, &previous.comment),
+        )
+        .await?,
+      );
+    }
   }
+  // Approving a comment settles the reports against it
+  let approved = is_admin && status.as_deref() == Some("approved");
   if let Some(status) = status {
     active_comment.status = Set(status);
   }
@@ -418,65 +807,135 @@ pub async fn update_comment(
     active_comment.mail = Set(Some(mail));
   }
   if let Some(url) = url {
-    active_comment.url = Set(Some(url));
+    active_comment.url = Set(Some(
+      state
+        .aliases
+        .resolve(url::normalize(&url, &state.page_query)),
+    ));
   }
 
-  let updated_comment = active_comment
-    .update(&state.conn)
+  let updated_comment = state
+    .repo
+    .comment()
+    .update_comment(active_comment)
     .await
     .map_err(AppError::from)?;
-  let (browser, os) = ua::parse(updated_comment.ua.unwrap_or("".to_owned()));
-  let like = updated_comment.like.unwrap_or(0);
-  let time = updated_comment.created_at.unwrap().timestamp_millis();
-  let pid = updated_comment.pid;
-  let rid = updated_comment.rid;
-  let html_output = render_md_to_html(updated_comment.comment.clone().unwrap().as_str());
-  if is_anonymous(id, &state.conn).await? {
-    let data = json!({
-      "addr":"",
-      "avatar": get_avatar("anonymous"),
-      "browser": browser,
+  if approved {
+    wl_report::Entity::delete_many()
+      .filter(wl_report::Column::CommentId.eq(id))
+      .exec(&state.conn)
+      .await
+      .map_err(AppError::from)?;
+  }
+  if is_admin && previous.status != updated_comment.status {
+    if let Some(to) = Class::from_status(&updated_comment.status) {
+      let from = Class::from_status(&previous.status);
+      let comment_id = previous.id;
+      let tokens = tokenize(
+        &previous.nick.clone().unwrap_or_default(),
+        &previous.mail.clone().unwrap_or_default(),
+        &previous.link.clone().unwrap_or_default(),
+        &previous.comment.clone().unwrap_or_default(),
+      );
+      let bayes = state.bayes.clone();
+      let conn = state.conn.clone();
+      spawn(async move {
+        bayes.train(&conn, comment_id, &tokens, to).await.ok();
+      });
+      // Only moderator corrections between spam and approved are worth reporting
+      if let (Some(akismet), Some(_)) = (state.akismet.clone(), from) {
+        spawn(async move {
+          submit_comment(&akismet, &previous, to).await.ok();
+        });
+      }
+    }
+  }
+  comment_data(state, updated_comment).await
+}
+
+/// Count a like of a comment or take it back. Only the count changes, a shadowbanned user
+/// gets the comment as if the like was counted
+pub async fn like_comment(
+  state: &AppState,
+  id: Id,
+  like: bool,
+  counted: bool,
+) -> Result<Value, Code> {
+  let comment = get_comment(CommentQueryBy::Id(id), &state.repo).await?;
+  let likes = (comment.like.unwrap_or(0) + if like { 1 } else { -1 }).max(0);
+  let comment = if counted {
+    state
+      .repo
+      .comment()
+      .update_comment(wl_comment::ActiveModel {
+        id: Unchanged(id),
+        like: Set(Some(likes)),
+        ..Default::default()
+      })
+      .await
+      .map_err(AppError::from)?
+  } else {
+    wl_comment::Model {
+      like: Some(likes),
+      ..comment
+    }
+  };
+  comment_data(state, comment).await
+}
+
+/// A comment as returned after a change
+async fn comment_data(state: &AppState, comment: wl_comment::Model) -> Result<Value, Code> {
+  let like = comment.like.unwrap_or(0);
+  let time = comment.created_at.unwrap().timestamp_millis();
+  let pid = comment.pid;
+  let rid = comment.rid;
+  let mentions = comment_mentions(&comment, state.mention_limit, &state.repo).await?;
+  let html_output = state.renderer.render(&mention::link(
+    comment.comment.as_deref().unwrap_or_default(),
+    &mentions,
+  ));
+  if is_anonymous(comment.id, &state.repo).await? {
+    let mut data = json!({
+      "avatar": state.avatar.get_avatar(comment.mail.as_deref().unwrap_or_default()),
       "comment": html_output,
-      "ip": updated_comment.ip,
-      "mail": updated_comment.mail,
-      "user_id": updated_comment.user_id,
+      "ip": comment.ip,
+      "mail": comment.mail,
+      "user_id": comment.user_id,
       "like": like,
-      "link": updated_comment.link,
-      "nick": updated_comment.nick,
-      "objectId": updated_comment.id,
-      "orig": updated_comment.comment,
-      "os": os,
-      "status": updated_comment.status,
+      "link": comment.link,
+      "nick": comment.nick,
+      "objectId": comment.id,
+      "orig": comment.comment,
+      "status": comment.status,
       "time": time,
-      "url": updated_comment.url,
+      "url": comment.url,
     });
+    state
+      .client_info
+      .describe(&mut data, comment.ip.as_deref(), comment.ua.as_deref());
     Ok(data)
   } else {
-    let user = get_user(
-      UserQueryBy::Id(updated_comment.user_id.unwrap() as u32),
-      &state.conn,
-    )
-    .await?;
+    let user = get_user(UserQueryBy::Id(comment.user_id.unwrap() as Id), &state.repo).await?;
     let mut data = json!({
-      "addr":"",
-      "avatar": get_avatar(&user.email),
-      "browser": browser,
+      "avatar": state.avatar.get_avatar(&user.email),
       "comment": html_output,
-      "ip": updated_comment.ip,
+      "ip": comment.ip,
       "label": user.label,
       "mail": user.email.clone(),
       "type": user.user_type,
-      "user_id": updated_comment.user_id,
+      "user_id": comment.user_id,
       "like": like,
-      "link": updated_comment.link,
-      "nick": updated_comment.nick,
-      "objectId": updated_comment.id,
-      "orig": updated_comment.comment,
-      "os": os,
-      "status": updated_comment.status,
+      "link": comment.link,
+      "nick": comment.nick,
+      "objectId": comment.id,
+      "orig": comment.comment,
+      "status": comment.status,
       "time": time,
-      "url": updated_comment.url,
+      "url": comment.url,
     });
+    state
+      .client_info
+      .describe(&mut data, comment.ip.as_deref(), comment.ua.as_deref());
     if let Some(pid) = pid {
       data["pid"] = json!(pid);
     }
@@ -486,3 +945,303 @@ pub async fn update_comment(
     Ok(data)
   }
 }
+
+pub async fn get_challenge(state: &AppState, client_ip: String) -> Challenge {
+  let spam_count = state.rate_limiter.spam_count(&client_ip).await;
+  state.pow.create_challenge(spam_count)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use super::*;
+  use crate::{app::test_state, helpers::forbidden::WordFilter};
+
+  async fn post(state: &AppState, comment: &str, pid: Option<i32>) -> Value {
+    create_comment(
+      state,
+      comment.to_string(),
+      String::new(),
+      "reader@example.com".to_string(),
+      "reader".to_string(),
+      "test".to_string(),
+      "/post".to_string(),
+      pid,
+      pid,
+      None,
+      "127.0.0.1".to_string(),
+      UserType::Anonymous,
+      "en".to_string(),
+    )
+    .await
+    .unwrap()
+  }
+
+  async fn list(state: &AppState, page: i32, page_size: i32) -> Value {
+    get_comment_info(
+      state,
+      "/post".to_string(),
+      page,
+      page_size,
+      "insertedAt_asc".to_string(),
+      Err(AppError::Error),
+      "127.0.0.1".to_string(),
+    )
+    .await
+    .unwrap()
+  }
+
+  async fn admin(state: &AppState) -> String {
+    let email = "admin@example.com".to_string();
+    state
+      .repo
+      .user()
+      .create_user(wl_users::ActiveModel {
+        display_name: Set("admin".to_string()),
+        email: Set(email.clone()),
+        password: Set(String::new()),
+        user_type: Set("administrator".to_string()),
+        ..Default::default()
+      })
+      .await
+      .unwrap();
+    email
+  }
+
+  #[actix_web::test]
+  async fn create_and_list() {
+    let state = test_state().await;
+    let parent = post(&state, "first", None).await;
+    assert_eq!(parent["status"], "approved");
+    assert_eq!(parent["comment"], "<p>first</p>\n");
+    let pid = parent["objectId"].as_i64().unwrap() as i32;
+    post(&state, "reply", Some(pid)).await;
+    let data = list(&state, 1, 10).await;
+    assert_eq!(data["count"], 2);
+    assert_eq!(data["totalPages"], 1);
+    let comments = data["data"].as_array().unwrap();
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0]["objectId"], parent["objectId"]);
+    let children = comments[0]["children"].as_array().unwrap();
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0]["orig"], "reply");
+  }
+
+  #[actix_web::test]
+  async fn paginate() {
+    let state = test_state().await;
+    for i in 0..5 {
+      post(&state, &format!("comment {i}"), None).await;
+    }
+    let first = list(&state, 1, 2).await;
+    assert_eq!(first["totalPages"], 3);
+    assert_eq!(first["count"], 5);
+    let orig = |data: &Value| {
+      data["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|comment| comment["orig"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(orig(&first), ["comment 0", "comment 1"]);
+    assert_eq!(orig(&list(&state, 3, 2).await), ["comment 4"]);
+    assert!(orig(&list(&state, 4, 2).await).is_empty());
+  }
+
+  #[actix_web::test]
+  async fn update() {
+    let state = test_state().await;
+    let email = admin(&state).await;
+    let created = post(&state, "before", None).await;
+    let id = created["objectId"].as_i64().unwrap() as Id;
+    let updated = update_comment(
+      &state,
+      email,
+      id,
+      Some("waiting".to_string()),
+      Some("after".to_string()),
+      None,
+      None,
+      None,
+      None,
+      None,
+      Some(1),
+    )
+    .await
+    .unwrap();
+    assert_eq!(updated["orig"], "after");
+    let stored = state.repo.comment().get_comment(id).await.unwrap().unwrap();
+    assert_eq!(stored.comment.as_deref(), Some("after"));
+    assert_eq!(stored.status, "waiting");
+    assert_eq!(stored.sticky, Some(1));
+    // Comments waiting for review are hidden from readers
+    assert_eq!(list(&state, 1, 10).await["count"], 0);
+  }
+
+  #[actix_web::test]
+  async fn audit_keeps_spam() {
+    let state = test_state().await;
+    let state = AppState {
+      comment_audit: true,
+      word_filter: Arc::new(
+        WordFilter::load(&state.conn, &["casino".to_string()])
+          .await
+          .unwrap(),
+      ),
+      ..state
+    };
+    assert_eq!(post(&state, "hello", None).await["status"], "waiting");
+    // A forbidden word set to spam is more severe than the audit
+    assert_eq!(post(&state, "casino", None).await["status"], "spam");
+  }
+
+  #[actix_web::test]
+  async fn mention() {
+    let state = test_state().await;
+    let first = post(&state, "first", None).await;
+    let href = format!("href=\"#{}\"", first["objectId"]);
+    let reply = post(&state, "@reader thanks", None).await;
+    assert!(
+      reply["comment"].as_str().unwrap().contains(&href),
+      "{reply}"
+    );
+    // The link is only rendered, the comment is kept as written
+    assert_eq!(reply["orig"], "@reader thanks");
+    let id = reply["objectId"].as_i64().unwrap() as Id;
+    let stored = state.repo.comment().get_comment(id).await.unwrap().unwrap();
+    assert_eq!(stored.comment.as_deref(), Some("@reader thanks"));
+    let data = list(&state, 1, 10).await;
+    let listed = &data["data"][1];
+    assert_eq!(listed["orig"], "@reader thanks");
+    assert!(listed["comment"].as_str().unwrap().contains(&href));
+  }
+
+  #[actix_web::test]
+  async fn duplicate() {
+    let state = test_state().await;
+    let duplicate = || {
+      is_duplicate(
+        "/post",
+        "reader@example.com",
+        "reader",
+        "",
+        "again",
+        &state.store,
+        &state.repo,
+      )
+    };
+    assert!(!duplicate().await.unwrap());
+    assert!(duplicate().await.unwrap());
+    // A submission that was not saved can be sent again
+    let key = submission_key("/post", "reader@example.com", "reader", "", "again");
+    state.store.remove(&key).await.unwrap();
+    assert!(!duplicate().await.unwrap());
+  }
+
+  #[actix_web::test]
+  async fn like() {
+    let state = test_state().await;
+    let created = post(&state, "liked", None).await;
+    let id = created["objectId"].as_i64().unwrap() as Id;
+    assert_eq!(
+      like_comment(&state, id, true, true).await.unwrap()["like"],
+      1
+    );
+    // A like that is not counted still looks counted
+    assert_eq!(
+      like_comment(&state, id, true, false).await.unwrap()["like"],
+      2
+    );
+    assert_eq!(
+      like_comment(&state, id, false, true).await.unwrap()["like"],
+      0
+    );
+    assert_eq!(
+      like_comment(&state, id, false, true).await.unwrap()["like"],
+      0
+    );
+    let stored = state.repo.comment().get_comment(id).await.unwrap().unwrap();
+    assert_eq!(stored.like, Some(0));
+  }
+
+  #[actix_web::test]
+  async fn owner_edit() {
+    let state = test_state().await;
+    admin(&state).await;
+    let email = "owner@example.com".to_string();
+    state
+      .repo
+      .user()
+      .create_user(wl_users::ActiveModel {
+        display_name: Set("owner".to_string()),
+        email: Set(email.clone()),
+        password: Set(String::new()),
+        user_type: Set("guest".to_string()),
+        ..Default::default()
+      })
+      .await
+      .unwrap();
+    let created = create_comment(
+      &state,
+      "mine".to_string(),
+      String::new(),
+      email.clone(),
+      "owner".to_string(),
+      "test".to_string(),
+      "/post".to_string(),
+      None,
+      None,
+      None,
+      "127.0.0.1".to_string(),
+      UserType::Guest(email.clone()),
+      "en".to_string(),
+    )
+    .await
+    .unwrap();
+    let id = created["objectId"].as_i64().unwrap() as Id;
+    state
+      .repo
+      .comment()
+      .update_comment(wl_comment::ActiveModel {
+        id: Unchanged(id),
+        status: Set("waiting".to_string()),
+        ..Default::default()
+      })
+      .await
+      .unwrap();
+    let edit = |status: Option<String>, comment: Option<String>, state: AppState| {
+      let email = email.clone();
+      async move {
+        update_comment(
+          &state,
+          email,
+          id,
+          status,
+          comment,
+          None,
+          None,
+          None,
+          None,
+          None,
+          Some(1),
+        )
+        .await
+        .unwrap()
+      }
+    };
+    // Authors can neither approve nor pin their comments
+    edit(Some("approved".to_string()), None, state.clone()).await;
+    let stored = state.repo.comment().get_comment(id).await.unwrap().unwrap();
+    assert_eq!((stored.status.as_str(), stored.sticky), ("waiting", None));
+    // An edit is moderated again, here with the comment audit on
+    let audited = AppState {
+      comment_audit: true,
+      ..state.clone()
+    };
+    let edited = edit(None, Some("edited".to_string()), audited).await;
+    assert_eq!(edited["orig"], "edited");
+    assert_eq!(edited["status"], "waiting");
+  }
+}
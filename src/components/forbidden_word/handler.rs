@@ -0,0 +1,85 @@
+use actix_web::{
+  delete, get, post, put,
+  web::{Data, Json, Path, Query},
+  HttpRequest, HttpResponse,
+};
+
+use crate::{
+  app::AppState,
+  components::forbidden_word::{model::*, service},
+  entities::Id,
+  helpers::header::extract_token,
+  response::{Code, Response},
+};
+
+#[get("/forbidden-word")]
+async fn get_forbidden_words(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<ForbiddenWordQuery>,
+) -> HttpResponse {
+  let Query(ForbiddenWordQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::get_forbidden_words(&state, token).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[post("/forbidden-word")]
+async fn create_forbidden_word(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<ForbiddenWordQuery>,
+  body: Json<ForbiddenWordBody>,
+) -> HttpResponse {
+  let Query(ForbiddenWordQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::create_forbidden_word(&state, token, body.into_inner()).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[put("/forbidden-word/{id}")]
+async fn update_forbidden_word(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<Id>,
+  query: Query<ForbiddenWordQuery>,
+  body: Json<ForbiddenWordBody>,
+) -> HttpResponse {
+  let Query(ForbiddenWordQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::update_forbidden_word(&state, token, path.into_inner(), body.into_inner()).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[delete("/forbidden-word/{id}")]
+async fn delete_forbidden_word(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<Id>,
+  query: Query<ForbiddenWordQuery>,
+) -> HttpResponse {
+  let Query(ForbiddenWordQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::delete_forbidden_word(&state, token, path.into_inner()).await {
+    Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
@@ -0,0 +1,12 @@
+mod handler;
+mod model;
+mod service;
+
+use actix_web::web::ServiceConfig;
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::get_forbidden_words);
+  cfg.service(handler::create_forbidden_word);
+  cfg.service(handler::update_forbidden_word);
+  cfg.service(handler::delete_forbidden_word);
+}
@@ -0,0 +1,19 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct ForbiddenWordQuery {
+  pub lang: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ForbiddenWordBody {
+  pub pattern: String,
+  #[serde(default)]
+  pub regex: bool,
+  #[serde(default)]
+  pub whole_word: bool,
+  #[serde(default)]
+  pub case_sensitive: bool,
+  // reject, spam, hold or mask
+  pub action: String,
+}
@@ -0,0 +1,106 @@
+use helpers::time::utc_now;
+use sea_orm::{ActiveModelTrait, EntityTrait, QueryOrder, Set};
+use serde_json::{json, Value};
+
+use crate::{
+  app::AppState,
+  components::{forbidden_word::model::*, user::model::verify_admin},
+  entities::{wl_forbidden_word, Id},
+  error::AppError,
+  helpers::forbidden::{compile, Action},
+  response::Code,
+};
+
+fn validate(body: &ForbiddenWordBody) -> Result<(), Code> {
+  if body.pattern.is_empty() || Action::parse(&body.action).is_none() {
+    return Err(Code::Error);
+  }
+  compile(
+    &body.pattern,
+    body.regex,
+    body.whole_word,
+    body.case_sensitive,
+  )
+  .map_err(|err| {
+    tracing::error!("Invalid forbidden word pattern: {err}");
+    Code::Error
+  })?;
+  Ok(())
+}
+
+pub async fn get_forbidden_words(state: &AppState, token: String) -> Result<Value, Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  let rules = wl_forbidden_word::Entity::find()
+    .order_by_asc(wl_forbidden_word::Column::Id)
+    .all(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  Ok(json!(rules))
+}
+
+pub async fn create_forbidden_word(
+  state: &AppState,
+  token: String,
+  body: ForbiddenWordBody,
+) -> Result<Value, Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  validate(&body)?;
+  let rule = wl_forbidden_word::ActiveModel {
+    pattern: Set(body.pattern),
+    regex: Set(body.regex),
+    whole_word: Set(body.whole_word),
+    case_sensitive: Set(body.case_sensitive),
+    action: Set(body.action),
+    created_at: Set(Some(utc_now())),
+    updated_at: Set(Some(utc_now())),
+    ..Default::default()
+  }
+  .insert(&state.conn)
+  .await
+  .map_err(AppError::from)?;
+  state.reload_caches().await?;
+  Ok(json!(rule))
+}
+
+pub async fn update_forbidden_word(
+  state: &AppState,
+  token: String,
+  id: Id,
+  body: ForbiddenWordBody,
+) -> Result<Value, Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  validate(&body)?;
+  if wl_forbidden_word::Entity::find_by_id(id)
+    .one(&state.conn)
+    .await
+    .map_err(AppError::from)?
+    .is_none()
+  {
+    return Err(Code::Error);
+  }
+  let rule = wl_forbidden_word::ActiveModel {
+    id: Set(id),
+    pattern: Set(body.pattern),
+    regex: Set(body.regex),
+    whole_word: Set(body.whole_word),
+    case_sensitive: Set(body.case_sensitive),
+    action: Set(body.action),
+    updated_at: Set(Some(utc_now())),
+    ..Default::default()
+  }
+  .update(&state.conn)
+  .await
+  .map_err(AppError::from)?;
+  state.reload_caches().await?;
+  Ok(json!(rule))
+}
+
+pub async fn delete_forbidden_word(state: &AppState, token: String, id: Id) -> Result<(), Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  wl_forbidden_word::Entity::delete_by_id(id)
+    .exec(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  state.reload_caches().await?;
+  Ok(())
+}
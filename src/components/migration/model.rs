@@ -1,8 +1,6 @@
-use crate::entities::{
-  wl_comment::Entity as Comment, wl_counter::Entity as Counter, wl_users::Entity as User,
-};
+use crate::entities::{wl_comment, wl_counter, wl_users, Id, Sticky};
 use chrono::{Local, Utc};
-use sea_orm::{prelude::DateTimeUtc, DerivePartialModel, FromQueryResult};
+use sea_orm::prelude::DateTimeUtc;
 use serde::{Deserialize, Serialize, Serializer};
 
 #[derive(Deserialize)]
@@ -62,7 +60,7 @@ pub struct CreateDataBody {
   #[serde(default, with = "datetime_utc_format")]
   pub updated_at: Option<chrono::DateTime<Utc>>,
   #[serde(rename = "objectId")]
-  pub object_id: Option<u32>,
+  pub object_id: Option<Id>,
   pub time: Option<i32>,
   pub reaction0: Option<i32>,
   pub reaction1: Option<i32>,
@@ -85,7 +83,7 @@ pub struct CreateDataBody {
 #[derive(Deserialize)]
 pub struct UpdateDataQuery {
   #[serde(rename = "objectId")]
-  pub object_id: u32,
+  pub object_id: Id,
   pub table: String,
   pub lang: String,
 }
@@ -93,7 +91,7 @@ pub struct UpdateDataQuery {
 #[derive(Deserialize)]
 pub struct UpdateDataBody {
   #[serde(rename = "objectId")]
-  pub object_id: Option<u32>,
+  pub object_id: Option<Id>,
   #[serde(rename = "2fa")]
   pub two_factor_auth: Option<String>,
   pub display_name: Option<String>,
@@ -126,11 +124,10 @@ where
   }
 }
 
-#[derive(Debug, DerivePartialModel, FromQueryResult, Serialize)]
-#[sea_orm(entity = "Comment")]
+#[derive(Debug, Serialize)]
 pub struct CommentData {
   #[serde(rename = "objectId")]
-  pub id: u32,
+  pub id: Id,
   pub user_id: Option<i32>,
   pub comment: Option<String>,
   pub ip: Option<String>,
@@ -139,27 +136,23 @@ pub struct CommentData {
   pub nick: Option<String>,
   pub pid: Option<i32>,
   pub rid: Option<i32>,
-  pub sticky: Option<i8>,
+  pub sticky: Option<Sticky>,
   pub status: String,
   pub like: Option<i32>,
   pub ua: Option<String>,
   pub url: Option<String>,
   #[serde(serialize_with = "fmt_datetime")]
-  #[sea_orm(from_col = "insertedAt")]
   pub inserted_at: Option<DateTimeUtc>,
   #[serde(serialize_with = "fmt_datetime")]
-  #[sea_orm(from_col = "createdAt")]
   pub created_at: Option<DateTimeUtc>,
   #[serde(serialize_with = "fmt_datetime")]
-  #[sea_orm(from_col = "updatedAt")]
   pub updated_at: Option<DateTimeUtc>,
 }
 
-#[derive(Debug, DerivePartialModel, FromQueryResult, Serialize)]
-#[sea_orm(entity = "Counter")]
+#[derive(Debug, Serialize)]
 pub struct CounterData {
   #[serde(rename = "objectId")]
-  pub id: u32,
+  pub id: Id,
   pub time: Option<i32>,
   pub reaction0: Option<i32>,
   pub reaction1: Option<i32>,
@@ -172,19 +165,14 @@ pub struct CounterData {
   pub reaction8: Option<i32>,
   pub url: String,
   #[serde(serialize_with = "fmt_datetime")]
-  #[sea_orm(from_col = "createdAt")]
   pub created_at: Option<DateTimeUtc>,
   #[serde(serialize_with = "fmt_datetime")]
-  #[sea_orm(from_col = "updatedAt")]
   pub updated_at: Option<DateTimeUtc>,
 }
-use crate::entities::wl_users;
-use sea_orm::prelude::Expr;
-#[derive(DerivePartialModel, FromQueryResult, Serialize)]
-#[sea_orm(entity = "User")]
+#[derive(Serialize)]
 pub struct UserData {
   #[serde(rename = "objectId")]
-  pub id: u32,
+  pub id: Id,
   pub display_name: String,
   pub email: String,
   pub password: String,
@@ -197,16 +185,81 @@ pub struct UserData {
   pub google: Option<String>,
   pub weibo: Option<String>,
   pub qq: Option<String>,
-  #[sea_orm(from_expr = "Expr::col(wl_users::Column::UserType)")]
   #[serde(rename = "type")]
   pub user_type: String,
-  #[sea_orm(from_expr = "Expr::col(wl_users::Column::TwoFactorAuth)")]
   #[serde(rename = "2fa")]
   pub two_factor_auth: Option<String>,
   #[serde(serialize_with = "fmt_datetime")]
-  #[sea_orm(from_col = "createdAt")]
   pub created_at: Option<DateTimeUtc>,
   #[serde(serialize_with = "fmt_datetime")]
-  #[sea_orm(from_col = "updatedAt")]
   pub updated_at: Option<DateTimeUtc>,
 }
+
+impl From<wl_comment::Model> for CommentData {
+  fn from(comment: wl_comment::Model) -> Self {
+    CommentData {
+      id: comment.id,
+      user_id: comment.user_id,
+      comment: comment.comment,
+      ip: comment.ip,
+      link: comment.link,
+      mail: comment.mail,
+      nick: comment.nick,
+      pid: comment.pid,
+      rid: comment.rid,
+      sticky: comment.sticky,
+      status: comment.status,
+      like: comment.like,
+      ua: comment.ua,
+      url: comment.url,
+      inserted_at: comment.inserted_at,
+      created_at: comment.created_at,
+      updated_at: comment.updated_at,
+    }
+  }
+}
+
+impl From<wl_counter::Model> for CounterData {
+  fn from(counter: wl_counter::Model) -> Self {
+    CounterData {
+      id: counter.id,
+      time: counter.time,
+      reaction0: counter.reaction0,
+      reaction1: counter.reaction1,
+      reaction2: counter.reaction2,
+      reaction3: counter.reaction3,
+      reaction4: counter.reaction4,
+      reaction5: counter.reaction5,
+      reaction6: counter.reaction6,
+      reaction7: counter.reaction7,
+      reaction8: counter.reaction8,
+      url: counter.url,
+      created_at: counter.created_at,
+      updated_at: counter.updated_at,
+    }
+  }
+}
+
+impl From<wl_users::Model> for UserData {
+  fn from(user: wl_users::Model) -> Self {
+    UserData {
+      id: user.id,
+      display_name: user.display_name,
+      email: user.email,
+      password: user.password,
+      label: user.label,
+      url: user.url,
+      avatar: user.avatar,
+      github: user.github,
+      twitter: user.twitter,
+      facebook: user.facebook,
+      google: user.google,
+      weibo: user.weibo,
+      qq: user.qq,
+      user_type: user.user_type,
+      two_factor_auth: user.two_factor_auth,
+      created_at: user.created_at,
+      updated_at: user.updated_at,
+    }
+  }
+}
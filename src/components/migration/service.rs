@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use crate::components::migration::model::CommentData;
 use crate::components::user::model::get_user;
 use crate::prelude::*;
@@ -8,35 +10,55 @@ use crate::{
     comment::model::{get_comment, CommentQueryBy},
     user::model::{has_user, UserQueryBy},
   },
-  entities::{wl_comment, wl_counter, wl_users},
+  entities::{wl_comment, wl_counter, wl_store, wl_users, Id},
   error::AppError,
+  helpers::url,
+  repository::{merge_counter, CommentFilter, CommentOrder},
   response::Code,
 };
 use chrono::{DateTime, Utc};
-use sea_orm::{ActiveModelTrait, EntityTrait, IntoActiveModel, Set};
+use sea_orm::{
+  sea_query::{Expr, OnConflict},
+  ActiveModelTrait,
+  ActiveValue::Unchanged,
+  ColumnTrait, DatabaseConnection, EntityTrait, Insert, IntoActiveModel, QueryFilter, QueryOrder,
+  Set, TransactionTrait, TryIntoModel,
+};
 use serde_json::{json, Value};
 
 use super::model::{CounterData, UserData};
 
 pub async fn export_data(state: &AppState, _lang: String) -> Result<Value, String> {
-  let comments = wl_comment::Entity::find()
-    .into_partial_model::<CommentData>()
-    .all(&state.conn)
+  let comments = state
+    .repo
+    .comment()
+    .get_comments(CommentFilter::default(), CommentOrder::IdAsc)
     .await
     .log_err()
-    .unwrap();
-  let counters = wl_counter::Entity::find()
-    .into_partial_model::<CounterData>()
-    .all(&state.conn)
+    .unwrap()
+    .into_iter()
+    .map(CommentData::from)
+    .collect::<Vec<_>>();
+  let counters = state
+    .repo
+    .counter()
+    .get_counters()
     .await
     .log_err()
-    .unwrap();
-  let users = wl_users::Entity::find()
-    .into_partial_model::<UserData>()
-    .all(&state.conn)
+    .unwrap()
+    .into_iter()
+    .map(CounterData::from)
+    .collect::<Vec<_>>();
+  let users = state
+    .repo
+    .user()
+    .get_users()
     .await
     .log_err()
-    .unwrap();
+    .unwrap()
+    .into_iter()
+    .map(UserData::from)
+    .collect::<Vec<_>>();
   let data = json!({
       "type": "waline",
       "version": 1,
@@ -74,14 +96,17 @@ pub async fn create_comment_data(
     nick: Set(nick),
     status: Set(status.unwrap()),
     ua: Set(ua),
-    url: Set(url),
+    url: Set(url.map(|url| url::normalize(&url, &state.page_query))),
     created_at: Set(created_at),
     updated_at: Set(updated_at),
     ..Default::default()
-  }
-  .insert(&state.conn)
-  .await
-  .map_err(AppError::from)?;
+  };
+  let comment = state
+    .repo
+    .comment()
+    .create_comment(comment)
+    .await
+    .map_err(AppError::from)?;
   Ok(json!({
     "objectId": comment.id,
     "comment": comment.comment,
@@ -114,47 +139,162 @@ pub async fn create_counter_data(
   created_at: Option<chrono::DateTime<Utc>>,
   updated_at: Option<chrono::DateTime<Utc>>,
 ) -> Result<wl_counter::Model, Code> {
-  Ok(
-    wl_counter::ActiveModel {
-      time: Set(time),
-      reaction0: Set(reaction0),
-      reaction1: Set(reaction1),
-      reaction2: Set(reaction2),
-      reaction3: Set(reaction3),
-      reaction4: Set(reaction4),
-      reaction5: Set(reaction5),
-      reaction6: Set(reaction6),
-      reaction7: Set(reaction7),
-      reaction8: Set(reaction8),
-      url: Set(url.unwrap()),
-      created_at: Set(created_at),
-      updated_at: Set(updated_at),
-      ..Default::default()
+  let url = url::normalize(&url.unwrap(), &state.page_query);
+  let counter = wl_counter::ActiveModel {
+    time: Set(time),
+    reaction0: Set(reaction0),
+    reaction1: Set(reaction1),
+    reaction2: Set(reaction2),
+    reaction3: Set(reaction3),
+    reaction4: Set(reaction4),
+    reaction5: Set(reaction5),
+    reaction6: Set(reaction6),
+    reaction7: Set(reaction7),
+    reaction8: Set(reaction8),
+    url: Set(url.clone()),
+    created_at: Set(created_at),
+    updated_at: Set(updated_at),
+    ..Default::default()
+  };
+  let counters = state.repo.counter();
+  // Urls that only differed before normalization end up in one counter
+  let counter = match counters.get_counter(&url).await.map_err(AppError::from)? {
+    Some(mut existing) => {
+      merge_counter(
+        &mut existing,
+        &counter.try_into_model().map_err(AppError::from)?,
+      );
+      counters
+        .save_counter(existing.into_active_model().reset_all())
+        .await
     }
-    .insert(&state.conn)
-    .await
-    .map_err(AppError::from)?,
+    None => counters.insert_counter(counter).await,
+  };
+  Ok(counter.map_err(AppError::from)?)
+}
+
+/// Marks in `wl_Store` that the urls were normalized, it never expires
+const NORMALIZED_URLS: &str = "migration:normalize_urls";
+/// Value of the marker once the urls were normalized
+const NORMALIZED: &str = "done";
+
+/// Add the marker unless it exists. MySQL counts a conflicting row as affected too, so
+/// whether the urls were normalized is told by the value of the marker instead
+fn insert_marker() -> Insert<wl_store::ActiveModel> {
+  wl_store::Entity::insert(wl_store::ActiveModel {
+    key: Set(NORMALIZED_URLS.to_string()),
+    value: Set(String::new()),
+    expires_at: Set(i64::MAX),
+  })
+  .on_conflict(
+    OnConflict::column(wl_store::Column::Key)
+      .do_nothing_on([wl_store::Column::Key])
+      .to_owned(),
   )
 }
 
+/// Rewrite the urls of comments and counters stored before they were normalized, once per
+/// database. Counters of the same page are merged into the oldest one. Everything happens
+/// in one transaction together with setting the marker, so an interrupted run is redone
+/// in full on the next start and concurrent starts rewrite the tables only once
+pub async fn normalize_urls(conn: &DatabaseConnection, query: &[String]) -> Result<(), AppError> {
+  let txn = conn.begin().await?;
+  insert_marker().exec_without_returning(&txn).await?;
+  // Setting the marker locks it, a concurrent start waits here and then finds it set
+  let claimed = wl_store::Entity::update_many()
+    .col_expr(wl_store::Column::Value, Expr::value(NORMALIZED))
+    .filter(wl_store::Column::Key.eq(NORMALIZED_URLS))
+    .filter(wl_store::Column::Value.ne(NORMALIZED))
+    .exec(&txn)
+    .await?;
+  if claimed.rows_affected == 0 {
+    return Ok(());
+  }
+  let mut comments = 0;
+  for comment in wl_comment::Entity::find()
+    .filter(wl_comment::Column::Url.is_not_null())
+    .all(&txn)
+    .await?
+  {
+    let Some(old) = comment.url else {
+      continue;
+    };
+    let normalized = url::normalize(&old, query);
+    if normalized != old {
+      wl_comment::ActiveModel {
+        id: Unchanged(comment.id),
+        url: Set(Some(normalized)),
+        ..Default::default()
+      }
+      .update(&txn)
+      .await?;
+      comments += 1;
+    }
+  }
+  let counters = wl_counter::Entity::find()
+    .order_by_asc(wl_counter::Column::Id)
+    .all(&txn)
+    .await?;
+  // Counters to keep with whether they changed, by normalized url
+  let mut kept = HashMap::<String, (wl_counter::Model, bool)>::new();
+  let mut merged = vec![];
+  for mut counter in counters {
+    let normalized = url::normalize(&counter.url, query);
+    match kept.get_mut(&normalized) {
+      Some((kept, changed)) => {
+        merge_counter(kept, &counter);
+        *changed = true;
+        merged.push(counter.id);
+      }
+      None => {
+        let changed = normalized != counter.url;
+        counter.url = normalized.clone();
+        kept.insert(normalized, (counter, changed));
+      }
+    }
+  }
+  let mut updated = 0;
+  for (counter, _) in kept.into_values().filter(|(_, changed)| *changed) {
+    counter.into_active_model().reset_all().update(&txn).await?;
+    updated += 1;
+  }
+  wl_counter::Entity::delete_many()
+    .filter(wl_counter::Column::Id.is_in(merged.clone()))
+    .exec(&txn)
+    .await?;
+  txn.commit().await?;
+  if comments > 0 || updated > 0 {
+    tracing::info!(
+      "Normalized the urls of {comments} comments and {updated} counters, merging {} duplicate counters",
+      merged.len()
+    );
+  }
+  Ok(())
+}
+
 pub async fn update_comment_data(
   state: &AppState,
-  object_id: u32,
+  object_id: Id,
   pid: Option<i32>,
   rid: Option<i32>,
 ) -> Result<bool, Code> {
-  let mut comment = get_comment(CommentQueryBy::Id(object_id), &state.conn)
+  let mut comment = get_comment(CommentQueryBy::Id(object_id), &state.repo)
     .await?
     .into_active_model();
   comment.pid = Set(pid);
   comment.rid = Set(rid);
-  comment.update(&state.conn).await.map_err(AppError::from)?;
+  state
+    .repo
+    .comment()
+    .update_comment(comment)
+    .await
+    .map_err(AppError::from)?;
   Ok(true)
 }
 
 pub async fn create_user_data(
   state: &AppState,
-  _object_id: Option<u32>,
+  _object_id: Option<Id>,
   display_name: Option<String>,
   password: Option<String>,
   email: Option<String>,
@@ -177,7 +317,7 @@ pub async fn create_user_data(
     updated_at: Set(updated_at),
     ..Default::default()
   };
-  match wl_users::Entity::insert(model).exec(&state.conn).await {
+  match state.repo.user().create_user(model).await {
     Ok(_) => Ok(true),
     Err(err) => Err(err.to_string()),
   }
@@ -185,7 +325,7 @@ pub async fn create_user_data(
 
 pub async fn update_user_data(
   state: &AppState,
-  _object_id: Option<u32>,
+  _object_id: Option<Id>,
   display_name: Option<String>,
   password: Option<String>,
   email: Option<String>,
@@ -198,13 +338,13 @@ pub async fn update_user_data(
 ) -> Result<(), Code> {
   if has_user(
     UserQueryBy::Email(email.clone().unwrap_or("".to_string())),
-    &state.conn,
+    &state.repo,
   )
   .await?
   {
     let mut active_user = get_user(
       UserQueryBy::Email(email.clone().unwrap_or("".to_string())),
-      &state.conn,
+      &state.repo,
     )
     .await?
     .into_active_model();
@@ -217,8 +357,10 @@ pub async fn update_user_data(
     active_user.two_factor_auth = Set(two_factor_auth);
     active_user.created_at = Set(created_at);
     active_user.updated_at = Set(updated_at);
-    match active_user
-      .update(&state.conn)
+    match state
+      .repo
+      .user()
+      .update_user(active_user)
       .await
       .log_err()
       .map_err(AppError::from)
@@ -227,7 +369,7 @@ pub async fn update_user_data(
       Err(_) => Err(Code::Error),
     }
   } else {
-    match (wl_users::ActiveModel {
+    let user = wl_users::ActiveModel {
       display_name: Set(display_name.unwrap()),
       email: Set(email.unwrap()),
       password: Set(password.unwrap()),
@@ -238,11 +380,8 @@ pub async fn update_user_data(
       created_at: Set(created_at),
       updated_at: Set(updated_at),
       ..Default::default()
-    }
-    .insert(&state.conn)
-    .await
-    .log_err())
-    {
+    };
+    match state.repo.user().create_user(user).await.log_err() {
       Ok(_) => Ok(()),
       Err(_) => Err(Code::Error),
     }
@@ -252,15 +391,19 @@ pub async fn update_user_data(
 pub async fn delete_data(state: &AppState, table: &str) -> Result<bool, Code> {
   match table {
     "Comment" => {
-      wl_comment::Entity::delete_many()
-        .exec(&state.conn)
+      state
+        .repo
+        .comment()
+        .delete_comments()
         .await
         .map_err(AppError::from)?;
       Ok(true)
     }
     "Counter" => {
-      wl_counter::Entity::delete_many()
-        .exec(&state.conn)
+      state
+        .repo
+        .counter()
+        .delete_counters()
         .await
         .map_err(AppError::from)?;
       Ok(true)
@@ -269,3 +412,20 @@ pub async fn delete_data(state: &AppState, table: &str) -> Result<bool, Code> {
     _ => Err(Code::Error),
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, QueryTrait};
+
+  use super::*;
+
+  #[test]
+  fn marker_insert_runs_on_mysql() {
+    let sql = insert_marker().build(DbBackend::MySql).to_string();
+    assert!(sql.starts_with("INSERT INTO `wl_Store`"), "{sql}");
+    assert!(
+      sql.ends_with("ON DUPLICATE KEY UPDATE `key` = `key`"),
+      "{sql}"
+    );
+  }
+}
@@ -1,7 +1,12 @@
 //! components
 
 pub mod article;
+pub mod avatar;
+pub mod ban;
 pub mod comment;
+pub mod forbidden_word;
 pub mod migration;
+pub mod page;
+pub mod report;
 pub mod ui;
 pub mod user;
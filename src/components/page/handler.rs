@@ -0,0 +1,119 @@
+use actix_web::{
+  delete, get, post, put,
+  web::{Data, Json, Path, Query},
+  HttpRequest, HttpResponse,
+};
+
+use crate::{
+  app::AppState,
+  components::page::{model::*, service},
+  entities::Id,
+  helpers::header::extract_token,
+  response::{Code, Response},
+};
+
+#[post("/page/move")]
+async fn move_page(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<PageQuery>,
+  body: Json<MovePageBody>,
+) -> HttpResponse {
+  let Query(PageQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::move_page(&state, token, body.into_inner()).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[get("/page/alias")]
+async fn get_aliases(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<PageQuery>,
+) -> HttpResponse {
+  let Query(PageQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::get_aliases(&state, token).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[delete("/page/alias/{id}")]
+async fn delete_alias(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<Id>,
+  query: Query<PageQuery>,
+) -> HttpResponse {
+  let Query(PageQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::delete_alias(&state, token, path.into_inner()).await {
+    Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[get("/page/setting")]
+async fn get_settings(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<PageQuery>,
+) -> HttpResponse {
+  let Query(PageQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::get_settings(&state, token).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[put("/page/setting")]
+async fn set_setting(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<PageQuery>,
+  body: Json<PageSettingBody>,
+) -> HttpResponse {
+  let Query(PageQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::set_setting(&state, token, body.into_inner()).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[delete("/page/setting/{id}")]
+async fn delete_setting(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<Id>,
+  query: Query<PageQuery>,
+) -> HttpResponse {
+  let Query(PageQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::delete_setting(&state, token, path.into_inner()).await {
+    Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
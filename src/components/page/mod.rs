@@ -0,0 +1,14 @@
+mod handler;
+mod model;
+mod service;
+
+use actix_web::web::ServiceConfig;
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::move_page);
+  cfg.service(handler::get_aliases);
+  cfg.service(handler::delete_alias);
+  cfg.service(handler::get_settings);
+  cfg.service(handler::set_setting);
+  cfg.service(handler::delete_setting);
+}
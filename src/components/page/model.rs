@@ -0,0 +1,36 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+  pub lang: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MovePageBody {
+  pub from: String,
+  pub to: String,
+  /// Move the pages below `from` as well
+  #[serde(default)]
+  pub prefix: bool,
+  /// Keep resolving `from` to `to` afterwards
+  #[serde(default)]
+  pub alias: bool,
+}
+
+#[derive(Deserialize)]
+pub struct PageSettingBody {
+  pub url: String,
+  /// Apply to the pages below `url` as well
+  #[serde(default)]
+  pub prefix: bool,
+  /// Reject new comments
+  #[serde(default)]
+  pub locked: bool,
+  /// Reject new comments this many days after the first one
+  pub close_after: Option<i32>,
+  /// Hold new comments for review, or skip the review `COMMENT_AUDIT` asks for
+  pub audit: Option<bool>,
+  /// Only accept comments from users who are logged in
+  #[serde(default)]
+  pub force_login: bool,
+}
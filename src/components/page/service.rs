@@ -0,0 +1,164 @@
+use helpers::time::utc_now;
+use sea_orm::{
+  ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, Set,
+  TryIntoModel,
+};
+use serde_json::{json, Value};
+
+use crate::{
+  app::AppState,
+  components::{page::model::*, user::model::verify_admin},
+  entities::{wl_page_alias, wl_page_setting, Id},
+  error::AppError,
+  helpers::url::{self, relocate},
+  response::Code,
+};
+
+/// Move the comments and counters of a page, or of every page below a prefix, then its
+/// aliases
+pub async fn move_page(state: &AppState, token: String, body: MovePageBody) -> Result<Value, Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  let from = url::normalize(&body.from, &state.page_query);
+  let to = url::normalize(&body.to, &state.page_query);
+  // Moving a prefix below itself would keep moving pages on every lookup
+  if relocate(&to, &from, &to, body.prefix).is_some() {
+    return Err(Code::Error);
+  }
+  let moved = state
+    .repo
+    .page()
+    .move_page(&from, &to, body.prefix)
+    .await
+    .map_err(AppError::from)?;
+  update_aliases(state, &from, &to, &body).await?;
+  Ok(json!({
+    "from": from,
+    "to": to,
+    "comments": moved.comments,
+    "counters": moved.counters,
+    "merged": moved.merged,
+  }))
+}
+
+/// Point the aliases of moved pages at their new url, drop those that would hide the moved
+/// pages and record the move itself when asked to
+async fn update_aliases(
+  state: &AppState,
+  from: &str,
+  to: &str,
+  body: &MovePageBody,
+) -> Result<(), Code> {
+  for alias in wl_page_alias::Entity::find()
+    .all(&state.conn)
+    .await
+    .map_err(AppError::from)?
+  {
+    if relocate(&alias.source, to, to, body.prefix).is_some() {
+      wl_page_alias::Entity::delete_by_id(alias.id)
+        .exec(&state.conn)
+        .await
+        .map_err(AppError::from)?;
+    } else if let Some(target) = relocate(&alias.target, from, to, body.prefix) {
+      let mut alias = alias.into_active_model();
+      alias.target = Set(target);
+      alias.updated_at = Set(Some(utc_now()));
+      alias.update(&state.conn).await.map_err(AppError::from)?;
+    }
+  }
+  if body.alias {
+    wl_page_alias::Entity::delete_many()
+      .filter(wl_page_alias::Column::Source.eq(from))
+      .exec(&state.conn)
+      .await
+      .map_err(AppError::from)?;
+    wl_page_alias::ActiveModel {
+      source: Set(from.to_string()),
+      target: Set(to.to_string()),
+      prefix: Set(body.prefix),
+      created_at: Set(Some(utc_now())),
+      updated_at: Set(Some(utc_now())),
+      ..Default::default()
+    }
+    .insert(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  }
+  state.reload_caches().await?;
+  Ok(())
+}
+
+pub async fn get_aliases(state: &AppState, token: String) -> Result<Value, Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  let aliases = wl_page_alias::Entity::find()
+    .order_by_asc(wl_page_alias::Column::Id)
+    .all(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  Ok(json!(aliases))
+}
+
+pub async fn delete_alias(state: &AppState, token: String, id: Id) -> Result<(), Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  wl_page_alias::Entity::delete_by_id(id)
+    .exec(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  state.reload_caches().await?;
+  Ok(())
+}
+
+pub async fn get_settings(state: &AppState, token: String) -> Result<Value, Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  let settings = wl_page_setting::Entity::find()
+    .order_by_asc(wl_page_setting::Column::Id)
+    .all(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  Ok(json!(settings))
+}
+
+/// Create the settings of a page or prefix, or replace the ones it already has
+pub async fn set_setting(
+  state: &AppState,
+  token: String,
+  body: PageSettingBody,
+) -> Result<Value, Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  if body.close_after.is_some_and(|days| days < 0) {
+    return Err(Code::Error);
+  }
+  let url = url::normalize(&body.url, &state.page_query);
+  let existing = wl_page_setting::Entity::find()
+    .filter(wl_page_setting::Column::Url.eq(&url))
+    .filter(wl_page_setting::Column::Prefix.eq(body.prefix))
+    .one(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  let mut setting = match existing {
+    Some(setting) => setting.into_active_model(),
+    None => wl_page_setting::ActiveModel {
+      url: Set(url),
+      prefix: Set(body.prefix),
+      created_at: Set(Some(utc_now())),
+      ..Default::default()
+    },
+  };
+  setting.locked = Set(body.locked);
+  setting.close_after = Set(body.close_after);
+  setting.audit = Set(body.audit);
+  setting.force_login = Set(body.force_login);
+  setting.updated_at = Set(Some(utc_now()));
+  let setting = setting.save(&state.conn).await.map_err(AppError::from)?;
+  state.reload_caches().await?;
+  Ok(json!(setting.try_into_model().map_err(AppError::from)?))
+}
+
+pub async fn delete_setting(state: &AppState, token: String, id: Id) -> Result<(), Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  wl_page_setting::Entity::delete_by_id(id)
+    .exec(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  state.reload_caches().await?;
+  Ok(())
+}
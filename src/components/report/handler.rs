@@ -0,0 +1,111 @@
+use actix_web::{
+  delete, get, post, put,
+  web::{Data, Json, Path, Query},
+  HttpRequest, HttpResponse,
+};
+
+use crate::{
+  app::AppState,
+  components::{
+    report::{model::*, service},
+    user::model::verify_user,
+  },
+  entities::Id,
+  helpers::{
+    header::{extract_ip, extract_token},
+    limiter::Policy,
+  },
+  response::{Code, Response},
+};
+
+#[post("/comment/{id}/report")]
+async fn report_comment(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<Id>,
+  query: Query<ReportQuery>,
+  body: Json<ReportBody>,
+) -> HttpResponse {
+  let Query(ReportQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let client_ip = extract_ip(&req, &state.trusted_proxies);
+  let reporter = match extract_token(&req) {
+    Ok(token) => match verify_user(&token, &state.jwt_token, &state.repo).await {
+      Ok(user) => Some(user),
+      Err(err) => return HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+    },
+    Err(_) => None,
+  };
+  let limit = state.rate_limiter.check(Policy::Report, &client_ip).await;
+  if !limit.allowed {
+    return limit
+      .response()
+      .json(Response::<()>::error(Code::FrequencyLimited, lang));
+  }
+  match service::report_comment(
+    &state,
+    path.into_inner(),
+    body.into_inner().reason,
+    reporter,
+    client_ip,
+  )
+  .await
+  {
+    Ok(_) => limit.response().json(Response::<()>::success(None, lang)),
+    Err(err) => limit.response().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[get("/report")]
+async fn get_reports(
+  req: HttpRequest,
+  state: Data<AppState>,
+  query: Query<ReportQuery>,
+) -> HttpResponse {
+  let Query(ReportQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::get_reports(&state, token).await {
+    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[put("/report/{id}")]
+async fn resolve_reports(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<Id>,
+  query: Query<ReportQuery>,
+  body: Json<ResolveReportBody>,
+) -> HttpResponse {
+  let Query(ReportQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::resolve_reports(&state, token, path.into_inner(), body.into_inner().action).await {
+    Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
+
+#[delete("/report/{id}")]
+async fn dismiss_reports(
+  req: HttpRequest,
+  state: Data<AppState>,
+  path: Path<Id>,
+  query: Query<ReportQuery>,
+) -> HttpResponse {
+  let Query(ReportQuery { lang }) = query;
+  let lang = lang.as_deref();
+  let Ok(token) = extract_token(&req) else {
+    return HttpResponse::Ok().json(Response::<()>::error(Code::Unauthorized, lang));
+  };
+  match service::dismiss_reports(&state, token, path.into_inner()).await {
+    Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, lang)),
+    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, lang)),
+  }
+}
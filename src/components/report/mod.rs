@@ -0,0 +1,12 @@
+mod handler;
+mod model;
+mod service;
+
+use actix_web::web::ServiceConfig;
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg.service(handler::report_comment);
+  cfg.service(handler::get_reports);
+  cfg.service(handler::resolve_reports);
+  cfg.service(handler::dismiss_reports);
+}
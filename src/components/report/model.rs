@@ -0,0 +1,17 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct ReportQuery {
+  pub lang: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ReportBody {
+  pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveReportBody {
+  // waiting, spam or delete
+  pub action: String,
+}
@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+
+use helpers::time::utc_now;
+use sea_orm::{
+  sea_query::OnConflict, ActiveValue::Unchanged, ColumnTrait, EntityTrait, Insert, PaginatorTrait,
+  QueryFilter, QueryOrder, Set,
+};
+use serde_json::{json, Value};
+
+use crate::{
+  app::AppState,
+  components::{comment::service as comment_service, user::model::verify_admin},
+  entities::{wl_comment, wl_report, wl_users, Id},
+  error::AppError,
+  helpers::{ban::Ban, limiter::client_key},
+  response::Code,
+};
+
+/// Longest reason kept, in characters
+const MAX_REASON: usize = 500;
+
+async fn count_reports(state: &AppState, id: Id) -> Result<u64, Code> {
+  let count = wl_report::Entity::find()
+    .filter(wl_report::Column::CommentId.eq(id))
+    .count(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  Ok(count)
+}
+
+async fn delete_reports(state: &AppState, id: Id) -> Result<(), Code> {
+  wl_report::Entity::delete_many()
+    .filter(wl_report::Column::CommentId.eq(id))
+    .exec(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  Ok(())
+}
+
+async fn set_status(state: &AppState, id: Id, status: &str) -> Result<(), Code> {
+  state
+    .repo
+    .comment()
+    .update_comment(wl_comment::ActiveModel {
+      id: Unchanged(id),
+      status: Set(status.to_string()),
+      updated_at: Set(Some(utc_now())),
+      ..Default::default()
+    })
+    .await
+    .map_err(AppError::from)?;
+  Ok(())
+}
+
+/// Add a report unless the visitor already reported the comment
+fn insert_report(report: wl_report::ActiveModel) -> Insert<wl_report::ActiveModel> {
+  wl_report::Entity::insert(report).on_conflict(
+    OnConflict::columns([wl_report::Column::CommentId, wl_report::Column::Visitor])
+      .do_nothing_on([wl_report::Column::CommentId, wl_report::Column::Visitor])
+      .to_owned(),
+  )
+}
+
+/// Record a report of a reader against a published comment. Readers are told apart by
+/// their account or their IP, by the /64 prefix for IPv6 as the rate limiter does, and
+/// report a comment once. A comment collecting `REPORT_THRESHOLD` reports waits for review
+/// again
+pub async fn report_comment(
+  state: &AppState,
+  id: Id,
+  reason: String,
+  reporter: Option<wl_users::Model>,
+  ip: String,
+) -> Result<(), Code> {
+  let reason = reason.trim();
+  if reason.is_empty() || reason.chars().count() > MAX_REASON {
+    return Err(Code::Error);
+  }
+  match reporter
+    .as_ref()
+    .and_then(|user| state.bans.check_user(user))
+  {
+    Some(Ban::Outright) => return Err(Code::Forbidden),
+    // A shadowbanned report looks accepted but is not recorded
+    Some(Ban::Shadow) => return Ok(()),
+    None => {}
+  }
+  let comment = state
+    .repo
+    .comment()
+    .get_comment(id)
+    .await
+    .map_err(AppError::from)?
+    .filter(|comment| comment.status == "approved")
+    .ok_or(Code::Error)?;
+  let visitor = match &reporter {
+    Some(user) => format!("user:{}", user.id),
+    None => format!("ip:{}", client_key(&ip)),
+  };
+  let report = wl_report::ActiveModel {
+    comment_id: Set(id),
+    visitor: Set(visitor),
+    reason: Set(reason.to_string()),
+    created_at: Set(Some(utc_now())),
+    updated_at: Set(Some(utc_now())),
+    ..Default::default()
+  };
+  insert_report(report)
+    .exec_without_returning(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  if state.report_threshold > 0 && count_reports(state, id).await? >= state.report_threshold {
+    tracing::info!("Comment {} was reported, holding it for review", comment.id);
+    set_status(state, id, "waiting").await?;
+  }
+  Ok(())
+}
+
+/// Reported comments with their reports, the most reported first
+pub async fn get_reports(state: &AppState, token: String) -> Result<Value, Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  let mut reports = BTreeMap::<Id, Vec<wl_report::Model>>::new();
+  for report in wl_report::Entity::find()
+    .order_by_asc(wl_report::Column::Id)
+    .all(&state.conn)
+    .await
+    .map_err(AppError::from)?
+  {
+    reports.entry(report.comment_id).or_default().push(report);
+  }
+  let mut data = vec![];
+  for (id, reports) in reports {
+    let Some(comment) = state
+      .repo
+      .comment()
+      .get_comment(id)
+      .await
+      .map_err(AppError::from)?
+    else {
+      continue;
+    };
+    data.push(json!({
+      "objectId": comment.id,
+      "comment": comment.comment,
+      "nick": comment.nick,
+      "mail": comment.mail,
+      "ip": comment.ip,
+      "url": comment.url,
+      "status": comment.status,
+      "insertedAt": comment.inserted_at,
+      "count": reports.len(),
+      "reports": reports
+        .iter()
+        .map(|report| json!({
+          "id": report.id,
+          "reason": report.reason,
+          "visitor": report.visitor,
+          "createdAt": report.created_at,
+        }))
+        .collect::<Vec<_>>(),
+    }));
+  }
+  data.sort_by_key(|entry| std::cmp::Reverse(entry["count"].as_u64()));
+  Ok(json!(data))
+}
+
+/// Act on the reports of a comment by holding it, marking it as spam or deleting it. The
+/// reports are closed afterwards
+pub async fn resolve_reports(
+  state: &AppState,
+  token: String,
+  id: Id,
+  action: String,
+) -> Result<(), Code> {
+  let email = verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  match action.as_str() {
+    "waiting" | "spam" => {
+      // Through the comment service, so that the spam classifiers learn from it
+      comment_service::update_comment(
+        state,
+        email,
+        id,
+        Some(action),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      )
+      .await?;
+    }
+    "delete" => comment_service::delete_comment(state, id, email).await?,
+    _ => return Err(Code::Error),
+  }
+  delete_reports(state, id).await
+}
+
+/// Drop the reports of a comment, publishing it again when they held it for review
+pub async fn dismiss_reports(state: &AppState, token: String, id: Id) -> Result<(), Code> {
+  verify_admin(&token, &state.jwt_token, &state.repo).await?;
+  let held =
+    state.report_threshold > 0 && count_reports(state, id).await? >= state.report_threshold;
+  let comment = state
+    .repo
+    .comment()
+    .get_comment(id)
+    .await
+    .map_err(AppError::from)?;
+  if held && comment.is_some_and(|comment| comment.status == "waiting") {
+    set_status(state, id, "approved").await?;
+  }
+  delete_reports(state, id).await
+}
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, QueryTrait};
+
+  use super::*;
+  use crate::{app::test_state, components::comment::model::UserType};
+
+  #[actix_web::test]
+  async fn one_report_per_visitor() {
+    let state = AppState {
+      report_threshold: 2,
+      ..test_state().await
+    };
+    let created = comment_service::create_comment(
+      &state,
+      "reported".to_string(),
+      String::new(),
+      "reader@example.com".to_string(),
+      "reader".to_string(),
+      "test".to_string(),
+      "/post".to_string(),
+      None,
+      None,
+      None,
+      "127.0.0.1".to_string(),
+      UserType::Anonymous,
+      "en".to_string(),
+    )
+    .await
+    .unwrap();
+    let id = created["objectId"].as_i64().unwrap() as Id;
+    let report = |ip: &str| report_comment(&state, id, "spam".to_string(), None, ip.to_string());
+    let status = || async {
+      let comment = state.repo.comment().get_comment(id).await.unwrap().unwrap();
+      comment.status
+    };
+    // Both addresses are in one /64 and report once
+    report("2001:db8::1").await.unwrap();
+    report("2001:db8::2").await.unwrap();
+    assert_eq!(count_reports(&state, id).await.unwrap(), 1);
+    assert_eq!(status().await, "approved");
+    report("2001:db9::1").await.unwrap();
+    assert_eq!(count_reports(&state, id).await.unwrap(), 2);
+    assert_eq!(status().await, "waiting");
+  }
+
+  #[test]
+  fn report_insert_runs_on_mysql() {
+    let sql = insert_report(wl_report::ActiveModel {
+      comment_id: Set(1),
+      visitor: Set("ip:1.1.1.1".to_string()),
+      ..Default::default()
+    })
+    .build(DbBackend::MySql)
+    .to_string();
+    assert!(sql.contains("ON DUPLICATE KEY UPDATE"), "{sql}");
+  }
+}
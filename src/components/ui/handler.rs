@@ -60,6 +60,17 @@ pub async fn ui_user_page() -> HttpResponse {
     .body(service::admin_page().await)
 }
 
+/// Only http(s) urls are shown, the visitor has to follow the link by themselves
+#[get("/redirect")]
+pub async fn ui_redirect_page(query: Query<UiRedirectQuery>) -> HttpResponse {
+  match reqwest::Url::parse(&query.url) {
+    Ok(url) if matches!(url.scheme(), "http" | "https") => HttpResponse::Ok()
+      .content_type(ContentType::html())
+      .body(service::redirect_page(url.as_str()).await),
+    _ => HttpResponse::BadRequest().finish(),
+  }
+}
+
 pub async fn ui_page() -> HttpResponse {
   HttpResponse::Ok()
     .content_type(ContentType::html())
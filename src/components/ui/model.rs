@@ -10,3 +10,8 @@ pub struct UIProfilePageQuery {
 pub struct UiLoginPageQeury {
   pub redirect: Option<String>,
 }
+
+#[derive(Deserialize)]
+pub struct UiRedirectQuery {
+  pub url: String,
+}
@@ -31,3 +31,34 @@ pub async fn admin_page() -> String {
        </html>"#
   )
 }
+
+fn escape_html(value: &str) -> String {
+  value
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&#39;")
+}
+
+/// Interstitial shown before leaving the site through an external comment link
+pub async fn redirect_page(url: &str) -> String {
+  let site_name = escape_html(&env::var("SITE_NAME").ok().unwrap_or_default());
+  let url = escape_html(url);
+  format!(
+    r#"<!doctype html>
+       <html>
+         <head>
+           <meta charset="utf-8">
+           <meta name="referrer" content="no-referrer">
+           <meta name="robots" content="noindex,nofollow">
+           <title>{site_name}</title>
+           <meta name="viewport" content="width=device-width,initial-scale=1">
+         </head>
+         <body>
+           <p>You are about to leave {site_name} for an external site:</p>
+           <p><a href="{url}" rel="nofollow ugc noopener noreferrer">{url}</a></p>
+         </body>
+       </html>"#
+  )
+}
@@ -10,7 +10,11 @@ use serde_json::json;
 use crate::{
   app::AppState,
   components::user::{model::*, service},
-  helpers::header::{extract_token, extract_token_from_header},
+  entities::Id,
+  helpers::{
+    header::{extract_ip, extract_token, extract_token_from_header},
+    limiter::Policy,
+  },
   prelude::Code,
   response::Response,
 };
@@ -29,6 +33,15 @@ pub async fn user_register(
     password,
     url,
   }) = body;
+  let limit = state
+    .rate_limiter
+    .check(Policy::Register, &extract_ip(&req, &state.trusted_proxies))
+    .await;
+  if !limit.allowed {
+    return limit
+      .response()
+      .json(Response::<()>::error(Code::FrequencyLimited, Some(&lang)));
+  }
   match service::user_register(
     &state,
     display_name,
@@ -46,21 +59,38 @@ pub async fn user_register(
   )
   .await
   {
-    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), Some(&lang))),
-    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, Some(&lang))),
+    Ok(data) => limit
+      .response()
+      .json(Response::success(Some(data), Some(&lang))),
+    Err(err) => limit
+      .response()
+      .json(Response::<()>::error(err, Some(&lang))),
   }
 }
 
 #[post("/token")]
-pub async fn user_login(state: Data<AppState>, body: Json<UserLoginBody>) -> HttpResponse {
+pub async fn user_login(
+  req: HttpRequest,
+  state: Data<AppState>,
+  body: Json<UserLoginBody>,
+) -> HttpResponse {
   let Json(UserLoginBody {
     code,
     email,
     password,
   }) = body;
+  let limit = state
+    .rate_limiter
+    .check(Policy::Login, &extract_ip(&req, &state.trusted_proxies))
+    .await;
+  if !limit.allowed {
+    return limit
+      .response()
+      .json(Response::<()>::error(Code::FrequencyLimited, None));
+  }
   match service::user_login(&state, code, email, password).await {
-    Ok(data) => HttpResponse::Ok().json(Response::success(Some(data), None)),
-    Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
+    Ok(data) => limit.response().json(Response::success(Some(data), None)),
+    Err(err) => limit.response().json(Response::<()>::error(err, None)),
   }
 }
 
@@ -112,13 +142,13 @@ pub async fn set_user_profile(
 pub async fn set_user_type(
   req: HttpRequest,
   state: Data<AppState>,
-  path: Path<u32>,
+  path: Path<Id>,
   body: Json<SetUserTypeBody>,
 ) -> HttpResponse {
   let user_id = path.into_inner();
-  let Json(SetUserTypeBody { r#type }) = body;
+  let Json(SetUserTypeBody { r#type, trust }) = body;
   match extract_token(&req) {
-    Ok(token) => match service::set_user_type(&state, token, user_id, r#type).await {
+    Ok(token) => match service::set_user_type(&state, token, user_id, r#type, trust).await {
       Ok(_) => HttpResponse::Ok().json(Response::<()>::success(None, None)),
       Err(err) => HttpResponse::Ok().json(Response::<()>::error(err, None)),
     },
@@ -1,8 +1,14 @@
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use helpers::jwt;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
 use serde::Deserialize;
+use serde_json::{json, Value};
 
 use crate::error::AppError;
-use crate::{entities::wl_users, response::Code};
+use crate::{
+  entities::{wl_user_trust, wl_users, Id},
+  repository::RepositoryManager,
+  response::Code,
+};
 
 #[derive(Deserialize)]
 pub struct UserRegisterQuery {
@@ -58,74 +64,124 @@ pub struct Get2faQuery {
   pub email: Option<String>,
 }
 
-pub async fn is_first_user(conn: &DatabaseConnection) -> Result<bool, Code> {
-  let users = wl_users::Entity::find()
-    .all(conn)
-    .await
-    .map_err(AppError::from)?;
-  Ok(users.is_empty())
+pub async fn is_first_user(repo: &RepositoryManager) -> Result<bool, Code> {
+  let users = repo.user().count_users().await.map_err(AppError::from)?;
+  Ok(users == 0)
 }
 
-pub async fn is_first_admin_user(id: u32, conn: &DatabaseConnection) -> Result<bool, Code> {
-  let users = wl_users::Entity::find()
-    .filter(wl_users::Column::UserType.eq("administrator"))
-    .order_by_asc(wl_users::Column::CreatedAt)
-    .all(conn)
+pub async fn is_first_admin_user(id: Id, repo: &RepositoryManager) -> Result<bool, Code> {
+  let first_user = repo
+    .user()
+    .get_first_admin_user()
     .await
     .map_err(AppError::from)?;
-  if let Some(first_user) = users.first() {
-    Ok(first_user.id == id)
-  } else {
-    Ok(false)
-  }
+  Ok(first_user.is_some_and(|first_user| first_user.id == id))
 }
 
-pub async fn is_admin_user(email: &str, conn: &DatabaseConnection) -> Result<bool, Code> {
-  let user = wl_users::Entity::find()
-    .filter(wl_users::Column::Email.eq(email))
-    .filter(wl_users::Column::UserType.eq("administrator"))
-    .one(conn)
+pub async fn is_admin_user(email: &str, repo: &RepositoryManager) -> Result<bool, Code> {
+  let is_admin = repo
+    .user()
+    .is_admin_user(email)
     .await
     .map_err(AppError::from)?;
-  Ok(user.is_some())
+  Ok(is_admin)
+}
+
+/// Resolve the user a token belongs to
+pub async fn verify_user(
+  token: &str,
+  jwt_token: &str,
+  repo: &RepositoryManager,
+) -> Result<wl_users::Model, Code> {
+  let email = jwt::verify::<String>(token, jwt_token)
+    .map_err(|_| Code::Unauthorized)?
+    .claims
+    .data;
+  Ok(get_user(UserQueryBy::Email(email), repo).await?)
+}
+
+/// Resolve the email of the administrator a token belongs to
+pub async fn verify_admin(
+  token: &str,
+  jwt_token: &str,
+  repo: &RepositoryManager,
+) -> Result<String, Code> {
+  let email = jwt::verify::<String>(token, jwt_token)
+    .map_err(|_| Code::Unauthorized)?
+    .claims
+    .data;
+  if is_admin_user(&email, repo).await? {
+    Ok(email)
+  } else {
+    Err(Code::Forbidden)
+  }
 }
 
 #[derive(Debug, Clone)]
 pub enum UserQueryBy {
-  Id(u32),
+  Id(Id),
   Email(String),
 }
 
-pub async fn has_user(query_by: UserQueryBy, conn: &DatabaseConnection) -> Result<bool, AppError> {
-  let mut query = wl_users::Entity::find();
-  match query_by {
-    UserQueryBy::Id(id) => query = query.filter(wl_users::Column::Id.eq(id)),
-    UserQueryBy::Email(email) => query = query.filter(wl_users::Column::Email.eq(email)),
+pub async fn has_user(query_by: UserQueryBy, repo: &RepositoryManager) -> Result<bool, AppError> {
+  match get_user(query_by, repo).await {
+    Ok(_) => Ok(true),
+    Err(AppError::UserNotFound) => Ok(false),
+    Err(err) => Err(err),
   }
-  let res = query.one(conn).await.map_err(AppError::from)?;
-  Ok(res.is_some())
 }
 
 #[derive(Deserialize)]
 pub struct SetUserTypeBody {
-  pub r#type: String,
+  pub r#type: Option<String>,
+  /// One of `TRUST_LEVELS`, empty to go back to the comment history
+  pub trust: Option<String>,
+}
+
+/// Overrides an administrator can set on how the comments of a user are reviewed,
+/// `trusted` skips the audit and `moderated` is always reviewed
+pub const TRUST_LEVELS: [&str; 2] = ["trusted", "moderated"];
+
+/// The trust level an administrator set on a user
+pub async fn get_trust(conn: &DatabaseConnection, user_id: Id) -> Result<Option<String>, AppError> {
+  let trust = wl_user_trust::Entity::find()
+    .filter(wl_user_trust::Column::UserId.eq(user_id))
+    .one(conn)
+    .await?;
+  Ok(trust.map(|trust| trust.trust))
 }
 
 pub async fn get_user(
   query_by: UserQueryBy,
-  conn: &DatabaseConnection,
+  repo: &RepositoryManager,
 ) -> Result<wl_users::Model, AppError> {
-  if !has_user(query_by.to_owned(), conn).await? {
-    return Err(AppError::UserNotFound);
-  }
-  let mut query = wl_users::Entity::find();
   match query_by {
-    UserQueryBy::Id(id) => query = query.filter(wl_users::Column::Id.eq(id)),
-    UserQueryBy::Email(email) => query = query.filter(wl_users::Column::Email.eq(email)),
+    UserQueryBy::Id(id) => repo.user().get_user_by_id(id).await,
+    UserQueryBy::Email(email) => repo.user().get_user_by_email(&email).await,
   }
-  query
-    .one(conn)
-    .await
-    .map_err(AppError::from)?
-    .ok_or(AppError::UserNotFound)
+  .map_err(AppError::from)?
+  .ok_or(AppError::UserNotFound)
+}
+
+/// A user as the admin user list shows it, with the column names of the table
+pub fn user_json(user: wl_users::Model) -> Value {
+  json!({
+    "objectId": user.id,
+    "display_name": user.display_name,
+    "email": user.email,
+    "password": user.password,
+    "type": user.user_type,
+    "label": user.label,
+    "url": user.url,
+    "avatar": user.avatar,
+    "github": user.github,
+    "twitter": user.twitter,
+    "facebook": user.facebook,
+    "google": user.google,
+    "weibo": user.weibo,
+    "qq": user.qq,
+    "2fa": user.two_factor_auth,
+    "createdAt": user.created_at,
+    "updatedAt": user.updated_at,
+  })
 }
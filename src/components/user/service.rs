@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use helpers::{
   hash, jwt,
   time::utc_now,
@@ -5,21 +7,17 @@ use helpers::{
 };
 use regex::Regex;
 use sea_orm::{
-  ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, Iterable, PaginatorTrait,
-  QueryFilter, QuerySelect, Set,
+  ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, ModelTrait, QueryFilter, Set,
 };
 use serde_json::{json, Value};
 
 use crate::{
   app::AppState,
-  components::user::model::{has_user, is_first_user, UserQueryBy},
+  components::user::model::{has_user, is_first_user, user_json, UserQueryBy, TRUST_LEVELS},
   config::EnvConfig,
   entities::*,
   error::AppError,
-  helpers::{
-    avatar::get_avatar,
-    email::{send_email_notification, CommentNotification, NotifyType},
-  },
+  helpers::email::{send_email_notification, CommentNotification, NotifyType},
   response::Code,
 };
 
@@ -40,8 +38,8 @@ pub async fn user_register(
   let hashed: String =
     hash::bcrypt_custom(&password, 8, helpers::hash::Version::TwoA).map_err(|_| Code::Error)?;
   let EnvConfig { site_name, .. } = EnvConfig::load_env().unwrap();
-  if has_user(UserQueryBy::Email(email.clone()), &state.conn).await? {
-    let user = get_user(UserQueryBy::Email(email.clone()), &state.conn).await?;
+  if has_user(UserQueryBy::Email(email.clone()), &state.repo).await? {
+    let user = get_user(UserQueryBy::Email(email.clone()), &state.repo).await?;
     if user.user_type != "administrator" || user.user_type != "guest" {
       let mut active_user = user.into_active_model();
       active_user.display_name = Set(display_name);
@@ -66,8 +64,10 @@ pub async fn user_register(
         notify_type: NotifyType::Notify,
         lang: Some(lang),
       });
-      return match active_user
-        .update(&state.conn)
+      return match state
+        .repo
+        .user()
+        .update_user(active_user)
         .await
         .map_err(AppError::from)
       {
@@ -84,7 +84,7 @@ pub async fn user_register(
     password: Set(hashed),
     ..Default::default()
   };
-  if is_first_user(&state.conn).await? {
+  if is_first_user(&state.repo).await? {
     user.user_type = Set("administrator".to_string());
     data = json!({});
   } else {
@@ -108,7 +108,13 @@ pub async fn user_register(
       lang: Some(lang),
     });
   }
-  match user.insert(&state.conn).await.map_err(AppError::from) {
+  match state
+    .repo
+    .user()
+    .create_user(user)
+    .await
+    .map_err(AppError::from)
+  {
     Ok(_) => Ok(data),
     Err(err) => Err(err.into()),
   }
@@ -120,7 +126,7 @@ pub async fn user_login(
   email: String,
   password: String,
 ) -> Result<Value, Code> {
-  let user = get_user(UserQueryBy::Email(email.clone()), &state.conn).await?;
+  let user = get_user(UserQueryBy::Email(email.clone()), &state.repo).await?;
   let result = hash::verify_bcrypt(&password, &user.password).map_err(AppError::from)?;
   if !result {
     return Err(Code::Error);
@@ -135,7 +141,7 @@ pub async fn user_login(
     "type": user.user_type,
     "label": user.label,
     "url": user.url,
-    "avatar": get_avatar(&user.email),
+    "avatar": state.avatar.get_avatar(&user.email),
     "github": user.github,
     "twitter": user.twitter,
     "facebook": user.facebook,
@@ -157,7 +163,7 @@ pub async fn get_login_user_info(state: &AppState, token: String) -> Result<Valu
     .map_err(AppError::from)?
     .claims
     .data;
-  let user = get_user(UserQueryBy::Email(email), &state.conn).await?;
+  let user = get_user(UserQueryBy::Email(email), &state.repo).await?;
   let mail_md5 = helpers::hash::md5(user.email.as_bytes());
   Ok(json! ({
       "display_name": user.display_name,
@@ -165,7 +171,7 @@ pub async fn get_login_user_info(state: &AppState, token: String) -> Result<Valu
       "type": user.user_type,
       "label": user.label,
       "url": user.url,
-      "avatar": get_avatar(&user.email),
+      "avatar": state.avatar.get_avatar(&user.email),
       "github": user.github,
       "twitter": user.twitter,
       "facebook": user.facebook,
@@ -191,7 +197,7 @@ pub async fn set_user_profile(
     .map_err(AppError::from)?
     .claims
     .data;
-  let mut active_user = get_user(UserQueryBy::Email(email), &state.conn)
+  let mut active_user = get_user(UserQueryBy::Email(email), &state.repo)
     .await?
     .into_active_model();
   if let Some(display_name) = display_name {
@@ -210,30 +216,39 @@ pub async fn set_user_profile(
     let hashed = helpers::hash::bcrypt(&password).map_err(|_| Code::Error)?;
     active_user.password = Set(hashed);
   }
-  let res = active_user.update(&state.conn).await;
+  let res = state.repo.user().update_user(active_user).await;
   Ok(res.is_ok())
 }
 
 pub async fn set_user_type(
   state: &AppState,
   token: String,
-  user_id: u32,
-  r#type: String,
+  user_id: Id,
+  r#type: Option<String>,
+  trust: Option<String>,
 ) -> Result<bool, Code> {
   let email = jwt::verify::<String>(&token, &state.jwt_token)
     .map_err(|_| Code::Unauthorized)?
     .claims
     .data;
-  if is_admin_user(&email, &state.conn).await? {
-    let mut active_user = get_user(UserQueryBy::Id(user_id), &state.conn)
+  if is_admin_user(&email, &state.repo).await? {
+    let mut active_user = get_user(UserQueryBy::Id(user_id), &state.repo)
       .await?
       .into_active_model();
-    if is_first_admin_user(user_id, &state.conn).await? {
+    if let Some(trust) = trust {
+      set_trust(state, user_id, trust).await?;
+    }
+    let Some(r#type) = r#type else {
+      return Ok(true);
+    };
+    if is_first_admin_user(user_id, &state.repo).await? {
       return Err(Code::Forbidden);
     }
     active_user.user_type = Set(r#type);
-    active_user
-      .update(&state.conn)
+    state
+      .repo
+      .user()
+      .update_user(active_user)
       .await
       .map_err(|_| AppError::Database)?;
     Ok(true)
@@ -242,45 +257,94 @@ pub async fn set_user_type(
   }
 }
 
+/// Record the trust level of a user, or drop it when `trust` is empty
+async fn set_trust(state: &AppState, user_id: Id, trust: String) -> Result<(), Code> {
+  if !trust.is_empty() && !TRUST_LEVELS.contains(&trust.as_str()) {
+    return Err(Code::Error);
+  }
+  let existing = wl_user_trust::Entity::find()
+    .filter(wl_user_trust::Column::UserId.eq(user_id))
+    .one(&state.conn)
+    .await
+    .map_err(AppError::from)?;
+  match existing {
+    Some(existing) if trust.is_empty() => {
+      existing.delete(&state.conn).await.map_err(AppError::from)?;
+    }
+    Some(existing) => {
+      let mut existing = existing.into_active_model();
+      existing.trust = Set(trust);
+      existing.updated_at = Set(Some(utc_now()));
+      existing.update(&state.conn).await.map_err(AppError::from)?;
+    }
+    None if trust.is_empty() => {}
+    None => {
+      wl_user_trust::ActiveModel {
+        user_id: Set(user_id),
+        trust: Set(trust),
+        created_at: Set(Some(utc_now())),
+        updated_at: Set(Some(utc_now())),
+        ..Default::default()
+      }
+      .insert(&state.conn)
+      .await
+      .map_err(AppError::from)?;
+    }
+  }
+  Ok(())
+}
+
 pub async fn get_user_info_list(state: &AppState, page: u32) -> Result<Value, Code> {
   let page_size = 10;
-  let paginator = wl_users::Entity::find()
-    .select_only()
-    .columns(wl_users::Column::iter().filter(|col| !matches!(col, wl_users::Column::Id)))
-    .column_as(wl_users::Column::Id, "objectId")
-    .into_json()
-    .paginate(&state.conn, page_size);
-  let total_pages = paginator.num_pages().await.map_err(AppError::from)?;
-  let users = paginator
-    .fetch_page((page - 1) as u64)
+  let users = state
+    .repo
+    .user()
+    .paginate_users(page_size, (page - 1) as u64)
     .await
     .map_err(AppError::from)?;
+  let trust = wl_user_trust::Entity::find()
+    .all(&state.conn)
+    .await
+    .map_err(AppError::from)?
+    .into_iter()
+    .map(|trust| (trust.user_id, trust.trust))
+    .collect::<HashMap<_, _>>();
+  let data = users
+    .items
+    .into_iter()
+    .map(|user| {
+      let trust = trust.get(&user.id).cloned();
+      let mut user = user_json(user);
+      user["trust"] = json!(trust);
+      user
+    })
+    .collect::<Vec<_>>();
   Ok(json!({
-    "data": users,
+    "data": data,
     "page": page,
     "pageSize": page_size,
-    "totalPages": total_pages,
+    "totalPages": users.total_pages,
   }))
 }
 
 pub async fn get_user_info(state: &AppState, email: Option<String>) -> Result<Value, Code> {
-  match wl_users::Entity::find()
-    .filter(wl_users::Column::Email.eq(email))
-    .select_only()
-    .columns(wl_users::Column::iter().filter(|col| !matches!(col, wl_users::Column::Id)))
-    .column_as(wl_users::Column::Id, "objectId")
-    .into_json()
-    .one(&state.conn)
+  let Some(email) = email else {
+    return Err(Code::Error);
+  };
+  match state
+    .repo
+    .user()
+    .get_user_by_email(&email)
     .await
     .map_err(AppError::from)?
   {
-    Some(data) => Ok(data),
+    Some(user) => Ok(user_json(user)),
     None => Err(Code::Error),
   }
 }
 
 pub async fn verification(state: &AppState, email: String, token: String) -> Result<bool, Code> {
-  let user = get_user(UserQueryBy::Email(email), &state.conn)
+  let user = get_user(UserQueryBy::Email(email), &state.repo)
     .await
     .map_err(AppError::from)?;
   tracing::debug!("type: {}", user.user_type);
@@ -293,8 +357,10 @@ pub async fn verification(state: &AppState, email: String, token: String) -> Res
   {
     let mut active_user = user.into_active_model();
     active_user.user_type = Set("guest".to_string());
-    active_user
-      .update(&state.conn)
+    state
+      .repo
+      .user()
+      .update_user(active_user)
       .await
       .map_err(AppError::from)?;
     return Ok(true);
@@ -310,13 +376,18 @@ pub async fn set_2fa(_state: &AppState, _code: String, _secret: String) -> Resul
 pub async fn get_2fa(state: &AppState, email: Option<String>) -> Result<Value, Code> {
   match email {
     Some(email) => {
-      let user = wl_users::Entity::find()
-        .filter(wl_users::Column::Email.eq(email))
-        .filter(wl_users::Column::TwoFactorAuth.is_not_null())
-        .filter(wl_users::Column::TwoFactorAuth.ne(""))
-        .one(&state.conn)
+      let user = state
+        .repo
+        .user()
+        .get_user_by_email(&email)
         .await
-        .map_err(AppError::from)?;
+        .map_err(AppError::from)?
+        .filter(|user| {
+          user
+            .two_factor_auth
+            .as_ref()
+            .is_some_and(|tfa| !tfa.is_empty())
+        });
       match user {
         Some(_) => Ok(json!({
             "enable": true
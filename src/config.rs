@@ -27,6 +27,50 @@ fn default_ipqps() -> u64 {
   60
 }
 
+fn default_rate_limit_login() -> String {
+  "10/60".to_string()
+}
+
+fn default_rate_limit_register() -> String {
+  "5/3600".to_string()
+}
+
+fn default_rate_limit_like() -> String {
+  "30/60".to_string()
+}
+
+fn default_rate_limit_counter() -> String {
+  "120/60".to_string()
+}
+
+fn default_rate_limit_preview() -> String {
+  "20/60".to_string()
+}
+
+fn default_rate_limit_report() -> String {
+  "10/3600".to_string()
+}
+
+fn default_rate_limit_avatar() -> String {
+  "60/60".to_string()
+}
+
+fn default_spam_classifier() -> String {
+  "both".to_string()
+}
+
+fn default_bayes_threshold() -> f64 {
+  0.9
+}
+
+fn default_bayes_min_documents() -> i32 {
+  10
+}
+
+fn default_store() -> String {
+  "memory".to_string()
+}
+
 fn default_host() -> String {
   "127.0.0.1".to_string()
 }
@@ -39,6 +83,14 @@ fn default_comment_audit() -> bool {
   false
 }
 
+fn default_trust_threshold() -> u64 {
+  0
+}
+
+fn default_report_threshold() -> u64 {
+  0
+}
+
 fn default_login() -> String {
   "no".to_string()
 }
@@ -47,6 +99,63 @@ fn default_disable_authore_notify() -> bool {
   false
 }
 
+fn default_pow_difficulty() -> u64 {
+  0
+}
+
+fn default_pow_max_difficulty() -> u64 {
+  1_000_000
+}
+
+fn default_markdown_extensions() -> Vec<String> {
+  [
+    "table",
+    "strikethrough",
+    "tasklist",
+    "footnote",
+    "emoji",
+    "autolink",
+  ]
+  .map(String::from)
+  .to_vec()
+}
+
+fn default_markdown_html() -> bool {
+  true
+}
+
+fn default_markdown_image() -> bool {
+  true
+}
+
+fn default_math_renderer() -> String {
+  "mathml".to_string()
+}
+
+fn default_highlight() -> String {
+  "InspiredGitHub".to_string()
+}
+
+fn default_mention_limit() -> usize {
+  5
+}
+
+fn default_avatar() -> String {
+  "gravatar".to_string()
+}
+
+fn default_avatar_default() -> String {
+  "mp".to_string()
+}
+
+fn default_avatar_cache_ttl() -> u64 {
+  86400
+}
+
+fn default_avatar_cache_size() -> u64 {
+  100
+}
+
 fn deserialize_comma_separated<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
 where
   D: Deserializer<'de>,
@@ -67,6 +176,7 @@ pub struct EnvConfig {
   pub jwt_token: String,
   pub site_name: String,
   pub site_url: String,
+  pub server_url: Option<String>,
   pub smtp_service: Option<String>,
   pub smtp_host: Option<String>,
   pub smtp_port: Option<u16>,
@@ -76,20 +186,107 @@ pub struct EnvConfig {
   pub levels: Option<String>,
   #[serde(default = "default_ipqps")]
   pub ipqps: u64,
+  #[serde(default = "default_rate_limit_login")]
+  pub rate_limit_login: String,
+  #[serde(default = "default_rate_limit_register")]
+  pub rate_limit_register: String,
+  #[serde(default = "default_rate_limit_like")]
+  pub rate_limit_like: String,
+  #[serde(default = "default_rate_limit_counter")]
+  pub rate_limit_counter: String,
+  #[serde(default = "default_rate_limit_preview")]
+  pub rate_limit_preview: String,
+  #[serde(default = "default_rate_limit_report")]
+  pub rate_limit_report: String,
+  #[serde(default = "default_rate_limit_avatar")]
+  pub rate_limit_avatar: String,
   #[serde(default = "default_comment_audit")]
   pub comment_audit: bool,
+  #[serde(default = "default_trust_threshold")]
+  pub trust_threshold: u64,
+  #[serde(default = "default_report_threshold")]
+  pub report_threshold: u64,
   #[serde(default = "default_akismet_key")]
   pub akismet_key: String,
+  pub akismet_url: Option<String>,
+  #[serde(default = "default_spam_classifier")]
+  pub spam_classifier: String,
+  #[serde(default = "default_bayes_threshold")]
+  pub bayes_threshold: f64,
+  #[serde(default = "default_bayes_min_documents")]
+  pub bayes_min_documents: i32,
   #[serde(default = "default_login")]
   pub login: String,
   #[serde(default = "default_disable_authore_notify")]
   pub disable_author_notify: bool,
+  #[serde(default = "default_mention_limit")]
+  pub mention_limit: usize,
+  pub ip_database: Option<String>,
+  #[serde(default = "default_avatar")]
+  pub avatar: String,
+  pub avatar_proxy: Option<String>,
+  #[serde(default = "default_avatar_default")]
+  pub avatar_default: String,
+  pub avatar_cache: Option<String>,
+  #[serde(default = "default_avatar_cache_ttl")]
+  pub avatar_cache_ttl: u64,
+  #[serde(default = "default_avatar_cache_size")]
+  pub avatar_cache_size: u64,
+  #[serde(default)]
+  pub disable_region: bool,
+  #[serde(default)]
+  pub disable_useragent: bool,
   #[serde(default, deserialize_with = "deserialize_comma_separated")]
   pub disallow_ip_list: Vec<String>,
   #[serde(default, deserialize_with = "deserialize_comma_separated")]
   pub forbidden_words: Vec<String>,
+  #[serde(default)]
+  pub max_links: usize,
+  #[serde(default, deserialize_with = "deserialize_comma_separated")]
+  pub blocked_domains: Vec<String>,
+  #[serde(default)]
+  pub link_redirect: bool,
+  #[serde(
+    default = "default_markdown_extensions",
+    deserialize_with = "deserialize_comma_separated"
+  )]
+  pub markdown_extensions: Vec<String>,
+  #[serde(default = "default_markdown_html")]
+  pub markdown_html: bool,
+  #[serde(default = "default_markdown_image")]
+  pub markdown_image: bool,
+  #[serde(default, deserialize_with = "deserialize_comma_separated")]
+  pub emoji: Vec<String>,
+  #[serde(default = "default_math_renderer")]
+  pub math_renderer: String,
+  #[serde(default = "default_highlight")]
+  pub highlight: String,
+  #[serde(default, deserialize_with = "deserialize_comma_separated")]
+  pub sanitize_tags: Vec<String>,
+  #[serde(default, deserialize_with = "deserialize_comma_separated")]
+  pub sanitize_attributes: Vec<String>,
+  #[serde(default, deserialize_with = "deserialize_comma_separated")]
+  pub sanitize_url_schemes: Vec<String>,
   #[serde(default, deserialize_with = "deserialize_comma_separated")]
   pub secure_domians: Vec<String>,
+  #[serde(default, deserialize_with = "deserialize_comma_separated")]
+  pub trusted_proxies: Vec<String>,
+  #[serde(default, deserialize_with = "deserialize_comma_separated")]
+  pub page_query: Vec<String>,
+  #[serde(default = "default_store")]
+  pub store: String,
+  #[serde(default = "default_pow_difficulty")]
+  pub pow_difficulty: u64,
+  #[serde(default = "default_pow_max_difficulty")]
+  pub pow_max_difficulty: u64,
+  #[cfg(feature = "leancloud")]
+  pub lean_id: Option<String>,
+  #[cfg(feature = "leancloud")]
+  pub lean_key: Option<String>,
+  #[cfg(feature = "leancloud")]
+  pub lean_master_key: Option<String>,
+  #[cfg(feature = "leancloud")]
+  pub lean_server: Option<String>,
 }
 
 impl EnvConfig {
@@ -1,5 +1,32 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.0
 
+/// Ids are unsigned in the Waline MySQL and SQLite schemas, PostgreSQL has no unsigned
+/// integers
+#[cfg(not(feature = "postgres"))]
+pub type Id = u32;
+#[cfg(feature = "postgres")]
+pub type Id = i32;
+
+/// `sticky` is a `tinyint`, PostgreSQL has no single byte integer
+#[cfg(not(feature = "postgres"))]
+pub type Sticky = i8;
+#[cfg(feature = "postgres")]
+pub type Sticky = i16;
+
+// The Waline PostgreSQL schema names its tables and columns unquoted, so PostgreSQL folds
+// them to lower case, `wl_comment.insertedat` for `wl_Comment.insertedAt`
+
+pub mod wl_ban;
+pub mod wl_bayes;
+pub mod wl_bayes_trained;
 pub mod wl_comment;
 pub mod wl_counter;
+pub mod wl_forbidden_word;
+#[cfg(feature = "leancloud")]
+pub mod wl_lean_id;
+pub mod wl_page_alias;
+pub mod wl_page_setting;
+pub mod wl_report;
+pub mod wl_store;
+pub mod wl_user_trust;
 pub mod wl_users;
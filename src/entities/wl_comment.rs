@@ -3,15 +3,19 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use super::{Id, Sticky};
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
-#[sea_orm(table_name = "wl_Comment")]
+#[cfg_attr(not(feature = "postgres"), sea_orm(table_name = "wl_Comment"))]
+#[cfg_attr(feature = "postgres", sea_orm(table_name = "wl_comment"))]
 pub struct Model {
   #[sea_orm(primary_key)]
-  pub id: u32,
+  pub id: Id,
   pub user_id: Option<i32>,
   #[sea_orm(column_type = "Text", nullable)]
   pub comment: Option<String>,
-  #[sea_orm(column_name = "insertedAt")]
+  #[cfg_attr(not(feature = "postgres"), sea_orm(column_name = "insertedAt"))]
+  #[cfg_attr(feature = "postgres", sea_orm(column_name = "insertedat"))]
   pub inserted_at: Option<DateTimeUtc>,
   pub ip: Option<String>,
   pub link: Option<String>,
@@ -19,15 +23,17 @@ pub struct Model {
   pub nick: Option<String>,
   pub pid: Option<i32>,
   pub rid: Option<i32>,
-  pub sticky: Option<i8>,
+  pub sticky: Option<Sticky>,
   pub status: String,
   pub like: Option<i32>,
   #[sea_orm(column_type = "Text", nullable)]
   pub ua: Option<String>,
   pub url: Option<String>,
-  #[sea_orm(column_name = "createdAt")]
+  #[cfg_attr(not(feature = "postgres"), sea_orm(column_name = "createdAt"))]
+  #[cfg_attr(feature = "postgres", sea_orm(column_name = "createdat"))]
   pub created_at: Option<DateTimeUtc>,
-  #[sea_orm(column_name = "updatedAt")]
+  #[cfg_attr(not(feature = "postgres"), sea_orm(column_name = "updatedAt"))]
+  #[cfg_attr(feature = "postgres", sea_orm(column_name = "updatedat"))]
   pub updated_at: Option<DateTimeUtc>,
 }
 
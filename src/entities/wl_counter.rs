@@ -3,11 +3,14 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use super::Id;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
-#[sea_orm(table_name = "wl_Counter")]
+#[cfg_attr(not(feature = "postgres"), sea_orm(table_name = "wl_Counter"))]
+#[cfg_attr(feature = "postgres", sea_orm(table_name = "wl_counter"))]
 pub struct Model {
   #[sea_orm(primary_key)]
-  pub id: u32,
+  pub id: Id,
   pub time: Option<i32>,
   pub reaction0: Option<i32>,
   pub reaction1: Option<i32>,
@@ -19,9 +22,11 @@ pub struct Model {
   pub reaction7: Option<i32>,
   pub reaction8: Option<i32>,
   pub url: String,
-  #[sea_orm(column_name = "createdAt")]
+  #[cfg_attr(not(feature = "postgres"), sea_orm(column_name = "createdAt"))]
+  #[cfg_attr(feature = "postgres", sea_orm(column_name = "createdat"))]
   pub created_at: Option<DateTimeUtc>,
-  #[sea_orm(column_name = "updatedAt")]
+  #[cfg_attr(not(feature = "postgres"), sea_orm(column_name = "updatedAt"))]
+  #[cfg_attr(feature = "postgres", sea_orm(column_name = "updatedat"))]
   pub updated_at: Option<DateTimeUtc>,
 }
 
@@ -3,11 +3,14 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use super::Id;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
-#[sea_orm(table_name = "wl_Users")]
+#[cfg_attr(not(feature = "postgres"), sea_orm(table_name = "wl_Users"))]
+#[cfg_attr(feature = "postgres", sea_orm(table_name = "wl_users"))]
 pub struct Model {
   #[sea_orm(primary_key)]
-  pub id: u32,
+  pub id: Id,
   pub display_name: String,
   pub email: String,
   pub password: String,
@@ -24,9 +27,11 @@ pub struct Model {
   pub qq: Option<String>,
   #[sea_orm(column_name = "2fa")]
   pub two_factor_auth: Option<String>,
-  #[sea_orm(column_name = "createdAt")]
+  #[cfg_attr(not(feature = "postgres"), sea_orm(column_name = "createdAt"))]
+  #[cfg_attr(feature = "postgres", sea_orm(column_name = "createdat"))]
   pub created_at: Option<DateTimeUtc>,
-  #[sea_orm(column_name = "updatedAt")]
+  #[cfg_attr(not(feature = "postgres"), sea_orm(column_name = "updatedAt"))]
+  #[cfg_attr(feature = "postgres", sea_orm(column_name = "updatedat"))]
   pub updated_at: Option<DateTimeUtc>,
 }
 
@@ -72,6 +72,14 @@ impl From<instant_akismet::Error> for AppError {
   }
 }
 
+#[cfg(feature = "redis")]
+impl From<redis::RedisError> for AppError {
+  fn from(err: redis::RedisError) -> Self {
+    tracing::error!("{:#?}", err);
+    AppError::Error
+  }
+}
+
 // impl ResponseError for AppError {
 //   fn status_code(&self) -> StatusCode {
 //     match self {
@@ -0,0 +1,42 @@
+//! Old page urls that resolve to the page they were moved to
+//!
+//! Aliases are stored in `wl_PageAlias` and kept in memory. An exact alias wins over a
+//! prefix alias, and the longest prefix wins among those.
+
+use std::sync::RwLock;
+
+use sea_orm::{DatabaseConnection, EntityTrait};
+
+use crate::{entities::wl_page_alias, error::AppError, helpers::url::relocate};
+
+#[derive(Debug)]
+pub struct PageAliases {
+  aliases: RwLock<Vec<wl_page_alias::Model>>,
+}
+
+impl PageAliases {
+  pub async fn load(conn: &DatabaseConnection) -> Result<Self, AppError> {
+    let aliases = PageAliases {
+      aliases: RwLock::new(Vec::new()),
+    };
+    aliases.reload(conn).await?;
+    Ok(aliases)
+  }
+
+  /// Reread the aliases after they were changed
+  pub async fn reload(&self, conn: &DatabaseConnection) -> Result<(), AppError> {
+    let mut aliases = wl_page_alias::Entity::find().all(conn).await?;
+    aliases.sort_by_key(|alias| (alias.prefix, std::cmp::Reverse(alias.source.len())));
+    *self.aliases.write().unwrap() = aliases;
+    Ok(())
+  }
+
+  /// The url a normalized url was moved to, or the url itself
+  pub fn resolve(&self, url: String) -> String {
+    let aliases = self.aliases.read().unwrap();
+    aliases
+      .iter()
+      .find_map(|alias| relocate(&url, &alias.source, &alias.target, alias.prefix))
+      .unwrap_or(url)
+  }
+}
@@ -1,11 +1,225 @@
-use helpers::time::utc_now;
+//! Avatars from Gravatar compatible services, keyed by the hash of the mail
 
-pub fn get_avatar(plain: &str) -> String {
-  let re = regex::Regex::new(r"^\d+@qq\.com$").unwrap();
-  if re.is_match(plain) {
-    let number = plain.split("@").next().unwrap();
-    format!("https://q1.qlogo.cn/g?b=qq&nk={}&s=100", number)
-  } else {
-    format!("https://api.multiavatar.com/{}.png", utc_now())
+use std::{
+  fs::{self, FileTimes},
+  io,
+  path::{Path, PathBuf},
+  sync::LazyLock,
+  time::{Duration, SystemTime},
+};
+
+use actix_web::web;
+use regex::Regex;
+
+use crate::error::AppError;
+
+static QQ_MAIL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\d+@qq\.com$").unwrap());
+
+/// Md5 of the trimmed, lowercased mail as Gravatar expects
+pub fn mail_hash(mail: &str) -> String {
+  helpers::hash::md5(mail.trim().to_lowercase().as_bytes())
+}
+
+pub fn is_hash(hash: &str) -> bool {
+  hash.len() == 32 && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+#[derive(Debug)]
+pub struct AvatarProvider {
+  base: String,
+  default: String,
+  /// Avatars are served from `/api/avatar/{hash}` when set
+  cache_url: Option<String>,
+}
+
+impl AvatarProvider {
+  /// `proxy` replaces the host of the provider, such as a Gravatar mirror
+  pub fn new(
+    provider: &str,
+    proxy: Option<String>,
+    default: String,
+    cache_url: Option<String>,
+  ) -> Self {
+    let base = match provider {
+      "cravatar" => "https://cravatar.cn/avatar",
+      "libravatar" => "https://seccdn.libravatar.org/avatar",
+      "gravatar" => "https://www.gravatar.com/avatar",
+      provider => {
+        tracing::warn!("Unknown avatar provider {provider}, using gravatar");
+        "https://www.gravatar.com/avatar"
+      }
+    };
+    let base = proxy
+      .filter(|proxy| !proxy.is_empty())
+      .unwrap_or(base.to_string());
+    AvatarProvider {
+      base: base.trim_end_matches('/').to_string(),
+      default,
+      cache_url: cache_url.map(|url| url.trim_end_matches('/').to_string()),
+    }
+  }
+
+  /// Where the provider serves the avatar of a hash
+  pub fn upstream(&self, hash: &str) -> String {
+    let url = format!("{}/{hash}", self.base);
+    reqwest::Url::parse_with_params(&url, [("d", self.default.as_str()), ("s", "100")])
+      .map(|url| url.to_string())
+      .unwrap_or(url)
+  }
+
+  /// Avatar url of a mail. QQ mails use the QQ avatar
+  pub fn get_avatar(&self, mail: &str) -> String {
+    if QQ_MAIL.is_match(mail) {
+      let number = mail.split('@').next().unwrap();
+      return format!("https://q1.qlogo.cn/g?b=qq&nk={}&s=100", number);
+    }
+    let hash = mail_hash(mail);
+    match &self.cache_url {
+      Some(cache_url) => format!("{cache_url}/{hash}"),
+      None => self.upstream(&hash),
+    }
+  }
+}
+
+/// Largest avatar accepted from the provider
+const MAX_AVATAR_BYTES: usize = 1024 * 1024;
+
+/// Avatar images cached on disk. Each hash is stored as the image and a `.type` file
+/// holding its content type. Reads bump the access time of the image, the least recently
+/// read avatars are removed once the cache grows past `max_bytes`
+#[derive(Debug)]
+pub struct AvatarCache {
+  dir: PathBuf,
+  ttl: Duration,
+  max_bytes: u64,
+  client: reqwest::Client,
+}
+
+impl AvatarCache {
+  pub fn new(dir: &str, ttl: Duration, max_bytes: u64) -> Result<Self, AppError> {
+    fs::create_dir_all(dir)?;
+    let client = reqwest::Client::builder()
+      .timeout(Duration::from_secs(10))
+      .build()
+      .map_err(|err| {
+        tracing::error!("{:#?}", err);
+        AppError::Error
+      })?;
+    Ok(AvatarCache {
+      dir: PathBuf::from(dir),
+      ttl,
+      max_bytes,
+      client,
+    })
+  }
+
+  pub fn ttl(&self) -> Duration {
+    self.ttl
+  }
+
+  /// Read a cached avatar with whether it has expired
+  pub async fn read(&self, hash: &str) -> Option<(Vec<u8>, String, bool)> {
+    let image = self.dir.join(hash);
+    let content_type = self.dir.join(format!("{hash}.type"));
+    let ttl = self.ttl;
+    web::block(move || {
+      let expired = fs::metadata(&image)
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| {
+          SystemTime::now()
+            .duration_since(modified)
+            .is_ok_and(|age| age > ttl)
+        })
+        .ok()?;
+      let bytes = fs::read(&image).ok()?;
+      // Set explicitly, filesystems are often mounted without access times
+      fs::File::options()
+        .write(true)
+        .open(&image)
+        .and_then(|file| file.set_times(FileTimes::new().set_accessed(SystemTime::now())))
+        .ok();
+      Some((bytes, fs::read_to_string(&content_type).ok()?, expired))
+    })
+    .await
+    .ok()
+    .flatten()
+  }
+
+  async fn write(&self, hash: &str, bytes: Vec<u8>, content_type: String) {
+    let dir = self.dir.clone();
+    let max_bytes = self.max_bytes;
+    let image = self.dir.join(hash);
+    let type_file = self.dir.join(format!("{hash}.type"));
+    let written = web::block(move || {
+      fs::write(&type_file, content_type)?;
+      fs::write(&image, bytes)?;
+      evict(&dir, max_bytes)
+    })
+    .await;
+    if !matches!(written, Ok(Ok(()))) {
+      tracing::warn!("Failed to cache avatar {hash}");
+    }
+  }
+
+  async fn download(&self, url: &str) -> Option<(Vec<u8>, String)> {
+    let mut response = self.client.get(url).send().await.ok()?;
+    if !response.status().is_success()
+      || response
+        .content_length()
+        .is_some_and(|length| length > MAX_AVATAR_BYTES as u64)
+    {
+      return None;
+    }
+    let content_type = response
+      .headers()
+      .get(reqwest::header::CONTENT_TYPE)
+      .and_then(|value| value.to_str().ok())
+      .filter(|value| value.starts_with("image/"))?
+      .to_string();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await.ok()? {
+      bytes.extend_from_slice(&chunk);
+      if bytes.len() > MAX_AVATAR_BYTES {
+        return None;
+      }
+    }
+    Some((bytes, content_type))
+  }
+
+  /// Download an avatar into the cache
+  pub async fn fetch(&self, provider: &AvatarProvider, hash: &str) -> Option<(Vec<u8>, String)> {
+    let (bytes, content_type) = self.download(&provider.upstream(hash)).await?;
+    self.write(hash, bytes.clone(), content_type.clone()).await;
+    Some((bytes, content_type))
+  }
+}
+
+/// Remove the least recently read avatars until the cache fits in `max_bytes`
+fn evict(dir: &Path, max_bytes: u64) -> io::Result<()> {
+  let mut images = Vec::new();
+  let mut total = 0;
+  for entry in fs::read_dir(dir)? {
+    let entry = entry?;
+    let name = entry.file_name().to_string_lossy().to_string();
+    if !is_hash(&name) {
+      continue;
+    }
+    let metadata = entry.metadata()?;
+    total += metadata.len();
+    let accessed = metadata.accessed().or_else(|_| metadata.modified())?;
+    images.push((accessed, metadata.len(), entry.path()));
+  }
+  if total <= max_bytes {
+    return Ok(());
+  }
+  images.sort_by_key(|(accessed, ..)| *accessed);
+  for (_, len, image) in images {
+    if total <= max_bytes {
+      break;
+    }
+    fs::remove_file(&image)?;
+    fs::remove_file(image.with_extension("type")).ok();
+    total -= len;
   }
+  Ok(())
 }
@@ -0,0 +1,86 @@
+//! Commenters who may not comment or vote
+//!
+//! Bans are stored in `wl_Ban` and kept in memory. A ban names a registered user by id,
+//! or an anonymous identity by mail or nick, both compared case-insensitively. A shadowban
+//! keeps the comments of the commenter as spam while they still see them published.
+
+use std::sync::RwLock;
+
+use sea_orm::{DatabaseConnection, EntityTrait};
+
+use crate::{
+  entities::{wl_ban, wl_users},
+  error::AppError,
+};
+
+/// Kinds of identity a ban can name
+pub const BAN_KINDS: [&str; 3] = ["user", "mail", "nick"];
+
+/// How a commenter is banned, ordered by severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Ban {
+  Shadow,
+  Outright,
+}
+
+#[derive(Debug)]
+pub struct Bans {
+  bans: RwLock<Vec<wl_ban::Model>>,
+}
+
+impl Bans {
+  pub async fn load(conn: &DatabaseConnection) -> Result<Self, AppError> {
+    let bans = Bans {
+      bans: RwLock::new(Vec::new()),
+    };
+    bans.reload(conn).await?;
+    Ok(bans)
+  }
+
+  /// Reread the bans after they were changed
+  pub async fn reload(&self, conn: &DatabaseConnection) -> Result<(), AppError> {
+    *self.bans.write().unwrap() = wl_ban::Entity::find().all(conn).await?;
+    Ok(())
+  }
+
+  pub fn has_shadowbans(&self) -> bool {
+    self.bans.read().unwrap().iter().any(|ban| ban.shadow)
+  }
+
+  /// The most severe ban on any of the identities of a commenter
+  pub fn check(&self, user_id: Option<i32>, mail: Option<&str>, nick: Option<&str>) -> Option<Ban> {
+    let user_id = user_id.map(|user_id| user_id.to_string());
+    let bans = self.bans.read().unwrap();
+    bans
+      .iter()
+      .filter(|ban| {
+        let identity = match ban.kind.as_str() {
+          "user" => user_id.as_deref(),
+          "mail" => mail,
+          "nick" => nick,
+          _ => None,
+        };
+        identity.is_some_and(|identity| identity.trim().eq_ignore_ascii_case(&ban.value))
+      })
+      .map(|ban| {
+        if ban.shadow {
+          Ban::Shadow
+        } else {
+          Ban::Outright
+        }
+      })
+      .max()
+  }
+
+  /// Bans on a registered user, administrators are never banned
+  pub fn check_user(&self, user: &wl_users::Model) -> Option<Ban> {
+    if user.user_type == "administrator" {
+      return None;
+    }
+    self.check(
+      Some(user.id as i32),
+      Some(&user.email),
+      Some(&user.display_name),
+    )
+  }
+}
@@ -0,0 +1,246 @@
+//! Naive Bayes spam classifier trained from moderation decisions
+
+use std::{
+  collections::{HashMap, HashSet},
+  sync::RwLock,
+};
+
+use sea_orm::{
+  sea_query::{Expr, Func, OnConflict},
+  ActiveValue::Set,
+  DatabaseConnection, EntityTrait, QuerySelect, TransactionTrait,
+};
+
+use crate::{
+  entities::{wl_bayes, wl_bayes_trained, Id},
+  error::AppError,
+  helpers::link::{domain, extract_urls},
+};
+
+/// Row holding the number of trained documents per class
+const DOCUMENTS: &str = "__documents__";
+/// Number of most significant tokens combined into the score
+const SIGNIFICANT_TOKENS: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Class {
+  Spam,
+  Ham,
+}
+
+impl Class {
+  /// Maps a comment status to the class it trains, `waiting` trains nothing
+  pub fn from_status(status: &str) -> Option<Class> {
+    match status {
+      "spam" => Some(Class::Spam),
+      "approved" => Some(Class::Ham),
+      _ => None,
+    }
+  }
+
+  fn name(&self) -> &'static str {
+    match self {
+      Class::Spam => "spam",
+      Class::Ham => "ham",
+    }
+  }
+
+  fn from_name(name: &str) -> Option<Class> {
+    match name {
+      "spam" => Some(Class::Spam),
+      "ham" => Some(Class::Ham),
+      _ => None,
+    }
+  }
+}
+
+/// Split a comment into word, link and metadata tokens
+pub fn tokenize(nick: &str, mail: &str, link: &str, comment: &str) -> HashSet<String> {
+  let mut tokens = HashSet::new();
+  for piece in comment
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|piece| !piece.is_empty())
+  {
+    if piece.is_ascii() {
+      if (2..=30).contains(&piece.len()) {
+        tokens.insert(piece.to_lowercase());
+      }
+    } else {
+      // Scripts without spaces are tokenized as character bigrams
+      let chars = piece.chars().collect::<Vec<_>>();
+      if chars.len() == 1 {
+        tokens.insert(piece.to_string());
+      }
+      for pair in chars.windows(2) {
+        tokens.insert(pair.iter().collect());
+      }
+    }
+  }
+  let links = extract_urls(comment);
+  for url in &links {
+    if let Some(domain) = domain(url) {
+      tokens.insert(format!("url:{domain}"));
+    }
+  }
+  tokens.insert(format!("links:{}", links.len().min(3)));
+  if let Some(domain) = mail
+    .rsplit_once('@')
+    .map(|(_, domain)| domain.to_lowercase())
+  {
+    tokens.insert(format!("mail:{domain}"));
+  }
+  match domain(link) {
+    Some(domain) => tokens.insert(format!("link:{domain}")),
+    None => tokens.insert("link:none".to_string()),
+  };
+  if !nick.is_empty() {
+    tokens.insert(format!("nick:{}", nick.to_lowercase()));
+  }
+  let length = comment.chars().count();
+  tokens.insert(format!("length:{}", usize::BITS - length.leading_zeros()));
+  tokens
+}
+
+#[derive(Debug, Default)]
+struct Model {
+  tokens: HashMap<String, (i32, i32)>,
+  spam: i32,
+  ham: i32,
+}
+
+#[derive(Debug)]
+pub struct BayesClassifier {
+  model: RwLock<Model>,
+  min_documents: i32,
+}
+
+impl BayesClassifier {
+  /// Load the persisted model
+  pub async fn load(conn: &DatabaseConnection, min_documents: i32) -> Result<Self, AppError> {
+    let mut model = Model::default();
+    for row in wl_bayes::Entity::find().all(conn).await? {
+      if row.token == DOCUMENTS {
+        model.spam = row.spam;
+        model.ham = row.ham;
+      } else {
+        model.tokens.insert(row.token, (row.spam, row.ham));
+      }
+    }
+    tracing::info!(
+      "Loaded spam classifier with {} spam and {} ham documents",
+      model.spam,
+      model.ham
+    );
+    Ok(BayesClassifier {
+      model: RwLock::new(model),
+      min_documents,
+    })
+  }
+
+  /// Probability that the tokens are spam, `None` until both classes are trained enough
+  pub fn score(&self, tokens: &HashSet<String>) -> Option<f64> {
+    let model = self.model.read().unwrap();
+    if model.spam < self.min_documents || model.ham < self.min_documents {
+      return None;
+    }
+    let mut probabilities = tokens
+      .iter()
+      .filter_map(|token| model.tokens.get(token))
+      // Tokens whose every document was unlearned carry no evidence
+      .filter(|&&(spam, ham)| spam + ham > 0)
+      .map(|&(spam, ham)| {
+        let spam_freq = spam as f64 / model.spam as f64;
+        let ham_freq = ham as f64 / model.ham as f64;
+        let p = spam_freq / (spam_freq + ham_freq);
+        // Robinson's smoothing towards 0.5 for rarely seen tokens
+        let n = (spam + ham) as f64;
+        ((0.5 + n * p) / (1.0 + n)).clamp(0.01, 0.99)
+      })
+      .collect::<Vec<_>>();
+    if probabilities.is_empty() {
+      return Some(0.5);
+    }
+    probabilities.sort_by(|a, b| (b - 0.5).abs().total_cmp(&(a - 0.5).abs()));
+    probabilities.truncate(SIGNIFICANT_TOKENS);
+    let (log_spam, log_ham) = probabilities
+      .iter()
+      .fold((0.0, 0.0), |(s, h), p| (s + p.ln(), h + (1.0 - p).ln()));
+    Some(1.0 / (1.0 + (log_ham - log_spam).exp()))
+  }
+
+  /// Learn a moderation decision. The class a comment was trained as is remembered, so
+  /// that a reclassification unlearns exactly what was learned before and nothing else
+  pub async fn train(
+    &self,
+    conn: &DatabaseConnection,
+    comment_id: Id,
+    tokens: &HashSet<String>,
+    to: Class,
+  ) -> Result<(), AppError> {
+    let txn = conn.begin().await?;
+    let from = wl_bayes_trained::Entity::find_by_id(comment_id)
+      .lock_exclusive()
+      .one(&txn)
+      .await?
+      .and_then(|trained| Class::from_name(&trained.class));
+    if from == Some(to) {
+      return Ok(());
+    }
+    let delta = |class: Class| -> i32 {
+      let mut delta = 0;
+      if from == Some(class) {
+        delta -= 1;
+      }
+      if to == class {
+        delta += 1;
+      }
+      delta
+    };
+    let (spam, ham) = (delta(Class::Spam), delta(Class::Ham));
+    let clamped = |column: wl_bayes::Column, delta: i32| {
+      Func::greatest([
+        Expr::col((wl_bayes::Entity, column)).add(delta),
+        Expr::value(0),
+      ])
+    };
+    for token in tokens.iter().map(String::as_str).chain([DOCUMENTS]) {
+      wl_bayes::Entity::insert(wl_bayes::ActiveModel {
+        token: Set(token.to_string()),
+        spam: Set(spam.max(0)),
+        ham: Set(ham.max(0)),
+      })
+      .on_conflict(
+        OnConflict::column(wl_bayes::Column::Token)
+          .value(
+            wl_bayes::Column::Spam,
+            clamped(wl_bayes::Column::Spam, spam),
+          )
+          .value(wl_bayes::Column::Ham, clamped(wl_bayes::Column::Ham, ham))
+          .to_owned(),
+      )
+      .exec(&txn)
+      .await?;
+    }
+    wl_bayes_trained::Entity::insert(wl_bayes_trained::ActiveModel {
+      comment_id: Set(comment_id),
+      class: Set(to.name().to_string()),
+    })
+    .on_conflict(
+      OnConflict::column(wl_bayes_trained::Column::CommentId)
+        .update_column(wl_bayes_trained::Column::Class)
+        .to_owned(),
+    )
+    .exec(&txn)
+    .await?;
+    txn.commit().await?;
+    let mut model = self.model.write().unwrap();
+    model.spam = (model.spam + spam).max(0);
+    model.ham = (model.ham + ham).max(0);
+    for token in tokens {
+      let entry = model.tokens.entry(token.clone()).or_insert((0, 0));
+      entry.0 = (entry.0 + spam).max(0);
+      entry.1 = (entry.1 + ham).max(0);
+    }
+    Ok(())
+  }
+}
@@ -4,7 +4,7 @@ use lettre::{
 };
 use strfmt::strfmt;
 
-use crate::{config::EnvConfig, locales::get_translation};
+use crate::{config::EnvConfig, entities::Id, locales::get_translation};
 
 struct SmtpConfig {
   host: &'static str,
@@ -44,7 +44,7 @@ impl SmtpService {
 pub struct CommentNotification<'a> {
   pub sender_name: String,
   pub sender_email: String,
-  pub comment_id: u32,
+  pub comment_id: Id,
   pub comment: String,
   pub url: String,
   pub notify_type: NotifyType,
@@ -55,6 +55,8 @@ pub enum NotifyType {
   Notify,
   NewComment,
   _ReplyComment,
+  /// The commenter mentioned the given email
+  Mention(String),
 }
 
 pub fn send_email_notification(notification: CommentNotification) {
@@ -98,6 +100,16 @@ pub fn send_email_notification(notification: CommentNotification) {
       to = &notification.sender_email;
       reply_to = &author_email;
     }
+    NotifyType::Mention(ref email) => {
+      let subject_template = get_translation(lang, "MAIL_SUBJECT_MENTION");
+      let body_template = get_translation(lang, "MAIL_TEMPLATE_MENTION");
+      subject = strfmt!(&subject_template, site_name => site_name.clone(), nick => notification.sender_name.clone()).unwrap();
+      body =
+        strfmt!(&body_template, site_url=> site_url, site_name=>site_name, nick=>notification.sender_name, comment=>notification.comment, post_url=>post_url)
+          .unwrap();
+      to = email;
+      reply_to = &author_email;
+    }
     NotifyType::Notify => {
       let subject_template = get_translation(lang, "Registration Confirm Mail");
       let body_template = get_translation(lang, "confirm registration");
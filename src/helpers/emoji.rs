@@ -0,0 +1,67 @@
+//! Waline emoji presets for `:shortcode:` expansion
+
+use std::{collections::HashMap, time::Duration};
+
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct PresetInfo {
+  #[serde(default)]
+  prefix: String,
+  #[serde(default = "default_type")]
+  r#type: String,
+  items: Vec<String>,
+}
+
+fn default_type() -> String {
+  "png".to_string()
+}
+
+/// Image emojis by shortcode, built from preset folders such as
+/// `//unpkg.com/@waline/emojis@1.2.0/weibo`
+#[derive(Debug, Default)]
+pub struct EmojiPresets {
+  pub bases: Vec<String>,
+  pub items: HashMap<String, String>,
+}
+
+impl EmojiPresets {
+  /// Fetch the `info.json` of every preset, a preset that fails to load is skipped
+  pub async fn load(presets: &[String]) -> Self {
+    let mut emojis = EmojiPresets::default();
+    let client = match reqwest::Client::builder()
+      .timeout(Duration::from_secs(10))
+      .build()
+    {
+      Ok(client) => client,
+      Err(_) => return emojis,
+    };
+    for base in presets.iter().filter(|base| !base.is_empty()) {
+      let base = base.trim_end_matches('/');
+      let url = if base.starts_with("//") {
+        format!("https:{base}/info.json")
+      } else {
+        format!("{base}/info.json")
+      };
+      let info = match client.get(&url).send().await {
+        Ok(response) => response.text().await.ok(),
+        Err(_) => None,
+      }
+      .and_then(|text| serde_json::from_str::<PresetInfo>(&text).ok());
+      let Some(info) = info else {
+        tracing::warn!("Failed to load emoji preset {url}");
+        continue;
+      };
+      for item in info.items {
+        let key = format!("{}{item}", info.prefix);
+        let src = format!("{base}/{key}.{}", info.r#type);
+        emojis.items.insert(key, src);
+      }
+      emojis.bases.push(format!("{base}/"));
+    }
+    if !emojis.items.is_empty() {
+      tracing::info!("Loaded {} preset emojis", emojis.items.len());
+    }
+    emojis
+  }
+}
@@ -0,0 +1,125 @@
+//! Forbidden word rules checked against new comments
+//!
+//! Rules are stored in `wl_ForbiddenWord` and compiled into memory. Words from
+//! `FORBIDDEN_WORDS` are kept as case-insensitive rules that mark comments as spam.
+
+use std::sync::RwLock;
+
+use regex::{Regex, RegexBuilder};
+use sea_orm::{DatabaseConnection, EntityTrait};
+
+use crate::{entities::wl_forbidden_word, error::AppError};
+
+const MASK: &str = "***";
+
+/// What happens to a comment matching a rule, ordered by severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+  Mask,
+  Hold,
+  Spam,
+  Reject,
+}
+
+impl Action {
+  pub fn parse(action: &str) -> Option<Action> {
+    match action {
+      "mask" => Some(Action::Mask),
+      "hold" => Some(Action::Hold),
+      "spam" => Some(Action::Spam),
+      "reject" => Some(Action::Reject),
+      _ => None,
+    }
+  }
+}
+
+pub fn compile(
+  pattern: &str,
+  regex: bool,
+  whole_word: bool,
+  case_sensitive: bool,
+) -> Result<Regex, regex::Error> {
+  let pattern = if regex {
+    pattern.to_string()
+  } else {
+    regex::escape(pattern)
+  };
+  let pattern = if whole_word {
+    format!(r"\b(?:{pattern})\b")
+  } else {
+    pattern
+  };
+  RegexBuilder::new(&pattern)
+    .case_insensitive(!case_sensitive)
+    .build()
+}
+
+#[derive(Debug)]
+struct Rule {
+  regex: Regex,
+  action: Action,
+}
+
+#[derive(Debug)]
+pub struct WordFilter {
+  rules: RwLock<Vec<Rule>>,
+  env_rules: Vec<Rule>,
+}
+
+impl WordFilter {
+  pub async fn load(conn: &DatabaseConnection, env_words: &[String]) -> Result<Self, AppError> {
+    let env_rules = env_words
+      .iter()
+      .filter(|word| !word.is_empty())
+      .filter_map(|word| compile(word, false, false, false).ok())
+      .map(|regex| Rule {
+        regex,
+        action: Action::Spam,
+      })
+      .collect();
+    let filter = WordFilter {
+      rules: RwLock::new(Vec::new()),
+      env_rules,
+    };
+    filter.reload(conn).await?;
+    Ok(filter)
+  }
+
+  /// Recompile the rules after they were changed
+  pub async fn reload(&self, conn: &DatabaseConnection) -> Result<(), AppError> {
+    let mut rules = Vec::new();
+    for model in wl_forbidden_word::Entity::find().all(conn).await? {
+      let regex = compile(
+        &model.pattern,
+        model.regex,
+        model.whole_word,
+        model.case_sensitive,
+      );
+      match (regex, Action::parse(&model.action)) {
+        (Ok(regex), Some(action)) => rules.push(Rule { regex, action }),
+        _ => tracing::warn!("Skipping invalid forbidden word rule {}", model.id),
+      }
+    }
+    *self.rules.write().unwrap() = rules;
+    Ok(())
+  }
+
+  /// Check the fields against every rule, masking matches in place. Returns the most
+  /// severe action among the matched rules
+  pub fn apply(&self, fields: &mut [&mut String]) -> Option<Action> {
+    let rules = self.rules.read().unwrap();
+    let mut verdict = None;
+    for rule in rules.iter().chain(self.env_rules.iter()) {
+      for field in fields.iter_mut() {
+        if !rule.regex.is_match(field) {
+          continue;
+        }
+        verdict = verdict.max(Some(rule.action));
+        if rule.action == Action::Mask {
+          **field = rule.regex.replace_all(field, MASK).into_owned();
+        }
+      }
+    }
+    verdict
+  }
+}
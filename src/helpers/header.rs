@@ -1,4 +1,10 @@
-use actix_web::{http::header::HeaderValue, HttpRequest};
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::{
+  http::header::{HeaderMap, HeaderValue, FORWARDED},
+  HttpRequest,
+};
+use ipnet::IpNet;
 
 use crate::error::AppError;
 
@@ -26,18 +32,104 @@ pub fn extract_token(req: &HttpRequest) -> Result<String, AppError> {
   Ok(auth_header[7..].to_string()) // Skip "Bearer " prefix
 }
 
-pub fn extract_ip(req: &HttpRequest) -> String {
-  if let Some(h) = req.headers().get("X-Forwarded-For") {
-    let s = h.to_str().unwrap_or("0.0.0.0").to_string();
-    s
-  } else if let Some(h) = req.headers().get("X-Real-IP") {
-    let s = h.to_str().ok().unwrap_or("0.0.0.0").to_string();
-    s
-  } else {
-    req
-      .peer_addr()
-      .map(|s| s.ip().to_string())
-      .unwrap_or_default()
+/// Parse a comma separated list of CIDRs or plain addresses, invalid entries are skipped
+pub fn parse_trusted_proxies(proxies: &[String]) -> Vec<IpNet> {
+  proxies
+    .iter()
+    .filter(|proxy| !proxy.is_empty())
+    .filter_map(|proxy| {
+      let net = proxy
+        .parse::<IpNet>()
+        .ok()
+        .or_else(|| proxy.parse::<IpAddr>().ok().map(IpNet::from));
+      if net.is_none() {
+        tracing::warn!("Ignoring invalid trusted proxy {proxy}");
+      }
+      net
+    })
+    .collect()
+}
+
+/// Parse a single hop of a forwarding chain, ports and IPv6 brackets are stripped
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+  let hop = hop.trim().trim_matches('"');
+  if let Ok(ip) = hop.parse::<IpAddr>() {
+    return Some(ip.to_canonical());
+  }
+  if let Ok(addr) = hop.parse::<SocketAddr>() {
+    return Some(addr.ip().to_canonical());
+  }
+  hop
+    .strip_prefix('[')
+    .and_then(|hop| hop.split_once(']'))
+    .and_then(|(ip, _)| ip.parse::<IpAddr>().ok())
+    .map(|ip| ip.to_canonical())
+}
+
+/// Collect the `for` parameters of RFC 7239 `Forwarded` headers
+fn forwarded_chain(headers: &HeaderMap) -> Vec<String> {
+  headers
+    .get_all(FORWARDED)
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .filter_map(|element| {
+      element.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        key
+          .trim()
+          .eq_ignore_ascii_case("for")
+          .then(|| value.trim().to_string())
+      })
+    })
+    .collect()
+}
+
+fn x_forwarded_for_chain(headers: &HeaderMap) -> Vec<String> {
+  headers
+    .get_all("X-Forwarded-For")
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .map(|hop| hop.trim().to_string())
+    .collect()
+}
+
+/// Resolve the client address. Forwarding headers are only honoured when the peer is a
+/// trusted proxy, and the chain is walked right-to-left until the first untrusted hop
+pub fn resolve_client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> IpAddr {
+  let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+  let peer = peer.to_canonical();
+  if !is_trusted(&peer) {
+    return peer;
+  }
+  let mut chain = forwarded_chain(headers);
+  if chain.is_empty() {
+    chain = x_forwarded_for_chain(headers);
+  }
+  if chain.is_empty() {
+    if let Some(real_ip) = headers.get("X-Real-IP").and_then(|h| h.to_str().ok()) {
+      chain.push(real_ip.to_string());
+    }
+  }
+  let mut client = peer;
+  for hop in chain.iter().rev() {
+    match parse_hop(hop) {
+      Some(ip) => {
+        client = ip;
+        if !is_trusted(&ip) {
+          break;
+        }
+      }
+      // Obfuscated or unknown identifiers end the chain at the last known hop
+      None => break,
+    }
+  }
+  client
+}
+
+pub fn extract_ip(req: &HttpRequest, trusted_proxies: &[IpNet]) -> String {
+  match req.peer_addr() {
+    Some(peer) => resolve_client_ip(peer.ip(), req.headers(), trusted_proxies).to_string(),
+    None => String::new(),
   }
 }
 
@@ -0,0 +1,62 @@
+//! Syntax highlighting of fenced code blocks with class-based css
+
+use syntect::{
+  highlighting::ThemeSet,
+  html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator},
+  parsing::SyntaxSet,
+  util::LinesWithEndings,
+};
+
+/// Prefix of every highlighting class, only these classes pass the sanitizer
+pub const CLASS_PREFIX: &str = "hl-";
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed {
+  prefix: CLASS_PREFIX,
+};
+
+#[derive(Debug)]
+pub struct Highlighter {
+  syntaxes: SyntaxSet,
+  css: String,
+}
+
+impl Highlighter {
+  /// `theme` is one of syntect's default themes, such as `InspiredGitHub` or
+  /// `base16-ocean.dark`. Returns `None` for an unknown theme
+  pub fn new(theme: &str) -> Option<Self> {
+    let themes = ThemeSet::load_defaults();
+    let Some(theme) = themes.themes.get(theme) else {
+      tracing::warn!(
+        "Unknown highlight theme {theme}, expected one of {}",
+        themes.themes.keys().cloned().collect::<Vec<_>>().join(", ")
+      );
+      return None;
+    };
+    let css = css_for_theme_with_class_style(theme, CLASS_STYLE).ok()?;
+    Some(Highlighter {
+      syntaxes: SyntaxSet::load_defaults_newlines(),
+      css,
+    })
+  }
+
+  /// Stylesheet for the highlighted code
+  pub fn css(&self) -> &str {
+    &self.css
+  }
+
+  /// Highlight code written in `lang`, `None` when the language is unknown
+  pub fn highlight(&self, code: &str, lang: &str) -> Option<String> {
+    let syntax = self.syntaxes.find_syntax_by_token(lang)?;
+    let mut generator =
+      ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntaxes, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+      generator
+        .parse_html_for_line_which_includes_newline(line)
+        .ok()?;
+    }
+    Some(format!(
+      r#"<pre class="{CLASS_PREFIX}code"><code>{}</code></pre>"#,
+      generator.finalize()
+    ))
+  }
+}
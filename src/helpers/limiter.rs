@@ -0,0 +1,153 @@
+//! Token bucket rate limiter with per-route policies
+
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+use actix_web::{HttpResponse, HttpResponseBuilder};
+use ipnet::Ipv6Net;
+
+use crate::helpers::store::Store;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Policy {
+  Comment,
+  Login,
+  Register,
+  Like,
+  Counter,
+  Preview,
+  Report,
+  Avatar,
+}
+
+/// `count` requests are allowed per `seconds`, bursting up to `count`
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+  pub count: u32,
+  pub seconds: u64,
+}
+
+impl Quota {
+  /// Parse a quota in the form of `count/seconds`, `0` disables the limit
+  pub fn parse(value: &str) -> Option<Option<Quota>> {
+    let value = value.trim();
+    if value == "0" {
+      return Some(None);
+    }
+    let (count, seconds) = value.split_once('/')?;
+    let count = count.trim().parse::<u32>().ok()?;
+    let seconds = seconds.trim().parse::<u64>().ok()?;
+    if count == 0 || seconds == 0 {
+      return Some(None);
+    }
+    Some(Some(Quota { count, seconds }))
+  }
+
+  pub fn rate(&self) -> f64 {
+    self.count as f64 / self.seconds as f64
+  }
+}
+
+/// Outcome of a rate limit check, carrying the values for the `RateLimit-*` headers
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+  pub allowed: bool,
+  quota: Option<Quota>,
+  remaining: u32,
+  reset: u64,
+}
+
+impl RateLimit {
+  pub fn unlimited() -> Self {
+    RateLimit {
+      allowed: true,
+      quota: None,
+      remaining: 0,
+      reset: 0,
+    }
+  }
+
+  /// Start an `200 OK` response with the rate limit headers attached
+  pub fn response(&self) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::Ok();
+    if let Some(quota) = self.quota {
+      builder
+        .insert_header(("RateLimit-Limit", quota.count.to_string()))
+        .insert_header(("RateLimit-Remaining", self.remaining.to_string()))
+        .insert_header(("RateLimit-Reset", self.reset.to_string()))
+        .insert_header((
+          "RateLimit-Policy",
+          format!("{};w={}", quota.count, quota.seconds),
+        ));
+      if !self.allowed {
+        builder.insert_header(("Retry-After", self.reset.to_string()));
+      }
+    }
+    builder
+  }
+}
+
+/// Group IPv6 clients by their /64 prefix, a single host usually owns the whole prefix
+pub fn client_key(client_ip: &str) -> String {
+  match client_ip.parse::<IpAddr>() {
+    Ok(IpAddr::V6(ip)) => Ipv6Net::new(ip, 64)
+      .map(|net| net.trunc().to_string())
+      .unwrap_or_else(|_| ip.to_string()),
+    Ok(ip) => ip.to_string(),
+    Err(_) => client_ip.to_string(),
+  }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+  quotas: HashMap<Policy, Quota>,
+  spam_window: Duration,
+  store: Store,
+}
+
+impl RateLimiter {
+  pub fn new(quotas: HashMap<Policy, Quota>, spam_window: Duration, store: Store) -> Self {
+    RateLimiter {
+      quotas,
+      spam_window,
+      store,
+    }
+  }
+
+  /// Take a token from the client's bucket for the given policy, failing open when the
+  /// store is unavailable
+  pub async fn check(&self, policy: Policy, client_ip: &str) -> RateLimit {
+    let quota = match self.quotas.get(&policy) {
+      Some(quota) => *quota,
+      None => return RateLimit::unlimited(),
+    };
+    let key = format!("limit:{policy:?}:{}", client_key(client_ip));
+    let (allowed, tokens) = match self.store.take_token(&key, quota).await {
+      Ok(result) => result,
+      Err(_) => return RateLimit::unlimited(),
+    };
+    let reset = if allowed {
+      (quota.count as f64 - tokens) / quota.rate()
+    } else {
+      (1.0 - tokens) / quota.rate()
+    };
+    RateLimit {
+      allowed,
+      quota: Some(quota),
+      remaining: tokens.floor() as u32,
+      reset: reset.ceil() as u64,
+    }
+  }
+
+  pub async fn record_spam(&self, client_ip: &str) {
+    let key = format!("spam:{}", client_key(client_ip));
+    self.store.incr(&key, self.spam_window).await.ok();
+  }
+
+  pub async fn spam_count(&self, client_ip: &str) -> usize {
+    let key = format!("spam:{}", client_key(client_ip));
+    match self.store.get(&key).await {
+      Ok(Some(count)) => count.parse().unwrap_or(0),
+      _ => 0,
+    }
+  }
+}
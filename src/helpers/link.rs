@@ -0,0 +1,93 @@
+//! Link-spam heuristics
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+static URL: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r#"(?i)https?://[^\s<>"')\]]+"#).unwrap());
+
+/// Host of a url without `www.`, lowercased
+pub fn domain(url: &str) -> Option<String> {
+  let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+  let host = rest.split(['/', '?', '#']).next()?.rsplit('@').next()?;
+  let host = host.split(':').next()?.trim_start_matches("www.");
+  (!host.is_empty()).then(|| host.to_lowercase())
+}
+
+/// Urls written in a comment, whether as markdown, html or bare links
+pub fn extract_urls(comment: &str) -> Vec<&str> {
+  URL.find_iter(comment).map(|url| url.as_str()).collect()
+}
+
+/// Whether a commenter link is safe to put in an `href`. Links without a scheme are
+/// accepted, anything but http(s) like `javascript:` or `data:` is not
+pub fn is_safe_link(link: &str) -> bool {
+  // Browsers ignore whitespace and control characters inside a scheme
+  let link = link
+    .chars()
+    .filter(|c| !c.is_whitespace() && !c.is_control())
+    .collect::<String>();
+  let head = link.split(['/', '?', '#']).next().unwrap_or_default();
+  let Some((scheme, rest)) = head.split_once(':') else {
+    return true;
+  };
+  if scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https") {
+    return true;
+  }
+  // A host with a port such as `example.com:8080`
+  (scheme.contains('.') || scheme.eq_ignore_ascii_case("localhost"))
+    && !rest.is_empty()
+    && rest.chars().all(|c| c.is_ascii_digit())
+}
+
+#[derive(Debug)]
+pub struct LinkPolicy {
+  max_links: usize,
+  blocked_domains: Vec<String>,
+}
+
+impl LinkPolicy {
+  /// `max_links` of `0` allows any number of links
+  pub fn new(max_links: usize, blocked_domains: &[String]) -> Self {
+    LinkPolicy {
+      max_links,
+      blocked_domains: blocked_domains
+        .iter()
+        .map(|domain| domain.trim_start_matches("www.").to_lowercase())
+        .filter(|domain| !domain.is_empty())
+        .collect(),
+    }
+  }
+
+  /// Blocked domains also cover their subdomains
+  fn is_blocked(&self, url: &str) -> bool {
+    let Some(domain) = self::domain(url) else {
+      return false;
+    };
+    self.blocked_domains.iter().any(|blocked| {
+      domain == *blocked
+        || domain
+          .strip_suffix(blocked.as_str())
+          .is_some_and(|sub| sub.ends_with('.'))
+    })
+  }
+
+  /// Whether the links of a comment look like spam
+  pub fn is_spam(&self, comment: &str, link: &str) -> bool {
+    let urls = extract_urls(comment);
+    if self.max_links > 0 && urls.len() > self.max_links {
+      tracing::info!("Comment has {} links", urls.len());
+      return true;
+    }
+    if urls
+      .into_iter()
+      .chain((!link.is_empty()).then_some(link))
+      .any(|url| self.is_blocked(url))
+    {
+      tracing::info!("Comment links to a blocked domain");
+      return true;
+    }
+    false
+  }
+}
@@ -1,13 +1,564 @@
-use pulldown_cmark::{self, Event};
-
-/// Render markdown to html
-pub fn render_md_to_html(markdown: &str) -> String {
-  let parser = pulldown_cmark::Parser::new(markdown);
-  let parser = parser.map(|event| match event {
-    Event::SoftBreak => Event::HardBreak,
-    _ => event,
-  });
-  let mut html_output = String::new();
-  pulldown_cmark::html::push_html(&mut html_output, parser);
-  html_output
+use std::{collections::HashSet, sync::LazyLock};
+
+use ammonia::Builder;
+use latex2mathml::{latex_to_mathml, DisplayStyle};
+use pulldown_cmark::{
+  self, CodeBlockKind, CowStr, Event, LinkType, Options, Tag, TagEnd, TextMergeStream,
+};
+use regex::{Captures, Regex};
+
+use crate::helpers::{
+  emoji::EmojiPresets,
+  highlight::{Highlighter, CLASS_PREFIX},
+  link::domain,
+};
+
+/// Markdown features enabled through `MARKDOWN_EXTENSIONS`, `MARKDOWN_HTML`,
+/// `MARKDOWN_IMAGE` and `MATH_RENDERER`
+#[derive(Debug, Clone)]
+pub struct MarkdownOptions {
+  pub options: Options,
+  pub emoji: bool,
+  pub autolink: bool,
+  pub html: bool,
+  pub image: bool,
+  pub math: bool,
+}
+
+impl MarkdownOptions {
+  pub fn new(extensions: &[String], html: bool, image: bool, math: bool) -> Self {
+    let mut markdown = MarkdownOptions {
+      options: Options::empty(),
+      emoji: false,
+      autolink: false,
+      html,
+      image,
+      math,
+    };
+    if math {
+      markdown.options.insert(Options::ENABLE_MATH);
+    }
+    for extension in extensions {
+      match extension.trim() {
+        "table" => markdown.options.insert(Options::ENABLE_TABLES),
+        "strikethrough" => markdown.options.insert(Options::ENABLE_STRIKETHROUGH),
+        "tasklist" => markdown.options.insert(Options::ENABLE_TASKLISTS),
+        "footnote" => markdown.options.insert(Options::ENABLE_FOOTNOTES),
+        "emoji" => markdown.emoji = true,
+        "autolink" => markdown.autolink = true,
+        "" => {}
+        extension => tracing::warn!("Ignoring unknown markdown extension {extension}"),
+      }
+    }
+    markdown
+  }
+}
+
+/// Footnote ids are prefixed so that they cannot clobber other ids on the page
+const FOOTNOTE_PREFIX: &str = "fn-";
+
+/// MathML produced for `$...$` and `$$...$$`
+const MATH_TAGS: [&str; 25] = [
+  "math",
+  "semantics",
+  "annotation",
+  "mrow",
+  "mi",
+  "mo",
+  "mn",
+  "mtext",
+  "mspace",
+  "msup",
+  "msub",
+  "msubsup",
+  "mfrac",
+  "msqrt",
+  "mroot",
+  "mover",
+  "munder",
+  "munderover",
+  "mtable",
+  "mtr",
+  "mtd",
+  "mstyle",
+  "menclose",
+  "mpadded",
+  "mphantom",
+];
+
+const MATH_ATTRIBUTES: [&str; 13] = [
+  "xmlns",
+  "display",
+  "mathvariant",
+  "stretchy",
+  "accent",
+  "columnalign",
+  "lspace",
+  "rspace",
+  "displaystyle",
+  "scriptlevel",
+  "width",
+  "linethickness",
+  "notation",
+];
+
+/// Tags whose content is always removed, the sanitizer refuses to allow them
+const CLEAN_CONTENT_TAGS: [&str; 2] = ["script", "style"];
+
+static URL_OR_EMOJI: LazyLock<Regex> = LazyLock::new(|| {
+  Regex::new(r#"(?P<url>https?://[^\s<>"]*[^\s<>"'.,;:!?)\]])|:(?P<emoji>[a-zA-Z0-9_+-]+):"#)
+    .unwrap()
+});
+
+/// The sanitizer always emits double quoted attributes, so anchors can be matched
+/// reliably on its output
+static ANCHOR: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r#"<a((?:\s+[^\s=>]+(?:="[^"]*")?)*)>"#).unwrap());
+static HREF: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\shref="([^"]*)""#).unwrap());
+static LINK_ATTRIBUTES: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r#"\s(?:rel|target)="[^"]*""#).unwrap());
+
+fn leak(value: &str) -> &'static str {
+  Box::leak(value.to_lowercase().into_boxed_str())
+}
+
+/// Build the sanitizer from the configured allowlists, an empty list keeps ammonia's
+/// defaults. Attributes are given as `tag:attribute`, with `*` for any tag, and are
+/// added to the default attributes so that links and images keep `href` and `src`
+pub fn sanitizer(
+  tags: &[String],
+  attributes: &[String],
+  url_schemes: &[String],
+) -> Builder<'static> {
+  let mut builder = Builder::default();
+  let allowed = |tag: &str| {
+    let allowed = !CLEAN_CONTENT_TAGS.contains(&tag);
+    if !allowed {
+      tracing::warn!("Ignoring <{tag}> in the sanitizer allowlist");
+    }
+    allowed
+  };
+  let tags = tags
+    .iter()
+    .map(|tag| leak(tag.trim()))
+    .filter(|tag| !tag.is_empty() && allowed(tag))
+    .collect::<HashSet<_>>();
+  if !tags.is_empty() {
+    builder.tags(tags);
+  }
+  for attribute in attributes.iter().filter(|attribute| !attribute.is_empty()) {
+    match attribute.split_once(':') {
+      Some(("*", name)) => {
+        builder.add_generic_attributes([leak(name.trim())]);
+      }
+      Some((tag, name)) if allowed(tag.trim()) => {
+        builder.add_tag_attributes(leak(tag.trim()), [leak(name.trim())]);
+      }
+      Some(_) => {}
+      None => tracing::warn!("Ignoring sanitizer attribute {attribute}, expected tag:attribute"),
+    }
+  }
+  let url_schemes = url_schemes
+    .iter()
+    .map(|scheme| leak(scheme.trim().trim_end_matches(':')))
+    .filter(|scheme| !scheme.is_empty())
+    .collect::<HashSet<_>>();
+  if !url_schemes.is_empty() {
+    builder.url_schemes(url_schemes);
+  }
+  builder
+}
+
+/// Render LaTeX to MathML, the sanitizer escapes whatever the converter lets through.
+/// Invalid LaTeX is shown as is
+fn math<'a>(latex: &str, style: DisplayStyle) -> Event<'a> {
+  match latex_to_mathml(latex, style) {
+    Ok(mathml) => Event::InlineHtml(mathml.into()),
+    Err(_) => Event::Code(latex.to_string().into()),
+  }
+}
+
+fn unescape_attribute(value: &str) -> String {
+  value
+    .replace("&quot;", "\"")
+    .replace("&nbsp;", "\u{a0}")
+    .replace("&amp;", "&")
+}
+
+fn escape_attribute(value: &str) -> String {
+  value.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+pub fn escape_html(value: &str) -> String {
+  escape_attribute(value)
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+}
+
+/// Renders comments to sanitized html and marks up outbound links
+#[derive(Debug)]
+pub struct Renderer {
+  sanitizer: Builder<'static>,
+  markdown: MarkdownOptions,
+  emojis: EmojiPresets,
+  highlighter: Option<Highlighter>,
+  site_domain: Option<String>,
+  redirect_url: Option<String>,
+}
+
+impl Renderer {
+  /// External links are sent through `redirect_url` when it is set
+  pub fn new(
+    site_url: &str,
+    redirect_url: Option<String>,
+    mut sanitizer: Builder<'static>,
+    markdown: MarkdownOptions,
+    emojis: EmojiPresets,
+    highlighter: Option<Highlighter>,
+  ) -> Self {
+    sanitizer.link_rel(None);
+    if markdown.options.contains(Options::ENABLE_TASKLISTS) {
+      sanitizer
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["type", "checked", "disabled"]);
+    }
+    if markdown.options.contains(Options::ENABLE_FOOTNOTES) {
+      sanitizer.add_tag_attributes("div", ["id"]);
+    }
+    if markdown.math {
+      sanitizer.add_tags(MATH_TAGS);
+      for tag in MATH_TAGS {
+        sanitizer.add_tag_attributes(tag, MATH_ATTRIBUTES);
+      }
+    }
+    if highlighter.is_some() {
+      sanitizer
+        .add_tag_attributes("pre", ["class"])
+        .add_tag_attributes("span", ["class"]);
+    }
+    let class_allowed = sanitizer.clone_generic_attributes().contains("class")
+      || sanitizer
+        .clone_tag_attributes()
+        .get("img")
+        .is_some_and(|attributes| attributes.contains("class"));
+    if !emojis.items.is_empty() && !class_allowed {
+      sanitizer.add_allowed_classes("img", ["wl-emoji"]);
+    }
+    let image = markdown.image;
+    let emoji_bases = emojis.bases.clone();
+    let highlight = highlighter.is_some();
+    sanitizer.attribute_filter(move |element, attribute, value| {
+      let allowed = match (element, attribute) {
+        // Without images only preset emojis are shown
+        ("img", "src") => image || emoji_bases.iter().any(|base| value.starts_with(base)),
+        ("input", "type") => value == "checkbox",
+        ("div", "id") => value.starts_with(FOOTNOTE_PREFIX),
+        ("pre" | "span", "class") if highlight => value
+          .split_whitespace()
+          .all(|class| class.starts_with(CLASS_PREFIX)),
+        _ => true,
+      };
+      allowed.then(|| value.into())
+    });
+    Renderer {
+      sanitizer,
+      markdown,
+      emojis,
+      highlighter,
+      site_domain: domain(site_url),
+      redirect_url,
+    }
+  }
+
+  /// Stylesheet for highlighted code, `None` when highlighting is disabled
+  pub fn highlight_css(&self) -> Option<&str> {
+    self.highlighter.as_ref().map(Highlighter::css)
+  }
+
+  pub fn render(&self, markdown: &str) -> String {
+    let html = self.sanitizer.clean(&self.to_html(markdown)).to_string();
+    self.rewrite_links(&html)
+  }
+
+  /// Render markdown to html
+  fn to_html(&self, markdown: &str) -> String {
+    let parser = pulldown_cmark::Parser::new_ext(markdown, self.markdown.options);
+    // Text is split around delimiters like `_`, merge it back for shortcodes and urls
+    let parser = TextMergeStream::new(parser);
+    let mut events = Vec::new();
+    // Links, images and code are left alone by autolinking and emojis
+    let mut in_link = 0;
+    let mut in_image = 0;
+    let mut in_code = false;
+    // Fenced code in a language known to the highlighter, with its text so far
+    let mut highlighted: Option<(CowStr, String)> = None;
+    for event in parser {
+      if let Some((_, code)) = highlighted.as_mut() {
+        match event {
+          Event::Text(text) => code.push_str(&text),
+          Event::End(TagEnd::CodeBlock) => {
+            let (lang, code) = highlighted.take().unwrap();
+            let html = self
+              .highlighter
+              .as_ref()
+              .and_then(|highlighter| highlighter.highlight(&code, &lang));
+            match html {
+              Some(html) => events.push(Event::Html(html.into())),
+              None => {
+                events.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))));
+                events.push(Event::Text(code.into()));
+                events.push(Event::End(TagEnd::CodeBlock));
+              }
+            }
+          }
+          _ => {}
+        }
+        continue;
+      }
+      match event {
+        Event::SoftBreak => events.push(Event::HardBreak),
+        Event::Html(html) | Event::InlineHtml(html) if !self.markdown.html => {
+          events.push(Event::Text(html))
+        }
+        Event::Start(Tag::Image {
+          link_type,
+          dest_url,
+          title,
+          id,
+        }) if !self.markdown.image => {
+          in_link += 1;
+          events.push(Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+          }));
+        }
+        Event::End(TagEnd::Image) if !self.markdown.image => {
+          in_link -= 1;
+          events.push(Event::End(TagEnd::Link));
+        }
+        Event::Start(Tag::Link { .. }) => {
+          in_link += 1;
+          events.push(event);
+        }
+        Event::End(TagEnd::Link) => {
+          in_link -= 1;
+          events.push(event);
+        }
+        Event::Start(Tag::Image { .. }) => {
+          in_image += 1;
+          events.push(event);
+        }
+        Event::End(TagEnd::Image) => {
+          in_image -= 1;
+          events.push(event);
+        }
+        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang)))
+          if self.highlighter.is_some() && !lang.is_empty() =>
+        {
+          highlighted = Some((lang, String::new()));
+        }
+        Event::InlineMath(latex) | Event::DisplayMath(latex) if in_image > 0 => {
+          events.push(Event::Text(latex))
+        }
+        Event::InlineMath(latex) => events.push(math(&latex, DisplayStyle::Inline)),
+        Event::DisplayMath(latex) => events.push(math(&latex, DisplayStyle::Block)),
+        Event::Start(Tag::CodeBlock(_)) => {
+          in_code = true;
+          events.push(event);
+        }
+        Event::End(TagEnd::CodeBlock) => {
+          in_code = false;
+          events.push(event);
+        }
+        Event::FootnoteReference(name) => {
+          events.push(Event::FootnoteReference(
+            format!("{FOOTNOTE_PREFIX}{name}").into(),
+          ));
+        }
+        Event::Start(Tag::FootnoteDefinition(name)) => {
+          events.push(Event::Start(Tag::FootnoteDefinition(
+            format!("{FOOTNOTE_PREFIX}{name}").into(),
+          )));
+        }
+        Event::Text(text) if !in_code && in_image == 0 => {
+          self.expand_text(text, in_link > 0, &mut events)
+        }
+        _ => events.push(event),
+      }
+    }
+    let mut html_output = String::new();
+    pulldown_cmark::html::push_html(&mut html_output, events.into_iter());
+    html_output
+  }
+
+  /// Expand `:shortcode:` emojis and turn bare urls into links
+  fn expand_text<'a>(&self, text: CowStr<'a>, in_link: bool, events: &mut Vec<Event<'a>>) {
+    if !self.markdown.emoji && (!self.markdown.autolink || in_link) {
+      events.push(Event::Text(text));
+      return;
+    }
+    let mut last = 0;
+    for caps in URL_OR_EMOJI.captures_iter(&text) {
+      let whole = caps.get(0).unwrap();
+      let replacement = if let Some(url) = caps.name("url") {
+        (self.markdown.autolink && !in_link).then(|| {
+          let url = url.as_str().to_string();
+          vec![
+            Event::Start(Tag::Link {
+              link_type: LinkType::Autolink,
+              dest_url: url.clone().into(),
+              title: "".into(),
+              id: "".into(),
+            }),
+            Event::Text(url.into()),
+            Event::End(TagEnd::Link),
+          ]
+        })
+      } else if let Some(name) = caps.name("emoji").filter(|_| self.markdown.emoji) {
+        let name = name.as_str();
+        match self.emojis.items.get(name) {
+          Some(src) => Some(vec![Event::InlineHtml(
+            format!(
+              r#"<img class="wl-emoji" src="{}" alt="{}">"#,
+              escape_html(src),
+              escape_html(name)
+            )
+            .into(),
+          )]),
+          None => emojis::get_by_shortcode(name)
+            .map(|emoji| vec![Event::Text(emoji.as_str().to_string().into())]),
+        }
+      } else {
+        None
+      };
+      if let Some(replacement) = replacement {
+        if whole.start() > last {
+          events.push(Event::Text(text[last..whole.start()].to_string().into()));
+        }
+        events.extend(replacement);
+        last = whole.end();
+      }
+    }
+    if last == 0 {
+      events.push(Event::Text(text));
+    } else if last < text.len() {
+      events.push(Event::Text(text[last..].to_string().into()));
+    }
+  }
+
+  fn is_external(&self, href: &str) -> bool {
+    let lower = href.to_lowercase();
+    if !(lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("//")) {
+      return false;
+    }
+    domain(href.trim_start_matches('/')) != self.site_domain
+  }
+
+  /// Send external links through the redirect page and mark them `nofollow`
+  fn rewrite_links(&self, html: &str) -> String {
+    ANCHOR
+      .replace_all(html, |caps: &Captures| {
+        let attributes = &caps[1];
+        let Some(value) = HREF.captures(attributes).map(|c| unescape_attribute(&c[1])) else {
+          return caps[0].to_string();
+        };
+        if !self.is_external(&value) {
+          return caps[0].to_string();
+        }
+        let target = match &self.redirect_url {
+          Some(redirect_url) => reqwest::Url::parse_with_params(redirect_url, [("url", &value)])
+            .map(|url| url.to_string())
+            .unwrap_or(value),
+          None => value,
+        };
+        let attributes = LINK_ATTRIBUTES.replace_all(attributes, "");
+        let attributes = HREF.replace(&attributes, |_: &Captures| {
+          format!(r#" href="{}""#, escape_attribute(&target))
+        });
+        format!(r#"<a{attributes} rel="nofollow ugc noopener" target="_blank">"#)
+      })
+      .into_owned()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn renderer(attributes: &[&str], math: bool, highlight: bool) -> Renderer {
+    let attributes = attributes.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    Renderer::new(
+      "https://example.com",
+      None,
+      sanitizer(&[], &attributes, &[]),
+      MarkdownOptions::new(&[], true, true, math),
+      EmojiPresets::default(),
+      highlight
+        .then(|| Highlighter::new("InspiredGitHub"))
+        .flatten(),
+    )
+  }
+
+  #[test]
+  fn strips_script_links() {
+    let renderer = renderer(&[], false, false);
+    for markdown in [
+      "[x](javascript:alert(1))",
+      "[x](JaVaScRiPt:alert(1))",
+      "<a href=\"javascript:alert(1)\">x</a>",
+      "[x](data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==)",
+      "![x](data:image/svg+xml;base64,PHN2Zz48L3N2Zz4=)",
+    ] {
+      let html = renderer.render(markdown);
+      assert!(!html.contains("javascript:"), "{markdown} rendered {html}");
+      assert!(!html.contains("data:"), "{markdown} rendered {html}");
+    }
+  }
+
+  #[test]
+  fn strips_event_handlers_and_scripts() {
+    let renderer = renderer(&[], true, false);
+    let html = renderer.render("<img src=\"x.png\" onerror=\"alert(1)\">");
+    assert!(!html.contains("onerror"), "{html}");
+    let html = renderer.render("<svg><script>alert(1)</script></svg>");
+    assert!(
+      !html.contains("<script") && !html.contains("alert"),
+      "{html}"
+    );
+    assert!(!html.contains("<svg"), "{html}");
+  }
+
+  #[test]
+  fn strips_mathml_links() {
+    let renderer = renderer(&[], true, false);
+    let html = renderer.render(
+      "<math href=\"javascript:alert(1)\"><mi xlink:href=\"javascript:alert(1)\">x</mi></math>",
+    );
+    assert!(html.contains("<math"), "{html}");
+    assert!(!html.contains("href"), "{html}");
+    assert!(renderer.render("$x^2$").contains("<msup>"));
+  }
+
+  #[test]
+  fn keeps_only_highlight_classes() {
+    let renderer = renderer(&[], false, true);
+    let html = renderer.render("```rust\nfn main() {}\n```");
+    assert!(html.contains(&format!("class=\"{CLASS_PREFIX}")), "{html}");
+    let html = renderer.render("<pre class=\"evil hl-code\">x</pre><span class=\"hl-a\">y</span>");
+    assert!(!html.contains("evil"), "{html}");
+    assert!(html.contains("<span class=\"hl-a\">"), "{html}");
+  }
+
+  #[test]
+  fn configured_attributes_extend_the_defaults() {
+    let renderer = renderer(&["*:title"], false, false);
+    let html = renderer.render(
+      "<a href=\"https://example.com/a\" title=\"t\">a</a> ![i](https://example.com/i.png)",
+    );
+    assert!(html.contains("href=\"https://example.com/a\""), "{html}");
+    assert!(html.contains("title=\"t\""), "{html}");
+    assert!(html.contains("src=\"https://example.com/i.png\""), "{html}");
+  }
 }
@@ -0,0 +1,90 @@
+//! `@nick` and `@email` mentions in comments
+
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+
+/// A mention resolved to a registered user or an earlier commenter on the page
+#[derive(Debug, Clone)]
+pub struct Mention {
+  /// What was written after `@`
+  pub token: String,
+  pub nick: String,
+  pub email: String,
+  pub href: Option<String>,
+}
+
+/// Mentions already written as markdown links, like the `[@nick](#id)` that the
+/// client prefixes replies with, are skipped
+static MENTION: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r"(^|[^\w@\[/])@([\w.+-]+@[\w-]+(?:\.[\w-]+)+|[\w-]+)").unwrap());
+
+static CODE: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r"(?s)```.*?(?:```|$)|`[^`\n]*`").unwrap());
+
+/// Split a comment into `(is_code, text)` parts, mentions in code are left alone
+fn split_code(comment: &str) -> Vec<(bool, &str)> {
+  let mut parts = Vec::new();
+  let mut last = 0;
+  for span in CODE.find_iter(comment) {
+    parts.push((false, &comment[last..span.start()]));
+    parts.push((true, span.as_str()));
+    last = span.end();
+  }
+  parts.push((false, &comment[last..]));
+  parts
+}
+
+/// Distinct mentions in the order they are written
+pub fn parse(comment: &str) -> Vec<String> {
+  let mut tokens = Vec::<String>::new();
+  for (_, text) in split_code(comment).into_iter().filter(|(code, _)| !code) {
+    for caps in MENTION.captures_iter(text) {
+      let token = &caps[2];
+      if !tokens.iter().any(|seen| seen.eq_ignore_ascii_case(token)) {
+        tokens.push(token.to_string());
+      }
+    }
+  }
+  tokens
+}
+
+fn escape_markdown(text: &str) -> String {
+  text
+    .chars()
+    .flat_map(|c| match c {
+      '\\' | '[' | ']' | '*' | '_' | '`' => vec!['\\', c],
+      c => vec![c],
+    })
+    .collect()
+}
+
+/// Turn resolved mentions into markdown links. Email mentions are notified but left as
+/// written, showing them by nick would tell readers who owns the address
+pub fn link(comment: &str, mentions: &[Mention]) -> String {
+  let mut linked = String::with_capacity(comment.len());
+  for (code, text) in split_code(comment) {
+    if code {
+      linked.push_str(text);
+      continue;
+    }
+    let text = MENTION.replace_all(text, |caps: &Captures| {
+      let mention = mentions
+        .iter()
+        .find(|mention| mention.token.eq_ignore_ascii_case(&caps[2]))
+        .filter(|mention| !mention.token.contains('@'));
+      match mention {
+        Some(Mention {
+          nick,
+          href: Some(href),
+          ..
+        }) if !href.contains(['<', '>']) && !href.contains(char::is_whitespace) => {
+          format!("{}[@{}](<{href}>)", &caps[1], escape_markdown(nick))
+        }
+        _ => caps[0].to_string(),
+      }
+    });
+    linked.push_str(&text);
+  }
+  linked
+}
@@ -1,8 +1,23 @@
 //! helpers
 
+pub mod alias;
 pub mod avatar;
+pub mod ban;
+pub mod bayes;
 pub mod email;
+pub mod emoji;
+pub mod forbidden;
 pub mod header;
+pub mod highlight;
+pub mod limiter;
+pub mod link;
 pub mod markdown;
+pub mod mention;
+pub mod page;
+pub mod pow;
+pub mod region;
+pub mod snippet;
 pub mod spam;
+pub mod store;
 pub mod ua;
+pub mod url;
@@ -0,0 +1,42 @@
+//! Comment settings of single pages or of every page below a prefix
+//!
+//! Settings are stored in `wl_PageSetting` and kept in memory. The settings of a page
+//! itself win over prefix settings, and the longest prefix wins among those.
+
+use std::sync::RwLock;
+
+use sea_orm::{DatabaseConnection, EntityTrait};
+
+use crate::{entities::wl_page_setting, error::AppError, helpers::url::relocate};
+
+#[derive(Debug)]
+pub struct PageSettings {
+  settings: RwLock<Vec<wl_page_setting::Model>>,
+}
+
+impl PageSettings {
+  pub async fn load(conn: &DatabaseConnection) -> Result<Self, AppError> {
+    let settings = PageSettings {
+      settings: RwLock::new(Vec::new()),
+    };
+    settings.reload(conn).await?;
+    Ok(settings)
+  }
+
+  /// Reread the settings after they were changed
+  pub async fn reload(&self, conn: &DatabaseConnection) -> Result<(), AppError> {
+    let mut settings = wl_page_setting::Entity::find().all(conn).await?;
+    settings.sort_by_key(|setting| (setting.prefix, std::cmp::Reverse(setting.url.len())));
+    *self.settings.write().unwrap() = settings;
+    Ok(())
+  }
+
+  /// The settings that apply to a normalized url
+  pub fn get(&self, url: &str) -> Option<wl_page_setting::Model> {
+    let settings = self.settings.read().unwrap();
+    settings
+      .iter()
+      .find(|setting| relocate(url, &setting.url, &setting.url, setting.prefix).is_some())
+      .cloned()
+  }
+}
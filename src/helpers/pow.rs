@@ -0,0 +1,138 @@
+//! Self-hosted proof-of-work challenge, compatible with the ALTCHA payload format
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use helpers::{
+  rand::min_max,
+  time::utc_now,
+  uuid::{self, Alphabet},
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::helpers::store::Store;
+
+const ALGORITHM: &str = "SHA-256";
+const CHALLENGE_TTL: i64 = 10 * 60;
+
+#[derive(Serialize)]
+pub struct Challenge {
+  pub algorithm: String,
+  pub challenge: String,
+  pub maxnumber: u64,
+  pub salt: String,
+  pub signature: String,
+}
+
+#[derive(Deserialize)]
+pub struct Solution {
+  pub algorithm: String,
+  pub challenge: String,
+  pub number: u64,
+  pub salt: String,
+  pub signature: String,
+}
+
+#[derive(Debug)]
+pub struct ProofOfWork {
+  secret: String,
+  difficulty: u64,
+  max_difficulty: u64,
+  store: Store,
+}
+
+fn sha256_hex(data: &str) -> String {
+  Sha256::digest(data.as_bytes())
+    .iter()
+    .map(|b| format!("{:02x}", b))
+    .collect()
+}
+
+impl ProofOfWork {
+  pub fn new(secret: String, difficulty: u64, max_difficulty: u64, store: Store) -> Self {
+    ProofOfWork {
+      secret,
+      difficulty,
+      max_difficulty: max_difficulty.max(difficulty),
+      store,
+    }
+  }
+
+  pub fn enabled(&self) -> bool {
+    self.difficulty > 0
+  }
+
+  fn sign(&self, challenge: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()).unwrap();
+    mac.update(challenge.as_bytes());
+    mac
+      .finalize()
+      .into_bytes()
+      .iter()
+      .map(|b| format!("{:02x}", b))
+      .collect()
+  }
+
+  /// Doubles the base difficulty for every spam comment recently seen from the client
+  pub fn difficulty_for(&self, spam_count: usize) -> u64 {
+    let shift = spam_count.min(32) as u32;
+    self
+      .difficulty
+      .saturating_mul(1 << shift)
+      .min(self.max_difficulty)
+  }
+
+  pub fn create_challenge(&self, spam_count: usize) -> Challenge {
+    let maxnumber = self.difficulty_for(spam_count);
+    let salt = format!(
+      "{}?expires={}",
+      uuid::uuid(&Alphabet::DEFAULT, 12),
+      utc_now().timestamp() + CHALLENGE_TTL
+    );
+    let number = min_max(0, maxnumber.min(i32::MAX as u64) as i32) as u64;
+    let challenge = sha256_hex(&format!("{salt}{number}"));
+    Challenge {
+      algorithm: ALGORITHM.to_string(),
+      signature: self.sign(&challenge),
+      challenge,
+      maxnumber,
+      salt,
+    }
+  }
+
+  /// Verify a base64 encoded solution, each solution can only be used once
+  pub async fn verify(&self, payload: &str) -> bool {
+    let solution = match STANDARD
+      .decode(payload)
+      .ok()
+      .and_then(|bytes| serde_json::from_slice::<Solution>(&bytes).ok())
+    {
+      Some(solution) => solution,
+      None => return false,
+    };
+    if solution.algorithm != ALGORITHM {
+      return false;
+    }
+    let expires = solution
+      .salt
+      .split_once("?expires=")
+      .and_then(|(_, expires)| expires.parse::<i64>().ok());
+    match expires {
+      Some(expires) if expires > utc_now().timestamp() => {}
+      _ => return false,
+    }
+    if sha256_hex(&format!("{}{}", solution.salt, solution.number)) != solution.challenge
+      || self.sign(&solution.challenge) != solution.signature
+    {
+      return false;
+    }
+    let ttl = Duration::from_secs(CHALLENGE_TTL as u64);
+    self
+      .store
+      .set_once(&format!("pow:{}", solution.signature), ttl)
+      .await
+      .unwrap_or(false)
+  }
+}
@@ -0,0 +1,124 @@
+//! Commenter region from an offline MaxMind (MMDB) database, and the
+//! `DISABLE_REGION` / `DISABLE_USERAGENT` switches for comment responses
+
+use std::{
+  collections::{BTreeMap, HashMap},
+  net::IpAddr,
+  sync::Mutex,
+};
+
+use maxminddb::{geoip2, Reader};
+use serde_json::{json, Value};
+
+use crate::helpers::ua;
+
+/// Lookups are cached until this many addresses have been seen
+const CACHE_CAPACITY: usize = 10_000;
+
+pub struct ClientInfo {
+  reader: Option<Reader<Vec<u8>>>,
+  cache: Mutex<HashMap<IpAddr, String>>,
+  disable_region: bool,
+  disable_useragent: bool,
+}
+
+impl std::fmt::Debug for ClientInfo {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ClientInfo")
+      .field(
+        "reader",
+        &self.reader.as_ref().map(|reader| &reader.metadata),
+      )
+      .field("disable_region", &self.disable_region)
+      .field("disable_useragent", &self.disable_useragent)
+      .finish()
+  }
+}
+
+/// English name, or any name when the database has no English one
+fn name(names: &Option<BTreeMap<&str, &str>>) -> Option<String> {
+  let names = names.as_ref()?;
+  names
+    .get("en")
+    .or_else(|| names.values().next())
+    .map(|name| name.to_string())
+}
+
+impl ClientInfo {
+  /// A database that cannot be opened leaves regions empty
+  pub fn load(ip_database: Option<&str>, disable_region: bool, disable_useragent: bool) -> Self {
+    let reader = ip_database
+      .filter(|path| !path.is_empty() && !disable_region)
+      .and_then(|path| match Reader::open_readfile(path) {
+        Ok(reader) => {
+          tracing::info!("Loaded {} from {path}", reader.metadata.database_type);
+          Some(reader)
+        }
+        Err(err) => {
+          tracing::warn!("Failed to open IP database {path}: {err}");
+          None
+        }
+      });
+    ClientInfo {
+      reader,
+      cache: Mutex::new(HashMap::new()),
+      disable_region,
+      disable_useragent,
+    }
+  }
+
+  fn lookup(&self, ip: IpAddr) -> String {
+    let Some(reader) = &self.reader else {
+      return String::new();
+    };
+    if let Some(region) = self.cache.lock().unwrap().get(&ip) {
+      return region.clone();
+    }
+    let mut parts = Vec::<String>::new();
+    if let Ok(city) = reader.lookup::<geoip2::City>(ip) {
+      let country = city.country.and_then(|country| name(&country.names));
+      let subdivision = city
+        .subdivisions
+        .and_then(|subdivisions| subdivisions.into_iter().next())
+        .and_then(|subdivision| name(&subdivision.names));
+      let city = city.city.and_then(|city| name(&city.names));
+      for part in [country, subdivision, city].into_iter().flatten() {
+        if !parts.contains(&part) {
+          parts.push(part);
+        }
+      }
+    }
+    let region = parts.join(" ");
+    let mut cache = self.cache.lock().unwrap();
+    if cache.len() >= CACHE_CAPACITY {
+      cache.clear();
+    }
+    cache.insert(ip, region.clone());
+    region
+  }
+
+  /// Region of a commenter, `None` when regions are hidden
+  pub fn region(&self, ip: Option<&str>) -> Option<String> {
+    if self.disable_region {
+      return None;
+    }
+    let ip = ip.and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+    Some(ip.map(|ip| self.lookup(ip)).unwrap_or_default())
+  }
+
+  /// Browser and os of a commenter, `None` when user agents are hidden
+  pub fn user_agent(&self, ua: Option<&str>) -> Option<(String, String)> {
+    (!self.disable_useragent).then(|| ua::parse(ua.unwrap_or_default().to_string()))
+  }
+
+  /// Fill `addr`, `browser` and `os` of a comment response, leaving out hidden ones
+  pub fn describe(&self, data: &mut Value, ip: Option<&str>, ua: Option<&str>) {
+    if let Some(addr) = self.region(ip) {
+      data["addr"] = json!(addr);
+    }
+    if let Some((browser, os)) = self.user_agent(ua) {
+      data["browser"] = json!(browser);
+      data["os"] = json!(os);
+    }
+  }
+}
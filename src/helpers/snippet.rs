@@ -0,0 +1,57 @@
+//! Highlighted excerpts of search results
+
+use regex::RegexBuilder;
+
+use crate::helpers::markdown::escape_html;
+
+/// Characters shown before the first occurrence
+const CONTEXT: usize = 40;
+/// Characters in an excerpt
+const LENGTH: usize = 160;
+
+/// Escaped html excerpt of `text` around the first word of `keyword` it contains, with
+/// every occurrence wrapped in `<mark>`. Text without one is excerpted from the start
+pub fn highlight(text: &str, keyword: &str) -> String {
+  let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+  let pattern = keyword
+    .split_whitespace()
+    .map(regex::escape)
+    .collect::<Vec<_>>()
+    .join("|");
+  let re = (!pattern.is_empty()).then(|| {
+    RegexBuilder::new(&pattern)
+      .case_insensitive(true)
+      .build()
+      .unwrap()
+  });
+  let first = re
+    .as_ref()
+    .and_then(|re| re.find(&text))
+    .map_or(0, |found| text[..found.start()].chars().count());
+  let skip = first.saturating_sub(CONTEXT);
+  let offset = |chars: usize| {
+    text
+      .char_indices()
+      .nth(chars)
+      .map_or(text.len(), |(offset, _)| offset)
+  };
+  let (start, end) = (offset(skip), offset(skip + LENGTH));
+  let excerpt = &text[start..end];
+  let mut html = String::new();
+  if start > 0 {
+    html.push('…');
+  }
+  let mut last = 0;
+  for found in re.iter().flat_map(|re| re.find_iter(excerpt)) {
+    html.push_str(&escape_html(&excerpt[last..found.start()]));
+    html.push_str("<mark>");
+    html.push_str(&escape_html(found.as_str()));
+    html.push_str("</mark>");
+    last = found.end();
+  }
+  html.push_str(&escape_html(&excerpt[last..]));
+  if end < text.len() {
+    html.push('…');
+  }
+  html
+}
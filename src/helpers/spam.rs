@@ -1,33 +1,99 @@
+use std::net::ToSocketAddrs;
+
 use instant_akismet::{AkismetClient, AkismetOptions, CheckResult, Comment};
 
-use crate::{config::EnvConfig, error::AppError};
+use crate::{entities::wl_comment, error::AppError, helpers::bayes::Class};
+
+/// Create the Akismet client and verify the key once. `base_url` points the client at
+/// another server, such as a local stub, in the form of `http://localhost:8080/1.1`
+pub async fn create_client(
+  site_url: String,
+  akismet_key: String,
+  base_url: Option<String>,
+) -> Result<Option<AkismetClient>, AppError> {
+  if akismet_key == "false" {
+    return Ok(None);
+  }
+  let mut options = AkismetOptions::default();
+  let mut http = reqwest::Client::builder();
+  if let Some(base_url) = base_url {
+    let url = reqwest::Url::parse(&base_url).map_err(|err| {
+      tracing::error!("Invalid AKISMET_URL {base_url}: {err}");
+      AppError::Error
+    })?;
+    let host = url.host_str().ok_or(AppError::Error)?.to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+    options.protocol = url.scheme().to_string();
+    options.host = match url.port() {
+      Some(port) => format!("{host}:{port}"),
+      None => host.clone(),
+    };
+    let version = url.path().trim_matches('/');
+    if !version.is_empty() {
+      options.version = version.to_string();
+    }
+    // API calls go to the `{key}.{host}` subdomain, resolve it like the host itself
+    if let Ok(addrs) = (host.as_str(), port).to_socket_addrs() {
+      for addr in addrs {
+        http = http.resolve(&format!("{akismet_key}.{host}"), addr);
+      }
+    }
+  }
+  let http = http.build().map_err(|err| {
+    tracing::error!("{:#?}", err);
+    AppError::Akismet
+  })?;
+  let client = AkismetClient::new(site_url, akismet_key, http, options);
+  match client.verify_key().await {
+    Ok(()) => Ok(Some(client)),
+    Err(instant_akismet::Error::Invalid(reason)) => {
+      tracing::error!("Akismet key is invalid, the anti-spam system is disabled: {reason}");
+      Ok(None)
+    }
+    Err(err) => {
+      tracing::warn!("Akismet key could not be verified: {err:?}");
+      Ok(Some(client))
+    }
+  }
+}
 
 pub async fn check_comment(
+  client: &AkismetClient,
   author: String,
   email: String,
   ip: String,
   content: String,
 ) -> Result<CheckResult, AppError> {
-  let EnvConfig {
-    site_url,
-    akismet_key,
-    ..
-  } = EnvConfig::load_env()?;
-  if akismet_key == "false" {
-    return Ok(CheckResult::Ham);
-  }
-  let akismet_client = AkismetClient::new(
-    site_url,                  // The URL for your blog
-    akismet_key,               // Your Akismet API key
-    reqwest::Client::new(),    // Reqwest client to use for requests
-    AkismetOptions::default(), // AkismetOptions config
-  );
-  akismet_client.verify_key().await?;
-  let comment = Comment::new(akismet_client.blog.as_ref(), &ip)
+  let comment = Comment::new(client.blog.as_ref(), &ip)
     .comment_author(&author)
     .comment_author_email(&email)
     .comment_content(&content);
-  let result = akismet_client.check_comment(comment).await?;
+  let result = client.check_comment(comment).await?;
   tracing::info!("Comment is {:#?}", result);
   Ok(result)
 }
+
+/// Report a moderator's reclassification back to Akismet
+pub async fn submit_comment(
+  client: &AkismetClient,
+  comment: &wl_comment::Model,
+  class: Class,
+) -> Result<(), AppError> {
+  let ip = comment.ip.clone().unwrap_or_default();
+  let mut akismet_comment = Comment::new(client.blog.as_ref(), &ip)
+    .comment_author(comment.nick.as_deref().unwrap_or_default())
+    .comment_author_email(comment.mail.as_deref().unwrap_or_default())
+    .comment_content(comment.comment.as_deref().unwrap_or_default());
+  if let Some(link) = comment.link.as_deref().filter(|link| !link.is_empty()) {
+    akismet_comment = akismet_comment.comment_author_url(link);
+  }
+  if let Some(ua) = comment.ua.as_deref() {
+    akismet_comment = akismet_comment.user_agent(ua);
+  }
+  match class {
+    Class::Spam => client.submit_spam(akismet_comment).await?,
+    Class::Ham => client.submit_ham(akismet_comment).await?,
+  }
+  tracing::info!("Submitted comment {} to Akismet as {:?}", comment.id, class);
+  Ok(())
+}
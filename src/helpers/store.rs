@@ -0,0 +1,408 @@
+//! Storage for rate limiter counters, duplicate detection and caches
+//!
+//! The in-memory store is the default. Deployments with several replicas can share
+//! the state through the SQL database or a Redis-compatible server.
+
+use std::{
+  collections::{hash_map::DefaultHasher, HashMap},
+  hash::{Hash, Hasher},
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use helpers::time::utc_now;
+use sea_orm::{
+  sea_query::OnConflict, ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection,
+  EntityTrait, QueryFilter, QuerySelect, TransactionTrait,
+};
+
+use crate::{entities::wl_store, error::AppError, helpers::limiter::Quota};
+
+const SHARDS: usize = 16;
+
+/// Refill the bucket for the elapsed time and try to take a token
+fn take(tokens: f64, updated: i64, now: i64, quota: Quota) -> (bool, f64) {
+  let capacity = quota.count as f64;
+  let elapsed = (now - updated).max(0) as f64 / 1000.0;
+  let tokens = (tokens + elapsed * quota.rate()).min(capacity);
+  if tokens >= 1.0 {
+    (true, tokens - 1.0)
+  } else {
+    (false, tokens)
+  }
+}
+
+/// The moment a bucket is full again, after which it can be forgotten
+fn refilled_at(tokens: f64, now: i64, quota: Quota) -> i64 {
+  now + ((quota.count as f64 - tokens) / quota.rate() * 1000.0).ceil() as i64
+}
+
+#[derive(Debug)]
+struct Entry {
+  value: String,
+  expires_at: i64,
+}
+
+#[derive(Debug)]
+pub struct MemoryStore {
+  shards: Vec<Mutex<HashMap<String, Entry>>>,
+}
+
+impl MemoryStore {
+  fn new() -> Self {
+    MemoryStore {
+      shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+    }
+  }
+
+  fn shard(&self, key: &str) -> &Mutex<HashMap<String, Entry>> {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    &self.shards[hasher.finish() as usize % SHARDS]
+  }
+
+  /// Update the live value of a key in place, expired entries are handed over as `None`
+  fn update<T>(&self, key: &str, f: impl FnOnce(Option<&str>, i64) -> (Entry, T)) -> T {
+    let now = utc_now().timestamp_millis();
+    let mut shard = self.shard(key).lock().unwrap();
+    let current = shard
+      .get(key)
+      .filter(|entry| entry.expires_at > now)
+      .map(|entry| entry.value.as_str());
+    let (entry, result) = f(current, now);
+    shard.insert(key.to_string(), entry);
+    result
+  }
+
+  fn get(&self, key: &str) -> Option<String> {
+    let now = utc_now().timestamp_millis();
+    let shard = self.shard(key).lock().unwrap();
+    shard
+      .get(key)
+      .filter(|entry| entry.expires_at > now)
+      .map(|entry| entry.value.clone())
+  }
+
+  fn remove(&self, key: &str) {
+    self.shard(key).lock().unwrap().remove(key);
+  }
+
+  fn evict(&self) {
+    let now = utc_now().timestamp_millis();
+    for shard in &self.shards {
+      shard
+        .lock()
+        .unwrap()
+        .retain(|_, entry| entry.expires_at > now);
+    }
+  }
+}
+
+fn parse_bucket(value: Option<&str>, now: i64, quota: Quota) -> (f64, i64) {
+  value
+    .and_then(|value| value.split_once(':'))
+    .and_then(|(tokens, updated)| Some((tokens.parse().ok()?, updated.parse().ok()?)))
+    .unwrap_or((quota.count as f64, now))
+}
+
+#[cfg(feature = "redis")]
+const TAKE_TOKEN_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+local tokens = tonumber(bucket[1]) or capacity
+local updated = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - updated) / 1000 * rate)
+local allowed = 0
+if tokens >= 1 then
+  tokens = tokens - 1
+  allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil((capacity - tokens) / rate * 1000) + 1)
+return {allowed, tostring(tokens)}
+"#;
+
+#[derive(Clone)]
+pub enum Store {
+  Memory(Arc<MemoryStore>),
+  Database(DatabaseConnection),
+  #[cfg(feature = "redis")]
+  Redis(redis::aio::ConnectionManager),
+}
+
+impl std::fmt::Debug for Store {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Store::Memory(_) => write!(f, "Store::Memory"),
+      Store::Database(_) => write!(f, "Store::Database"),
+      #[cfg(feature = "redis")]
+      Store::Redis(_) => write!(f, "Store::Redis"),
+    }
+  }
+}
+
+impl Store {
+  /// Select the store from `STORE`: `memory`, `database` or a `redis://` URL
+  pub async fn connect(store: &str, conn: &DatabaseConnection) -> Result<Store, AppError> {
+    match store {
+      "" | "memory" => Ok(Store::Memory(Arc::new(MemoryStore::new()))),
+      "database" => Ok(Store::Database(conn.clone())),
+      #[cfg(feature = "redis")]
+      url if url.starts_with("redis://") || url.starts_with("rediss://") => {
+        let client = redis::Client::open(url)?;
+        Ok(Store::Redis(client.get_connection_manager().await?))
+      }
+      _ => {
+        tracing::error!("Unsupported store {store}");
+        Err(AppError::Error)
+      }
+    }
+  }
+
+  /// Take a token from a bucket and return whether it was allowed and the tokens left
+  pub async fn take_token(&self, key: &str, quota: Quota) -> Result<(bool, f64), AppError> {
+    match self {
+      Store::Memory(store) => Ok(store.update(key, |value, now| {
+        let (tokens, updated) = parse_bucket(value, now, quota);
+        let (allowed, tokens) = take(tokens, updated, now, quota);
+        let entry = Entry {
+          value: format!("{tokens}:{now}"),
+          expires_at: refilled_at(tokens, now, quota),
+        };
+        (entry, (allowed, tokens))
+      })),
+      Store::Database(conn) => {
+        let txn = conn.begin().await?;
+        let now = utc_now().timestamp_millis();
+        let current = lock_row(&txn, key, now).await?;
+        let (tokens, updated) =
+          parse_bucket(current.as_ref().map(|m| m.value.as_str()), now, quota);
+        let (allowed, tokens) = take(tokens, updated, now, quota);
+        upsert(
+          &txn,
+          key,
+          format!("{tokens}:{now}"),
+          refilled_at(tokens, now, quota),
+        )
+        .await?;
+        txn.commit().await?;
+        Ok((allowed, tokens))
+      }
+      #[cfg(feature = "redis")]
+      Store::Redis(conn) => {
+        let (allowed, tokens): (i64, String) = redis::Script::new(TAKE_TOKEN_SCRIPT)
+          .key(key)
+          .arg(quota.count)
+          .arg(quota.rate())
+          .invoke_async(&mut conn.clone())
+          .await?;
+        Ok((allowed == 1, tokens.parse().unwrap_or(0.0)))
+      }
+    }
+  }
+
+  /// Increment a counter, every increment extends its lifetime to `ttl`
+  pub async fn incr(&self, key: &str, ttl: Duration) -> Result<u64, AppError> {
+    let ttl = ttl.as_millis() as i64;
+    match self {
+      Store::Memory(store) => Ok(store.update(key, |value, now| {
+        let count = value.and_then(|v| v.parse::<u64>().ok()).unwrap_or(0) + 1;
+        let entry = Entry {
+          value: count.to_string(),
+          expires_at: now + ttl,
+        };
+        (entry, count)
+      })),
+      Store::Database(conn) => {
+        let txn = conn.begin().await?;
+        let now = utc_now().timestamp_millis();
+        let count = lock_row(&txn, key, now)
+          .await?
+          .and_then(|model| model.value.parse::<u64>().ok())
+          .unwrap_or(0)
+          + 1;
+        upsert(&txn, key, count.to_string(), now + ttl).await?;
+        txn.commit().await?;
+        Ok(count)
+      }
+      #[cfg(feature = "redis")]
+      Store::Redis(conn) => {
+        let (count,): (u64,) = redis::pipe()
+          .atomic()
+          .incr(key, 1)
+          .pexpire(key, ttl)
+          .ignore()
+          .query_async(&mut conn.clone())
+          .await?;
+        Ok(count)
+      }
+    }
+  }
+
+  pub async fn get(&self, key: &str) -> Result<Option<String>, AppError> {
+    match self {
+      Store::Memory(store) => Ok(store.get(key)),
+      Store::Database(conn) => {
+        let now = utc_now().timestamp_millis();
+        Ok(
+          wl_store::Entity::find_by_id(key)
+            .one(conn)
+            .await?
+            .filter(|model| model.expires_at > now)
+            .map(|model| model.value),
+        )
+      }
+      #[cfg(feature = "redis")]
+      Store::Redis(conn) => Ok(redis::AsyncCommands::get(&mut conn.clone(), key).await?),
+    }
+  }
+
+  pub async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), AppError> {
+    let ttl = ttl.as_millis() as i64;
+    match self {
+      Store::Memory(store) => {
+        store.update(key, |_, now| {
+          let entry = Entry {
+            value: value.to_string(),
+            expires_at: now + ttl,
+          };
+          (entry, ())
+        });
+        Ok(())
+      }
+      Store::Database(conn) => {
+        let now = utc_now().timestamp_millis();
+        upsert(conn, key, value.to_string(), now + ttl).await
+      }
+      #[cfg(feature = "redis")]
+      Store::Redis(conn) => {
+        Ok(redis::AsyncCommands::pset_ex(&mut conn.clone(), key, value, ttl as u64).await?)
+      }
+    }
+  }
+
+  /// Mark a key as seen, returns `false` when it was already seen within `ttl`
+  pub async fn set_once(&self, key: &str, ttl: Duration) -> Result<bool, AppError> {
+    let ttl = ttl.as_millis() as i64;
+    match self {
+      Store::Memory(store) => Ok(store.update(key, |value, now| {
+        let entry = Entry {
+          value: "1".to_string(),
+          expires_at: now + ttl,
+        };
+        (entry, value.is_none())
+      })),
+      Store::Database(conn) => {
+        let txn = conn.begin().await?;
+        let now = utc_now().timestamp_millis();
+        let seen = lock_row(&txn, key, now).await?.is_some();
+        if !seen {
+          upsert(&txn, key, "1".to_string(), now + ttl).await?;
+        }
+        txn.commit().await?;
+        Ok(!seen)
+      }
+      #[cfg(feature = "redis")]
+      Store::Redis(conn) => {
+        let set: Option<String> = redis::cmd("SET")
+          .arg(key)
+          .arg(1)
+          .arg("NX")
+          .arg("PX")
+          .arg(ttl)
+          .query_async(&mut conn.clone())
+          .await?;
+        Ok(set.is_some())
+      }
+    }
+  }
+
+  pub async fn remove(&self, key: &str) -> Result<(), AppError> {
+    match self {
+      Store::Memory(store) => {
+        store.remove(key);
+        Ok(())
+      }
+      Store::Database(conn) => {
+        wl_store::Entity::delete_by_id(key).exec(conn).await?;
+        Ok(())
+      }
+      #[cfg(feature = "redis")]
+      Store::Redis(conn) => Ok(redis::AsyncCommands::del(&mut conn.clone(), key).await?),
+    }
+  }
+
+  /// Remove expired keys, Redis expires them by itself
+  pub async fn evict(&self) -> Result<(), AppError> {
+    match self {
+      Store::Memory(store) => {
+        store.evict();
+        Ok(())
+      }
+      Store::Database(conn) => {
+        let now = utc_now().timestamp_millis();
+        wl_store::Entity::delete_many()
+          .filter(wl_store::Column::ExpiresAt.lte(now))
+          .exec(conn)
+          .await?;
+        Ok(())
+      }
+      #[cfg(feature = "redis")]
+      Store::Redis(_) => Ok(()),
+    }
+  }
+}
+
+/// Lock the row of a key until the transaction ends and return it while it is live
+///
+/// A missing key is inserted as an expired placeholder first, so concurrent callers queue
+/// on the same row instead of both reading nothing and overwriting each other.
+async fn lock_row<C: ConnectionTrait>(
+  txn: &C,
+  key: &str,
+  now: i64,
+) -> Result<Option<wl_store::Model>, AppError> {
+  wl_store::Entity::insert(wl_store::ActiveModel {
+    key: Set(key.to_string()),
+    value: Set(String::new()),
+    expires_at: Set(now),
+  })
+  .on_conflict(
+    OnConflict::column(wl_store::Column::Key)
+      .do_nothing_on([wl_store::Column::Key])
+      .to_owned(),
+  )
+  .exec_without_returning(txn)
+  .await?;
+  Ok(
+    wl_store::Entity::find_by_id(key)
+      .lock_exclusive()
+      .one(txn)
+      .await?
+      .filter(|model| model.expires_at > now),
+  )
+}
+
+async fn upsert<C: ConnectionTrait>(
+  conn: &C,
+  key: &str,
+  value: String,
+  expires_at: i64,
+) -> Result<(), AppError> {
+  wl_store::Entity::insert(wl_store::ActiveModel {
+    key: Set(key.to_string()),
+    value: Set(value),
+    expires_at: Set(expires_at),
+  })
+  .on_conflict(
+    OnConflict::column(wl_store::Column::Key)
+      .update_columns([wl_store::Column::Value, wl_store::Column::ExpiresAt])
+      .to_owned(),
+  )
+  .exec(conn)
+  .await?;
+  Ok(())
+}
@@ -0,0 +1,159 @@
+//! Page urls as comments and counters are keyed by
+
+use percent_encoding::percent_decode_str;
+
+/// Characters kept percent-encoded in a path. Decoded they would end the path or split a
+/// segment, or be decoded once more the next time the url is normalized
+const RESERVED: [u8; 4] = [b'/', b'?', b'#', b'%'];
+
+/// Percent-decode and lowercase a path, leaving reserved characters encoded in upper case
+fn decode_path(path: &str) -> String {
+  let decode = |text: &str| {
+    percent_decode_str(text)
+      .decode_utf8()
+      .map_or_else(|_| text.to_lowercase(), |text| text.to_lowercase())
+  };
+  let mut decoded = String::with_capacity(path.len());
+  let mut start = 0;
+  let mut index = 0;
+  while let Some(offset) = path[index..].find('%') {
+    index += offset;
+    let escaped = path
+      .get(index + 1..index + 3)
+      .filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+      .filter(|byte| RESERVED.contains(byte));
+    match escaped {
+      Some(byte) => {
+        decoded.push_str(&decode(&path[start..index]));
+        decoded.push_str(&format!("%{byte:02X}"));
+        index += 3;
+        start = index;
+      }
+      None => index += 1,
+    }
+  }
+  decoded.push_str(&decode(&path[start..]));
+  decoded
+}
+
+/// Reduce the url of a page to its canonical path, so that every spelling of the same page
+/// shares comments and counters. The origin and fragment are dropped, the path is
+/// percent-decoded and lowercased, repeated and trailing slashes as well as a final
+/// `index.html` are removed. Of the query string only the parameters named in `query` are
+/// kept, sorted, for sites that tell pages apart by them. Normalizing a normalized url
+/// changes nothing
+pub fn normalize(url: &str, query: &[String]) -> String {
+  let url = url.trim();
+  let url = url.split('#').next().unwrap_or_default();
+  let (url, search) = url.split_once('?').unwrap_or((url, ""));
+  let origin = url.split_once("://").filter(|(scheme, _)| {
+    !scheme.is_empty()
+      && scheme
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+  });
+  let path = match origin {
+    Some((_, rest)) => rest.find('/').map_or("", |start| &rest[start..]),
+    None => url,
+  };
+  let path = decode_path(path);
+  let mut segments = path
+    .split('/')
+    .filter(|segment| !segment.is_empty())
+    .collect::<Vec<_>>();
+  if matches!(segments.last(), Some(&"index.html" | &"index.htm")) {
+    segments.pop();
+  }
+  let mut params = search
+    .split('&')
+    .filter(|param| {
+      let name = param.split('=').next().unwrap_or_default();
+      query.iter().any(|kept| kept == name)
+    })
+    .collect::<Vec<_>>();
+  params.sort_unstable();
+  if params.is_empty() {
+    format!("/{}", segments.join("/"))
+  } else {
+    format!("/{}?{}", segments.join("/"), params.join("&"))
+  }
+}
+
+/// Where `url` ends up when `from` moves to `to`. With `prefix`, pages below `from` and
+/// its kept query strings move along. All three are normalized urls
+pub fn relocate(url: &str, from: &str, to: &str, prefix: bool) -> Option<String> {
+  if url == from {
+    return Some(to.to_string());
+  }
+  if !prefix {
+    return None;
+  }
+  let rest = if from == "/" {
+    url
+      .strip_prefix('/')
+      .filter(|rest| rest.starts_with('?'))
+      .unwrap_or(url)
+  } else {
+    url
+      .strip_prefix(from)
+      .filter(|rest| rest.starts_with(['/', '?']))?
+  };
+  Some(if to == "/" && rest.starts_with('/') {
+    rest.to_string()
+  } else {
+    format!("{to}{rest}")
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn normalize_urls() {
+    let query = ["lang".to_string(), "p".to_string()];
+    let cases = [
+      ("/", "/"),
+      ("", "/"),
+      ("/blog/post/", "/blog/post"),
+      ("//blog///post//", "/blog/post"),
+      ("/blog/index.html", "/blog"),
+      ("/index.htm", "/"),
+      (
+        "https://example.com/Blog/Post/?utm=1#comments",
+        "/blog/post",
+      ),
+      ("https://example.com", "/"),
+      ("/post?p=2&utm=x&lang=en", "/post?lang=en&p=2"),
+      ("/post?utm=x", "/post"),
+      ("/%E6%96%87%E7%AB%A0/", "/文章"),
+      ("/Caf%C3%A9", "/café"),
+      ("/a%3Fb", "/a%3Fb"),
+      ("/a%3fb%23c", "/a%3Fb%23c"),
+      ("/a%2Fb/c", "/a%2Fb/c"),
+      ("/100%25", "/100%25"),
+      ("/100%", "/100%"),
+      ("/%zz", "/%zz"),
+      ("/out/http://example.com/", "/out/http:/example.com"),
+    ];
+    for (url, expected) in cases {
+      let normalized = normalize(url, &query);
+      assert_eq!(normalized, expected, "{url}");
+      assert_eq!(normalize(&normalized, &query), normalized, "{url} twice");
+    }
+  }
+
+  #[test]
+  fn relocate_pages() {
+    assert_eq!(relocate("/a", "/a", "/b", false).as_deref(), Some("/b"));
+    assert_eq!(relocate("/a/c", "/a", "/b", false), None);
+    assert_eq!(relocate("/a/c", "/a", "/b", true).as_deref(), Some("/b/c"));
+    assert_eq!(relocate("/ab", "/a", "/b", true), None);
+    assert_eq!(
+      relocate("/a?p=1", "/a", "/b", true).as_deref(),
+      Some("/b?p=1")
+    );
+    assert_eq!(relocate("/a/c", "/a", "/", true).as_deref(), Some("/c"));
+  }
+}
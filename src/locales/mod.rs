@@ -15,8 +15,14 @@ fn zh_cn() -> TranslationMap {
   m.insert("Duplicate Content", "发送的内容之前已经发过");
   m.insert("Comment too fast", "评论太快啦，请慢点！");
   m.insert("Unauthorized", "Unauthorized");
+  m.insert("CAPTCHA_FAILED", "人机验证失败，请刷新后重试");
+  m.insert("FORBIDDEN_WORD", "评论包含违禁词，请修改后再提交");
+  m.insert("INVALID_LINK", "网址格式不正确");
+  m.insert("COMMENT_CLOSED", "该页面的评论已关闭");
   m.insert("MAIL_SUBJECT_ADMIN", "{site_name} 上有新评论了");
   m.insert("MAIL_TEMPLATE_ADMIN", "<div style='border-top:2px solid #12ADDB;box-shadow:0 1px 3px #AAAAAA;line-height:180%;padding:0 15px 12px;margin:50px auto;font-size:12px;'> <h2 style='border-bottom:1px solid #DDD;font-size:14px;font-weight:normal;padding:13px 0 10px 8px;'> 您在<a style='text-decoration:none;color: #12ADDB;' href='{site_url}' target='_blank'>{site_name}</a>上的文章有了新的评论 </h2> <p><strong>{nick}</strong>回复说：</p><div style='background-color: #f5f5f5;padding: 10px 15px;margin:18px 0;word-wrap:break-word;'>{comment}</div><p>您可以点击<a style='text-decoration:none; color:#12addb' href='{post_url}' target='_blank'>查看回复的完整內容</a></p><br/> </div>");
+  m.insert("MAIL_SUBJECT_MENTION", "{nick} 在 {site_name} 上提到了你");
+  m.insert("MAIL_TEMPLATE_MENTION", "<div style='border-top:2px solid #12ADDB;box-shadow:0 1px 3px #AAAAAA;line-height:180%;padding:0 15px 12px;margin:50px auto;font-size:12px;'> <h2 style='border-bottom:1px solid #DDD;font-size:14px;font-weight:normal;padding:13px 0 10px 8px;'> <strong>{nick}</strong>在<a style='text-decoration:none;color: #12ADDB;' href='{site_url}' target='_blank'>{site_name}</a>上提到了您 </h2> <div style='background-color: #f5f5f5;padding: 10px 15px;margin:18px 0;word-wrap:break-word;'>{comment}</div><p>您可以点击<a style='text-decoration:none; color:#12addb' href='{post_url}' target='_blank'>查看完整內容</a></p><br/> </div>");
   m.insert("Registration Confirm Mail", "【{name}】注册确认邮件");
   m.insert("confirm registration", "请点击 <a href='{url}'>{url}</a> 确认注册，链接有效时间为 1 个小时。如果不是你在注册，请忽略这封邮件。");
   m.insert("Registration confirm mail send failed", "注册确认邮件发送失败，请{%- if isAdmin -%}检查一下网站的邮件相关配置{% else %}确认你的邮箱输入无误并联系管理员{%- endif -%}。");
@@ -34,8 +40,14 @@ fn zh_tw() -> TranslationMap {
   m.insert("Duplicate Content", "發送的內容之前已經發過");
   m.insert("Comment too fast", "評論太快啦，請慢點！");
   m.insert("Unauthorized", "Unauthorized");
+  m.insert("CAPTCHA_FAILED", "人機驗證失敗，請刷新後重試");
+  m.insert("FORBIDDEN_WORD", "評論包含違禁詞，請修改後再提交");
+  m.insert("INVALID_LINK", "網址格式不正確");
+  m.insert("COMMENT_CLOSED", "該頁面的評論已關閉");
   m.insert("MAIL_SUBJECT_ADMIN", "{site_name} 上有新評論了");
   m.insert("MAIL_TEMPLATE_ADMIN", "<div style='border-top:2px solid #12ADDB;box-shadow:0 1px 3px #AAAAAA;line-height:180%;padding:0 15px 12px;margin:50px auto;font-size:12px;'> <h2 style='border-bottom:1px solid #DDD;font-size:14px;font-weight:normal;padding:13px 0 10px 8px;'> 您在<a style='text-decoration:none;color: #12ADDB;' href='{site_url}' target='_blank'>{site_name}</a>上的文章有新評論了 </h2> <p><strong>{nick}</strong>回復說：</p><div style='background-color: #f5f5f5;padding: 10px 15px;margin:18px 0;word-wrap:break-word;'>{comment}</div><p>您可以點擊<a style='text-decoration:none; color:#12addb' href='{post_url}' target='_blank'>查看回復的完整內容</a></p><br/> </div>");
+  m.insert("MAIL_SUBJECT_MENTION", "{nick} 在 {site_name} 上提到了你");
+  m.insert("MAIL_TEMPLATE_MENTION", "<div style='border-top:2px solid #12ADDB;box-shadow:0 1px 3px #AAAAAA;line-height:180%;padding:0 15px 12px;margin:50px auto;font-size:12px;'> <h2 style='border-bottom:1px solid #DDD;font-size:14px;font-weight:normal;padding:13px 0 10px 8px;'> <strong>{nick}</strong>在<a style='text-decoration:none;color: #12ADDB;' href='{site_url}' target='_blank'>{site_name}</a>上提到了您 </h2> <div style='background-color: #f5f5f5;padding: 10px 15px;margin:18px 0;word-wrap:break-word;'>{comment}</div><p>您可以點擊<a style='text-decoration:none; color:#12addb' href='{post_url}' target='_blank'>查看完整內容</a></p><br/> </div>");
   m.insert("Registration Confirm Mail", "『{name}』註冊確認郵件");
   m.insert("confirm registration", "請點擊 <a href=\"{url}\">{url}</a> 確認註冊，鏈接有效時間為 1 個小時。如果不是你在註冊，請忽略這封郵件。");
   m.insert("Registration confirm mail send failed", "註冊確認郵件發送失敗，{%- if isAdmin -%}檢查一下網站的郵件相關配置{% else %}確認你的郵箱輸入無誤後聯繫管理員{%- endif -%}。");
@@ -59,8 +71,26 @@ fn en() -> TranslationMap {
   m.insert("Duplicate Content", "Duplicate Content");
   m.insert("Comment too fast", "Comment too fast");
   m.insert("Unauthorized", "Unauthorized");
+  m.insert(
+    "CAPTCHA_FAILED",
+    "Captcha validation failed, please refresh and try again",
+  );
+  m.insert(
+    "FORBIDDEN_WORD",
+    "Comment contains forbidden words, please revise it",
+  );
+  m.insert(
+    "INVALID_LINK",
+    "Invalid link, only http and https are allowed",
+  );
+  m.insert("COMMENT_CLOSED", "Comments are closed on this page");
   m.insert("MAIL_SUBJECT_ADMIN", "New comment on {site_name}");
   m.insert("MAIL_TEMPLATE_ADMIN", "<div style='border-top:2px solid #12ADDB;box-shadow:0 1px 3px #AAAAAA;line-height:180%;padding:0 15px 12px;margin:50px auto;font-size:12px;'> <h2 style='border-bottom:1px solid #DDD;font-size:14px;font-weight:normal;padding:13px 0 10px 8px;'> New comment on <a style='text-decoration:none;color: #12ADDB;' href='{site_url}' target='_blank'>{site_name}</a> </h2> <p><strong>{nick}</strong> wrote:</p><div style='background-color: #f5f5f5;padding: 10px 15px;margin:18px 0;word-wrap:break-word;'>{comment}</div><p><a style='text-decoration:none; color:#12addb' href='{post_url}' target='_blank'>View page</a></p><br/></div>");
+  m.insert(
+    "MAIL_SUBJECT_MENTION",
+    "{nick} mentioned you on {site_name}",
+  );
+  m.insert("MAIL_TEMPLATE_MENTION", "<div style='border-top:2px solid #12ADDB;box-shadow:0 1px 3px #AAAAAA;line-height:180%;padding:0 15px 12px;margin:50px auto;font-size:12px;'> <h2 style='border-bottom:1px solid #DDD;font-size:14px;font-weight:normal;padding:13px 0 10px 8px;'> <strong>{nick}</strong> mentioned you on <a style='text-decoration:none;color: #12ADDB;' href='{site_url}' target='_blank'>{site_name}</a> </h2> <div style='background-color: #f5f5f5;padding: 10px 15px;margin:18px 0;word-wrap:break-word;'>{comment}</div><p><a style='text-decoration:none; color:#12addb' href='{post_url}' target='_blank'>View page</a></p><br/></div>");
   m.insert(
     "Registration Confirm Mail",
     "[{name}] Registration Confirm Mail",
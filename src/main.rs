@@ -11,6 +11,7 @@ mod locales;
 mod prelude;
 mod repository;
 mod response;
+mod schema;
 mod traits;
 
 #[actix_web::main]
@@ -1,25 +1,452 @@
-use crate::entities::wl_comment;
-use sea_orm::{ActiveModelTrait, DatabaseConnection, DbErr, EntityTrait};
+use std::cmp::Ordering;
+
+use async_trait::async_trait;
+use sea_orm::{
+  prelude::DateTimeUtc,
+  sea_query::{Alias, Expr},
+  ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbBackend, DbErr,
+  EntityTrait, FromQueryResult, Order, PaginatorTrait, QueryFilter, QueryOrder, QueryResult,
+  QuerySelect, QueryTrait, Select,
+};
+
+use super::{paginate, Page};
+use crate::entities::{wl_comment, Id};
+
+/// Conditions a comment has to meet, unset fields match anything
+#[derive(Debug, Clone, Default)]
+pub struct CommentFilter {
+  /// Normalized url of the page
+  pub url: Option<String>,
+  /// `Some(None)` only matches top level comments
+  pub pid: Option<Option<i32>>,
+  pub user_id: Option<i32>,
+  pub mail: Option<String>,
+  pub nick: Option<String>,
+  pub link: Option<String>,
+  pub comment: Option<String>,
+  pub status: Option<String>,
+  pub exclude_status: Vec<String>,
+  /// Inserted at or after this time
+  pub inserted_after: Option<DateTimeUtc>,
+  /// Inserted before this time
+  pub inserted_before: Option<DateTimeUtc>,
+}
+
+impl CommentFilter {
+  fn select(&self) -> Select<wl_comment::Entity> {
+    let mut select = wl_comment::Entity::find();
+    if let Some(url) = &self.url {
+      select = select.filter(wl_comment::Column::Url.eq(url));
+    }
+    match self.pid {
+      Some(Some(pid)) => select = select.filter(wl_comment::Column::Pid.eq(pid)),
+      Some(None) => select = select.filter(wl_comment::Column::Pid.is_null()),
+      None => {}
+    }
+    if let Some(user_id) = self.user_id {
+      select = select.filter(wl_comment::Column::UserId.eq(user_id));
+    }
+    if let Some(mail) = &self.mail {
+      select = select.filter(wl_comment::Column::Mail.eq(mail));
+    }
+    if let Some(nick) = &self.nick {
+      select = select.filter(wl_comment::Column::Nick.eq(nick));
+    }
+    if let Some(link) = &self.link {
+      select = select.filter(wl_comment::Column::Link.eq(link));
+    }
+    if let Some(comment) = &self.comment {
+      select = select.filter(wl_comment::Column::Comment.eq(comment));
+    }
+    if let Some(status) = &self.status {
+      select = select.filter(wl_comment::Column::Status.eq(status));
+    }
+    if !self.exclude_status.is_empty() {
+      select = select.filter(wl_comment::Column::Status.is_not_in(self.exclude_status.clone()));
+    }
+    if let Some(after) = self.inserted_after {
+      select = select.filter(wl_comment::Column::InsertedAt.gte(after));
+    }
+    if let Some(before) = self.inserted_before {
+      select = select.filter(wl_comment::Column::InsertedAt.lt(before));
+    }
+    select
+  }
+
+  pub(super) fn matches(&self, comment: &wl_comment::Model) -> bool {
+    fn eq(expected: &Option<String>, actual: &Option<String>) -> bool {
+      expected.is_none() || expected.as_ref() == actual.as_ref()
+    }
+    let inserted_at = comment.inserted_at;
+    eq(&self.url, &comment.url)
+      && self.pid.is_none_or(|pid| pid == comment.pid)
+      && self
+        .user_id
+        .is_none_or(|user_id| Some(user_id) == comment.user_id)
+      && eq(&self.mail, &comment.mail)
+      && eq(&self.nick, &comment.nick)
+      && eq(&self.link, &comment.link)
+      && eq(&self.comment, &comment.comment)
+      && self
+        .status
+        .as_ref()
+        .is_none_or(|status| *status == comment.status)
+      && !self.exclude_status.contains(&comment.status)
+      && self
+        .inserted_after
+        .is_none_or(|after| inserted_at.is_some_and(|at| at >= after))
+      && self
+        .inserted_before
+        .is_none_or(|before| inserted_at.is_some_and(|at| at < before))
+  }
+}
+
+/// Columns covered by the full-text index
+pub const SEARCH_COLUMNS: [&str; 5] = ["comment", "nick", "mail", "link", "url"];
+
+/// A comment found by a search with its relevance, higher is better
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+  pub comment: wl_comment::Model,
+  pub score: f64,
+}
+
+impl FromQueryResult for SearchHit {
+  fn from_query_result(res: &QueryResult, pre: &str) -> Result<Self, DbErr> {
+    Ok(SearchHit {
+      comment: wl_comment::Model::from_query_result(res, pre)?,
+      score: res.try_get(pre, "score")?,
+    })
+  }
+}
+
+/// Words of a search, every one has to be found in one of the indexed columns. Quotes and
+/// the other operators of the full-text query syntaxes are dropped
+fn search_terms(keyword: &str) -> Vec<String> {
+  keyword
+    .split_whitespace()
+    .map(|term| {
+      term
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '@' | '.' | '-' | '_' | '/'))
+        .collect::<String>()
+    })
+    .filter(|term| !term.is_empty())
+    .collect()
+}
+
+/// Shortest word in the InnoDB full-text index, the default of `innodb_ft_min_token_size`
+const MYSQL_MIN_TOKEN_SIZE: usize = 3;
+
+/// The default InnoDB stopwords, which are left out of the full-text index
+const MYSQL_STOPWORDS: [&str; 35] = [
+  "a", "about", "an", "are", "as", "at", "be", "by", "com", "de", "en", "for", "from", "how", "i",
+  "in", "is", "it", "la", "of", "on", "or", "that", "the", "this", "to", "was", "what", "when",
+  "where", "who", "will", "with", "und", "www",
+];
+
+/// Split the terms for a MySQL boolean mode query, whose operators split words, into the
+/// alphanumeric words the full-text index can find and the fragments it cannot because
+/// they are too short or stopwords. A required word that is not indexed matches nothing,
+/// so the fragments are looked up with `LIKE` instead
+fn mysql_terms(terms: &[String]) -> (Vec<String>, Vec<String>) {
+  terms
+    .iter()
+    .flat_map(|term| term.split(|c: char| !c.is_alphanumeric()))
+    .filter(|part| !part.is_empty())
+    .map(str::to_lowercase)
+    .partition(|part| {
+      part.chars().count() >= MYSQL_MIN_TOKEN_SIZE && !MYSQL_STOPWORDS.contains(&part.as_str())
+    })
+}
+
+/// Count how often the terms occur in the indexed columns, `None` when one is missing
+fn search_score(terms: &[String], comment: &wl_comment::Model) -> Option<f64> {
+  let fields = [
+    &comment.comment,
+    &comment.nick,
+    &comment.mail,
+    &comment.link,
+    &comment.url,
+  ]
+  .map(|field| field.as_deref().unwrap_or_default().to_lowercase());
+  let mut score = 0;
+  for term in terms {
+    let term = term.to_lowercase();
+    let found = fields
+      .iter()
+      .map(|field| field.matches(term.as_str()).count())
+      .sum::<usize>();
+    if found == 0 {
+      return None;
+    }
+    score += found;
+  }
+  Some(score as f64)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CommentOrder {
+  IdAsc,
+  IdDesc,
+  InsertedAtAsc,
+  InsertedAtDesc,
+  LikeDesc,
+}
+
+impl CommentOrder {
+  fn column(&self) -> (wl_comment::Column, Order) {
+    match self {
+      CommentOrder::IdAsc => (wl_comment::Column::Id, Order::Asc),
+      CommentOrder::IdDesc => (wl_comment::Column::Id, Order::Desc),
+      CommentOrder::InsertedAtAsc => (wl_comment::Column::InsertedAt, Order::Asc),
+      CommentOrder::InsertedAtDesc => (wl_comment::Column::InsertedAt, Order::Desc),
+      CommentOrder::LikeDesc => (wl_comment::Column::Like, Order::Desc),
+    }
+  }
+
+  pub fn compare(&self, a: &wl_comment::Model, b: &wl_comment::Model) -> Ordering {
+    match self {
+      CommentOrder::IdAsc => a.id.cmp(&b.id),
+      CommentOrder::IdDesc => b.id.cmp(&a.id),
+      CommentOrder::InsertedAtAsc => a.inserted_at.cmp(&b.inserted_at),
+      CommentOrder::InsertedAtDesc => b.inserted_at.cmp(&a.inserted_at),
+      CommentOrder::LikeDesc => b.like.cmp(&a.like),
+    }
+  }
+}
+
+#[async_trait]
+pub trait CommentRepository: std::fmt::Debug + Send + Sync {
+  async fn get_comment(&self, id: Id) -> Result<Option<wl_comment::Model>, DbErr>;
+  async fn first_comment(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+  ) -> Result<Option<wl_comment::Model>, DbErr>;
+  async fn get_comments(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+  ) -> Result<Vec<wl_comment::Model>, DbErr>;
+  async fn count_comments(&self, filter: CommentFilter) -> Result<u64, DbErr>;
+  /// `page` starts from 0
+  async fn paginate_comments(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+    page_size: u64,
+    page: u64,
+  ) -> Result<Page<wl_comment::Model>, DbErr>;
+  async fn create_comment(
+    &self,
+    comment: wl_comment::ActiveModel,
+  ) -> Result<wl_comment::Model, DbErr>;
+  async fn update_comment(
+    &self,
+    comment: wl_comment::ActiveModel,
+  ) -> Result<wl_comment::Model, DbErr>;
+  async fn delete_comment(&self, id: Id) -> Result<(), DbErr>;
+  async fn delete_comments(&self) -> Result<(), DbErr>;
+  /// Comments matching `filter` whose comment, nick, mail, link or url contain every word
+  /// of `keyword`, most relevant first. Backends without a full-text index count the
+  /// occurrences of the words
+  async fn search_comments(
+    &self,
+    keyword: &str,
+    filter: CommentFilter,
+    page_size: u64,
+    page: u64,
+  ) -> Result<Page<SearchHit>, DbErr> {
+    let terms = search_terms(keyword);
+    let mut hits = self
+      .get_comments(filter, CommentOrder::InsertedAtDesc)
+      .await?
+      .into_iter()
+      .filter_map(|comment| {
+        let score = search_score(&terms, &comment)?;
+        Some(SearchHit { comment, score })
+      })
+      .collect::<Vec<_>>();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(paginate(hits, page_size, page))
+  }
+}
 
 #[derive(Debug, Clone)]
-pub struct CommentRepository<'a> {
-  pub db: &'a DatabaseConnection,
+pub struct DbCommentRepository {
+  pub db: DatabaseConnection,
 }
 
-impl<'a> CommentRepository<'a> {
-  pub async fn get_comments(&self) -> Result<Vec<wl_comment::Model>, DbErr> {
-    wl_comment::Entity::find().all(self.db).await
+#[async_trait]
+impl CommentRepository for DbCommentRepository {
+  async fn get_comment(&self, id: Id) -> Result<Option<wl_comment::Model>, DbErr> {
+    wl_comment::Entity::find_by_id(id).one(&self.db).await
+  }
+  async fn first_comment(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+  ) -> Result<Option<wl_comment::Model>, DbErr> {
+    let (column, order) = order.column();
+    filter.select().order_by(column, order).one(&self.db).await
   }
-  pub async fn create_comment(
+  async fn get_comments(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+  ) -> Result<Vec<wl_comment::Model>, DbErr> {
+    let (column, order) = order.column();
+    filter.select().order_by(column, order).all(&self.db).await
+  }
+  async fn count_comments(&self, filter: CommentFilter) -> Result<u64, DbErr> {
+    filter.select().count(&self.db).await
+  }
+  async fn paginate_comments(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+    page_size: u64,
+    page: u64,
+  ) -> Result<Page<wl_comment::Model>, DbErr> {
+    let (column, order) = order.column();
+    let paginator = filter
+      .select()
+      .order_by(column, order)
+      .paginate(&self.db, page_size);
+    Ok(Page {
+      total_pages: paginator.num_pages().await?,
+      items: paginator.fetch_page(page).await?,
+      total_items: paginator.num_items().await?,
+    })
+  }
+  async fn create_comment(
     &self,
     comment: wl_comment::ActiveModel,
   ) -> Result<wl_comment::Model, DbErr> {
-    comment.insert(self.db).await
+    comment.insert(&self.db).await
   }
-  pub async fn update_comment(
+  async fn update_comment(
     &self,
     comment: wl_comment::ActiveModel,
   ) -> Result<wl_comment::Model, DbErr> {
-    comment.update(self.db).await
+    comment.update(&self.db).await
+  }
+  async fn delete_comment(&self, id: Id) -> Result<(), DbErr> {
+    wl_comment::Entity::delete_by_id(id).exec(&self.db).await?;
+    Ok(())
+  }
+  async fn delete_comments(&self) -> Result<(), DbErr> {
+    wl_comment::Entity::delete_many().exec(&self.db).await?;
+    Ok(())
+  }
+  async fn search_comments(
+    &self,
+    keyword: &str,
+    filter: CommentFilter,
+    page_size: u64,
+    page: u64,
+  ) -> Result<Page<SearchHit>, DbErr> {
+    let terms = search_terms(keyword);
+    if terms.is_empty() {
+      return Ok(paginate(vec![], page_size, page));
+    }
+    let mut select = filter.select();
+    let (matches, score) = match self.db.get_database_backend() {
+      // Every term is a quoted prefix query on the `wl_comment_fts` table
+      DbBackend::Sqlite => {
+        QueryTrait::query(&mut select).inner_join(
+          Alias::new("wl_comment_fts"),
+          Expr::cust("\"wl_comment_fts\".\"rowid\" = \"wl_Comment\".\"id\""),
+        );
+        let query = terms
+          .iter()
+          .map(|term| format!("\"{term}\"*"))
+          .collect::<Vec<_>>()
+          .join(" ");
+        (
+          Condition::all().add(Expr::cust_with_values(
+            "\"wl_comment_fts\" MATCH ?",
+            [query],
+          )),
+          Expr::cust("-bm25(\"wl_comment_fts\")"),
+        )
+      }
+      DbBackend::MySql => {
+        let (words, fragments) = mysql_terms(&terms);
+        let mut matches = Condition::all();
+        for fragment in fragments {
+          matches = matches.add(Expr::cust_with_values(
+            "CONCAT_WS(' ', `comment`, `nick`, `mail`, `link`, `url`) LIKE ?",
+            [format!("%{fragment}%")],
+          ));
+        }
+        if words.is_empty() {
+          (matches, Expr::cust("0e0"))
+        } else {
+          let query = words
+            .iter()
+            .map(|word| format!("+{word}*"))
+            .collect::<Vec<_>>()
+            .join(" ");
+          let against =
+            "MATCH (`comment`, `nick`, `mail`, `link`, `url`) AGAINST (? IN BOOLEAN MODE)";
+          (
+            matches.add(Expr::cust_with_values(against, [query.clone()])),
+            Expr::cust_with_values(against, [query]),
+          )
+        }
+      }
+      DbBackend::Postgres => {
+        let query = terms
+          .iter()
+          .map(|term| format!("'{term}':*"))
+          .collect::<Vec<_>>()
+          .join(" & ");
+        let document = format!("to_tsvector('simple', {})", search_document());
+        (
+          Condition::all().add(Expr::cust_with_values(
+            format!("{document} @@ to_tsquery('simple', $1)"),
+            [query.clone()],
+          )),
+          Expr::cust_with_values(
+            format!("CAST(ts_rank({document}, to_tsquery('simple', $1)) AS DOUBLE PRECISION)"),
+            [query],
+          ),
+        )
+      }
+    };
+    let paginator = select
+      .filter(matches)
+      .expr_as(score, "score")
+      .order_by_desc(Expr::cust("score"))
+      .order_by_desc(wl_comment::Column::InsertedAt)
+      .into_model::<SearchHit>()
+      .paginate(&self.db, page_size);
+    Ok(Page {
+      total_pages: paginator.num_pages().await?,
+      items: paginator.fetch_page(page).await?,
+      total_items: paginator.num_items().await?,
+    })
+  }
+}
+
+/// The indexed columns as one text, the PostgreSQL index is built on the same expression
+pub fn search_document() -> String {
+  SEARCH_COLUMNS
+    .map(|column| format!("coalesce(\"{column}\", '')"))
+    .join(" || ' ' || ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn mysql_terms_look_up_unindexed_words_with_like() {
+    let terms = search_terms("The go-lang a.b@example.com waline");
+    let (words, fragments) = mysql_terms(&terms);
+    assert_eq!(words, ["lang", "example", "waline"]);
+    assert_eq!(fragments, ["the", "go", "a", "b", "com"]);
   }
 }
@@ -1,51 +1,98 @@
-use crate::entities::wl_counter;
+use async_trait::async_trait;
 use helpers::time::utc_now;
 use sea_orm::{
-  ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait,
+  ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel,
   QueryFilter, Set,
 };
 
-#[derive(Debug, Clone)]
-pub struct CounterRepository<'a> {
-  pub db: &'a DatabaseConnection,
+use crate::entities::wl_counter;
+
+/// Add the views and reactions of `other` to `counter`
+pub fn merge_counter(counter: &mut wl_counter::Model, other: &wl_counter::Model) {
+  fn add(a: &mut Option<i32>, b: Option<i32>) {
+    if b.is_some() {
+      *a = Some(a.unwrap_or(0) + b.unwrap_or(0));
+    }
+  }
+  add(&mut counter.time, other.time);
+  add(&mut counter.reaction0, other.reaction0);
+  add(&mut counter.reaction1, other.reaction1);
+  add(&mut counter.reaction2, other.reaction2);
+  add(&mut counter.reaction3, other.reaction3);
+  add(&mut counter.reaction4, other.reaction4);
+  add(&mut counter.reaction5, other.reaction5);
+  add(&mut counter.reaction6, other.reaction6);
+  add(&mut counter.reaction7, other.reaction7);
+  add(&mut counter.reaction8, other.reaction8);
 }
 
-impl<'a> CounterRepository<'a> {
-  pub async fn has_counter(&self, url: &str) -> Result<bool, DbErr> {
-    wl_counter::Entity::find()
-      .filter(wl_counter::Column::Url.eq(url))
-      .count(self.db)
+#[async_trait]
+pub trait CounterRepository: std::fmt::Debug + Send + Sync {
+  async fn get_counter(&self, url: &str) -> Result<Option<wl_counter::Model>, DbErr>;
+  async fn get_counters(&self) -> Result<Vec<wl_counter::Model>, DbErr>;
+  async fn create_counter(&self, url: String) -> Result<wl_counter::Model, DbErr> {
+    self
+      .insert_counter(wl_counter::ActiveModel {
+        time: Set(Some(0)),
+        url: Set(url),
+        created_at: Set(Some(utc_now())),
+        ..Default::default()
+      })
       .await
-      .map(|x| x > 0)
   }
+  async fn insert_counter(
+    &self,
+    counter: wl_counter::ActiveModel,
+  ) -> Result<wl_counter::Model, DbErr>;
+  async fn save_counter(
+    &self,
+    counter: wl_counter::ActiveModel,
+  ) -> Result<wl_counter::Model, DbErr>;
+  /// Add `time` views to the counter of a page, creating it on the first view
+  async fn update_counter(&self, url: &str, time: i32) -> Result<wl_counter::Model, DbErr> {
+    let counter = match self.get_counter(url).await? {
+      Some(counter) => counter,
+      None => self.create_counter(url.to_string()).await?,
+    };
+    let views = counter.time.unwrap_or(0) + time;
+    let mut active_counter = counter.into_active_model();
+    active_counter.time = Set(Some(views));
+    active_counter.updated_at = Set(Some(utc_now()));
+    self.save_counter(active_counter).await
+  }
+  async fn delete_counters(&self) -> Result<(), DbErr>;
+}
+
+#[derive(Debug, Clone)]
+pub struct DbCounterRepository {
+  pub db: DatabaseConnection,
+}
 
-  pub async fn get_counter(&self, url: &str) -> Result<Option<wl_counter::Model>, DbErr> {
+#[async_trait]
+impl CounterRepository for DbCounterRepository {
+  async fn get_counter(&self, url: &str) -> Result<Option<wl_counter::Model>, DbErr> {
     wl_counter::Entity::find()
       .filter(wl_counter::Column::Url.eq(url))
-      .one(self.db)
+      .one(&self.db)
       .await
   }
-  pub async fn create_counter(&self, url: String) -> Result<wl_counter::Model, DbErr> {
-    wl_counter::ActiveModel {
-      time: Set(Some(0)),
-      url: Set(url),
-      created_at: Set(Some(utc_now())),
-      ..Default::default()
-    }
-    .insert(self.db)
-    .await
-  }
-  pub async fn update_counter(&self, url: &str, time: i32) {
-    //   let counter = self.get_counter(url).await?;
-    //   let mut active_counter = counter.unwrap_or_else(|| wl_counter::ActiveModel {
-    //     time: Set(Some(0)),
-    //     url: Set(url.to_string()),
-    //     created_at: Set(Some(utc_now())),
-    //     ..Default::default()
-    //   });
-    //   active_counter.time = Set(Some(
-    //     active_counter.time.take().unwrap_or(Some(0)).unwrap_or(0) + time,
-    //   ));
-    //   active_counter.update(self.db).await
+  async fn get_counters(&self) -> Result<Vec<wl_counter::Model>, DbErr> {
+    wl_counter::Entity::find().all(&self.db).await
+  }
+  async fn insert_counter(
+    &self,
+    counter: wl_counter::ActiveModel,
+  ) -> Result<wl_counter::Model, DbErr> {
+    counter.insert(&self.db).await
+  }
+  async fn save_counter(
+    &self,
+    counter: wl_counter::ActiveModel,
+  ) -> Result<wl_counter::Model, DbErr> {
+    counter.update(&self.db).await
+  }
+  async fn delete_counters(&self) -> Result<(), DbErr> {
+    wl_counter::Entity::delete_many().exec(&self.db).await?;
+    Ok(())
   }
 }
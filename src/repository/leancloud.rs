@@ -0,0 +1,830 @@
+//! LeanCloud LeanStorage backend, for the `Comment`, `Counter` and `Users` classes the
+//! Waline Node server keeps its data in
+//!
+//! LeanStorage names objects by string `objectId`s while waline-mini uses numeric ids. The
+//! objects are left as the Waline Node server writes them, an `objectId` is numbered in
+//! the `wl_LeanId` table of `DATABASE_URL` the first time it is read, and `pid`, `rid` and
+//! `user_id` are translated between both on the way in and out.
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::{header::CONTENT_TYPE, Method};
+use sea_orm::{
+  sea_query::{value::sea_value_to_json_value, OnConflict},
+  ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, IdenStatic,
+  IntoActiveModel, Iterable, QueryFilter, Set, TryIntoModel, Value,
+};
+use serde_json::{json, Map, Value as Json};
+
+use super::{
+  page::move_counters,
+  row::{Column, Row},
+  CommentFilter, CommentOrder, CommentRepository, CounterRepository, Page, PageMove,
+  PageRepository, UserRepository,
+};
+use crate::{
+  entities::{wl_comment, wl_counter, wl_lean_id, wl_users, Id},
+  error::AppError,
+  helpers::url::relocate,
+};
+
+const COMMENT: &str = "Comment";
+const COUNTER: &str = "Counter";
+const USERS: &str = "Users";
+/// Most objects LeanStorage returns for one query
+const QUERY_LIMIT: u64 = 1000;
+
+type Object = Map<String, Json>;
+
+/// Fields of a class holding the `objectId` of another object, and that object's class
+fn references(class: &str) -> &'static [(&'static str, &'static str)] {
+  match class {
+    COMMENT => &[("pid", COMMENT), ("rid", COMMENT), ("user_id", USERS)],
+    _ => &[],
+  }
+}
+
+fn lean_error(err: impl std::fmt::Display) -> DbErr {
+  DbErr::Custom(format!("LeanCloud: {err}"))
+}
+
+fn numeric_id(object: &Object) -> Option<Id> {
+  object.get("id")?.as_u64()?.try_into().ok()
+}
+
+/// Dates are `{"__type": "Date", "iso": ...}` objects, except `createdAt` and `updatedAt`
+fn parse_date(json: &Json) -> Option<DateTime<Utc>> {
+  let iso = json.get("iso").unwrap_or(json).as_str()?;
+  DateTime::parse_from_rfc3339(iso)
+    .ok()
+    .map(|date| date.to_utc())
+}
+
+/// Convert a field to the type of the column `template` belongs to
+fn to_value(template: &Value, json: &Json) -> Option<Value> {
+  let int = || {
+    json
+      .as_i64()
+      .or_else(|| json.as_f64().map(|float| float as i64))
+      .or_else(|| json.as_bool().map(i64::from))
+  };
+  Some(match template {
+    Value::TinyInt(_) => Value::TinyInt(Some(int()? as i8)),
+    Value::SmallInt(_) => Value::SmallInt(Some(int()? as i16)),
+    Value::Int(_) => Value::Int(Some(int()? as i32)),
+    Value::Unsigned(_) => Value::Unsigned(Some(int()? as u32)),
+    Value::String(_) => Value::String(Some(Box::new(match json {
+      Json::String(string) => string.clone(),
+      json => json.to_string(),
+    }))),
+    Value::ChronoDateTimeUtc(_) => Value::ChronoDateTimeUtc(Some(Box::new(parse_date(json)?))),
+    _ => return None,
+  })
+}
+
+fn to_json(value: &Value) -> Json {
+  match value {
+    Value::ChronoDateTimeUtc(Some(date)) => json!({
+      "__type": "Date",
+      "iso": date.to_rfc3339_opts(SecondsFormat::Millis, true),
+    }),
+    value => sea_value_to_json_value(value),
+  }
+}
+
+fn from_object<R: Row>(object: &Object) -> Result<R, DbErr> {
+  let id = numeric_id(object).ok_or_else(|| lean_error("object without a numeric id"))?;
+  let mut active = R::blank(id).into_active_model();
+  for column in Column::<R>::iter().filter(|column| column.as_str() != "id") {
+    let Some(template) = active.get(column).into_value() else {
+      continue;
+    };
+    let value = object
+      .get(column.as_str())
+      .filter(|json| !json.is_null())
+      .and_then(|json| to_value(&template, json));
+    match value {
+      Some(value) => active.set(column, value),
+      None if column.def().is_null() => active.set(column, template.as_null()),
+      None => {}
+    }
+  }
+  active.try_into_model()
+}
+
+/// Fields to save, the timestamps of an object are kept by LeanStorage
+fn to_object<R: Row>(changes: &R::Active, create: bool) -> Object {
+  let mut object = Object::new();
+  for column in Column::<R>::iter() {
+    let name = column.as_str();
+    if matches!(name, "id" | "createdAt" | "updatedAt") {
+      continue;
+    }
+    if let ActiveValue::Set(value) = changes.get(column) {
+      match to_json(&value) {
+        Json::Null if create => {}
+        Json::Null => {
+          object.insert(name.to_string(), json!({"__op": "Delete"}));
+        }
+        json => {
+          object.insert(name.to_string(), json);
+        }
+      }
+    }
+  }
+  object
+}
+
+fn clause(field: &str, condition: Json) -> Json {
+  let mut object = Object::new();
+  object.insert(field.to_string(), condition);
+  Json::Object(object)
+}
+
+/// `pid` and `user_id` are given as `objectId`s, see [`LeanCloud::comment_where`]
+fn comment_where(filter: &CommentFilter, pid: Option<Json>, user_id: Option<Json>) -> Json {
+  let mut clauses = Vec::new();
+  for (field, value) in [
+    ("url", &filter.url),
+    ("mail", &filter.mail),
+    ("nick", &filter.nick),
+    ("link", &filter.link),
+    ("comment", &filter.comment),
+    ("status", &filter.status),
+  ] {
+    if let Some(value) = value {
+      clauses.push(clause(field, json!(value)));
+    }
+  }
+  match (filter.pid, pid) {
+    (Some(Some(_)), Some(pid)) => clauses.push(clause("pid", pid)),
+    (Some(None), _) => clauses.push(clause("pid", json!({"$exists": false}))),
+    _ => {}
+  }
+  if let Some(user_id) = user_id {
+    clauses.push(clause("user_id", user_id));
+  }
+  if !filter.exclude_status.is_empty() {
+    clauses.push(clause("status", json!({"$nin": filter.exclude_status})));
+  }
+  if let Some(after) = filter.inserted_after {
+    clauses.push(clause(
+      "insertedAt",
+      json!({"$gte": to_json(&after.into())}),
+    ));
+  }
+  if let Some(before) = filter.inserted_before {
+    clauses.push(clause(
+      "insertedAt",
+      json!({"$lt": to_json(&before.into())}),
+    ));
+  }
+  match clauses.len() {
+    0 => json!({}),
+    1 => clauses.remove(0),
+    _ => json!({"$and": clauses}),
+  }
+}
+
+/// Where clause matching the url of a page, with `prefix` the urls below it as well
+fn url_below(from: &str, prefix: bool) -> Json {
+  match (prefix, from) {
+    (false, _) => json!({"url": from}),
+    (true, "/") => json!({}),
+    (true, _) => json!({"url": {"$regex": format!("^{}($|[/?])", regex::escape(from))}}),
+  }
+}
+
+fn comment_order(order: CommentOrder) -> &'static str {
+  match order {
+    // Ids are handed out as objects are first read, creation is the closest order
+    CommentOrder::IdAsc => "createdAt",
+    CommentOrder::IdDesc => "-createdAt",
+    CommentOrder::InsertedAtAsc => "insertedAt",
+    CommentOrder::InsertedAtDesc => "-insertedAt",
+    CommentOrder::LikeDesc => "-like",
+  }
+}
+
+pub struct LeanCloud {
+  server: String,
+  app_id: String,
+  app_key: String,
+  client: reqwest::Client,
+  /// Holds the `wl_LeanId` table
+  db: DatabaseConnection,
+  /// Numeric id of each `objectId`
+  ids: Mutex<HashMap<(&'static str, String), Id>>,
+  /// `objectId` of each numeric id
+  object_ids: Mutex<HashMap<(&'static str, Id), String>>,
+}
+
+impl std::fmt::Debug for LeanCloud {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("LeanCloud")
+      .field("server", &self.server)
+      .field("app_id", &self.app_id)
+      .finish()
+  }
+}
+
+impl LeanCloud {
+  /// The master key is used instead of the app key when it is given. International apps
+  /// have a default server, apps in China have to set their own domain
+  pub async fn connect(
+    app_id: String,
+    app_key: String,
+    master_key: Option<String>,
+    server: Option<String>,
+    db: DatabaseConnection,
+  ) -> Result<Self, AppError> {
+    let server = server
+      .filter(|server| !server.is_empty())
+      .unwrap_or_else(|| {
+        let prefix = app_id.chars().take(8).collect::<String>().to_lowercase();
+        format!("https://{prefix}.api.lncldglobal.com")
+      });
+    let app_key = match master_key.filter(|key| !key.is_empty()) {
+      Some(master_key) => format!("{master_key},master"),
+      None => app_key,
+    };
+    let client = reqwest::Client::builder()
+      .timeout(Duration::from_secs(10))
+      .build()
+      .map_err(|err| {
+        tracing::error!("{:#?}", err);
+        AppError::Error
+      })?;
+    let lean = LeanCloud {
+      server: server.trim_end_matches('/').to_string(),
+      app_id,
+      app_key,
+      client,
+      db,
+      ids: Mutex::new(HashMap::new()),
+      object_ids: Mutex::new(HashMap::new()),
+    };
+    // Fail on start rather than on the first request when the app cannot be reached
+    lean.count(USERS, &json!({})).await?;
+    tracing::info!("Using LeanCloud storage at {}", lean.server);
+    Ok(lean)
+  }
+
+  async fn request(
+    &self,
+    method: Method,
+    path: &str,
+    query: &[(&str, String)],
+    body: Option<Json>,
+  ) -> Result<Json, DbErr> {
+    let mut request = self
+      .client
+      .request(method, format!("{}/1.1/{path}", self.server))
+      .query(query)
+      .header("X-LC-Id", &self.app_id)
+      .header("X-LC-Key", &self.app_key);
+    if let Some(body) = body {
+      request = request
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_string());
+    }
+    let response = request.send().await.map_err(lean_error)?;
+    let status = response.status();
+    let text = response.text().await.map_err(lean_error)?;
+    if !status.is_success() {
+      return Err(lean_error(format!("{status} {text}")));
+    }
+    serde_json::from_str(&text).map_err(lean_error)
+  }
+
+  /// Objects matching `filter`, and how many there are in total when `count` is set
+  async fn query(
+    &self,
+    class: &str,
+    filter: &Json,
+    order: &str,
+    limit: u64,
+    skip: u64,
+    count: bool,
+  ) -> Result<(Vec<Object>, u64), DbErr> {
+    let mut query = vec![
+      ("where", filter.to_string()),
+      ("limit", limit.to_string()),
+      ("skip", skip.to_string()),
+    ];
+    if !order.is_empty() {
+      query.push(("order", order.to_string()));
+    }
+    if count {
+      query.push(("count", "1".to_string()));
+    }
+    let response = self
+      .request(Method::GET, &format!("classes/{class}"), &query, None)
+      .await?;
+    let objects = response["results"]
+      .as_array()
+      .map(|results| {
+        results
+          .iter()
+          .filter_map(|object| object.as_object().cloned())
+          .collect()
+      })
+      .unwrap_or_default();
+    Ok((objects, response["count"].as_u64().unwrap_or(0)))
+  }
+
+  async fn query_all(&self, class: &str, filter: &Json, order: &str) -> Result<Vec<Object>, DbErr> {
+    let mut objects = Vec::new();
+    loop {
+      let (page, _) = self
+        .query(
+          class,
+          filter,
+          order,
+          QUERY_LIMIT,
+          objects.len() as u64,
+          false,
+        )
+        .await?;
+      let last = (page.len() as u64) < QUERY_LIMIT;
+      objects.extend(page);
+      if last {
+        return Ok(objects);
+      }
+    }
+  }
+
+  async fn put(&self, class: &str, object_id: &str, object: Object) -> Result<(), DbErr> {
+    self
+      .request(
+        Method::PUT,
+        &format!("classes/{class}/{object_id}"),
+        &[],
+        Some(Json::Object(object)),
+      )
+      .await?;
+    Ok(())
+  }
+
+  fn remember(&self, class: &'static str, id: Id, object_id: &str) {
+    self
+      .ids
+      .lock()
+      .unwrap()
+      .insert((class, object_id.to_string()), id);
+    self
+      .object_ids
+      .lock()
+      .unwrap()
+      .insert((class, id), object_id.to_string());
+  }
+
+  /// Numeric id of an object, numbered the first time it is seen
+  async fn id(&self, class: &'static str, object_id: &str) -> Result<Id, DbErr> {
+    if let Some(id) = self
+      .ids
+      .lock()
+      .unwrap()
+      .get(&(class, object_id.to_string()))
+    {
+      return Ok(*id);
+    }
+    let key = format!("{class}/{object_id}");
+    wl_lean_id::Entity::insert(wl_lean_id::ActiveModel {
+      key: Set(key.clone()),
+      ..Default::default()
+    })
+    .on_conflict(
+      OnConflict::column(wl_lean_id::Column::Key)
+        .do_nothing_on([wl_lean_id::Column::Key])
+        .to_owned(),
+    )
+    .exec_without_returning(&self.db)
+    .await?;
+    let row = wl_lean_id::Entity::find()
+      .filter(wl_lean_id::Column::Key.eq(key))
+      .one(&self.db)
+      .await?
+      .ok_or(DbErr::RecordNotFound(object_id.to_string()))?;
+    self.remember(class, row.id, object_id);
+    Ok(row.id)
+  }
+
+  async fn object_id(&self, class: &'static str, id: Id) -> Result<Option<String>, DbErr> {
+    if let Some(object_id) = self.object_ids.lock().unwrap().get(&(class, id)) {
+      return Ok(Some(object_id.clone()));
+    }
+    let object_id = wl_lean_id::Entity::find_by_id(id)
+      .one(&self.db)
+      .await?
+      .and_then(|row| {
+        let (row_class, object_id) = row.key.split_once('/')?;
+        (row_class == class).then(|| object_id.to_string())
+      });
+    if let Some(object_id) = &object_id {
+      self.remember(class, id, object_id);
+    }
+    Ok(object_id)
+  }
+
+  /// Number an object and the objects it references
+  async fn localize<R: Row>(&self, class: &'static str, object: &Object) -> Result<R, DbErr> {
+    let mut object = object.clone();
+    let object_id = object
+      .get("objectId")
+      .and_then(Json::as_str)
+      .ok_or_else(|| lean_error("object without an objectId"))?
+      .to_string();
+    object.insert("id".to_string(), json!(self.id(class, &object_id).await?));
+    for (field, target) in references(class) {
+      if let Some(reference) = object.get(*field).and_then(Json::as_str).map(String::from) {
+        object.insert(field.to_string(), json!(self.id(target, &reference).await?));
+      }
+    }
+    from_object(&object)
+  }
+
+  /// Turn numeric references back into `objectId`s, a reference to an object that was
+  /// never read is left out
+  async fn remote(&self, class: &'static str, mut object: Object) -> Result<Object, DbErr> {
+    for (field, target) in references(class) {
+      let Some(id) = object.get(*field).and_then(Json::as_u64) else {
+        continue;
+      };
+      match self.object_id(target, id as Id).await? {
+        Some(object_id) => object.insert(field.to_string(), json!(object_id)),
+        None => object.remove(*field),
+      };
+    }
+    Ok(object)
+  }
+
+  /// Where clause of a comment filter, with the references as `objectId`s
+  async fn comment_where(&self, filter: &CommentFilter) -> Result<Json, DbErr> {
+    // An id without an object matches nothing, as no reference is stored as a number
+    let pid = match filter.pid {
+      Some(Some(pid)) => Some(
+        self
+          .object_id(COMMENT, pid as Id)
+          .await?
+          .map_or(json!(pid), Json::from),
+      ),
+      _ => None,
+    };
+    let user_id = match filter.user_id {
+      Some(user_id) => Some(
+        self
+          .object_id(USERS, user_id as Id)
+          .await?
+          .map_or(json!(user_id), Json::from),
+      ),
+      None => None,
+    };
+    Ok(comment_where(filter, pid, user_id))
+  }
+
+  /// Where clause matching the object with a numeric id
+  async fn by_id(&self, class: &'static str, id: Id) -> Result<Option<Json>, DbErr> {
+    Ok(
+      self
+        .object_id(class, id)
+        .await?
+        .map(|object_id| json!({"objectId": object_id})),
+    )
+  }
+
+  async fn find<R: Row>(
+    &self,
+    class: &'static str,
+    filter: &Json,
+    order: &str,
+    limit: u64,
+    skip: u64,
+  ) -> Result<Vec<R>, DbErr> {
+    let (objects, _) = self.query(class, filter, order, limit, skip, false).await?;
+    let mut rows = Vec::with_capacity(objects.len());
+    for object in &objects {
+      rows.push(self.localize(class, object).await?);
+    }
+    Ok(rows)
+  }
+
+  async fn find_one<R: Row>(
+    &self,
+    class: &'static str,
+    filter: &Json,
+    order: &str,
+  ) -> Result<Option<R>, DbErr> {
+    Ok(
+      self
+        .find(class, filter, order, 1, 0)
+        .await?
+        .into_iter()
+        .next(),
+    )
+  }
+
+  async fn find_all<R: Row>(
+    &self,
+    class: &'static str,
+    filter: &Json,
+    order: &str,
+  ) -> Result<Vec<R>, DbErr> {
+    let objects = self.query_all(class, filter, order).await?;
+    let mut rows = Vec::with_capacity(objects.len());
+    for object in &objects {
+      rows.push(self.localize(class, object).await?);
+    }
+    Ok(rows)
+  }
+
+  async fn count(&self, class: &str, filter: &Json) -> Result<u64, DbErr> {
+    Ok(self.query(class, filter, "", 0, 0, true).await?.1)
+  }
+
+  async fn paginate<R: Row>(
+    &self,
+    class: &'static str,
+    filter: &Json,
+    order: &str,
+    page_size: u64,
+    page: u64,
+  ) -> Result<Page<R>, DbErr> {
+    let page_size = page_size.max(1);
+    let total_items = self.count(class, filter).await?;
+    Ok(Page {
+      items: self
+        .find(
+          class,
+          filter,
+          order,
+          page_size,
+          page.saturating_mul(page_size),
+        )
+        .await?,
+      total_items,
+      total_pages: total_items.div_ceil(page_size),
+    })
+  }
+
+  async fn fetch<R: Row>(&self, class: &'static str, object_id: &str) -> Result<R, DbErr> {
+    let object = self
+      .request(
+        Method::GET,
+        &format!("classes/{class}/{object_id}"),
+        &[],
+        None,
+      )
+      .await?;
+    self
+      .localize(
+        class,
+        object.as_object().ok_or_else(|| lean_error(&object))?,
+      )
+      .await
+  }
+
+  async fn create<R: Row>(&self, class: &'static str, changes: &R::Active) -> Result<R, DbErr> {
+    let object = self.remote(class, to_object::<R>(changes, true)).await?;
+    let created = self
+      .request(
+        Method::POST,
+        &format!("classes/{class}"),
+        &[],
+        Some(Json::Object(object)),
+      )
+      .await?;
+    let object_id = created["objectId"]
+      .as_str()
+      .ok_or_else(|| lean_error(&created))?;
+    self.fetch(class, object_id).await
+  }
+
+  async fn update<R: Row>(&self, class: &'static str, changes: &R::Active) -> Result<R, DbErr> {
+    let id = R::active_id(changes).ok_or(DbErr::RecordNotUpdated)?;
+    let object_id = self
+      .object_id(class, id)
+      .await?
+      .ok_or(DbErr::RecordNotUpdated)?;
+    let object = self.remote(class, to_object::<R>(changes, false)).await?;
+    if !object.is_empty() {
+      self.put(class, &object_id, object).await?;
+    }
+    self.fetch(class, &object_id).await
+  }
+
+  async fn delete(&self, class: &'static str, filter: &Json) -> Result<(), DbErr> {
+    for object in self.query_all(class, filter, "").await? {
+      let Some(object_id) = object["objectId"].as_str() else {
+        continue;
+      };
+      self
+        .request(
+          Method::DELETE,
+          &format!("classes/{class}/{object_id}"),
+          &[],
+          None,
+        )
+        .await?;
+    }
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl CommentRepository for LeanCloud {
+  async fn get_comment(&self, id: Id) -> Result<Option<wl_comment::Model>, DbErr> {
+    match self.by_id(COMMENT, id).await? {
+      Some(filter) => self.find_one(COMMENT, &filter, "").await,
+      None => Ok(None),
+    }
+  }
+  async fn first_comment(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+  ) -> Result<Option<wl_comment::Model>, DbErr> {
+    self
+      .find_one(
+        COMMENT,
+        &self.comment_where(&filter).await?,
+        comment_order(order),
+      )
+      .await
+  }
+  async fn get_comments(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+  ) -> Result<Vec<wl_comment::Model>, DbErr> {
+    self
+      .find_all(
+        COMMENT,
+        &self.comment_where(&filter).await?,
+        comment_order(order),
+      )
+      .await
+  }
+  async fn count_comments(&self, filter: CommentFilter) -> Result<u64, DbErr> {
+    self
+      .count(COMMENT, &self.comment_where(&filter).await?)
+      .await
+  }
+  async fn paginate_comments(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+    page_size: u64,
+    page: u64,
+  ) -> Result<Page<wl_comment::Model>, DbErr> {
+    self
+      .paginate(
+        COMMENT,
+        &self.comment_where(&filter).await?,
+        comment_order(order),
+        page_size,
+        page,
+      )
+      .await
+  }
+  async fn create_comment(
+    &self,
+    comment: wl_comment::ActiveModel,
+  ) -> Result<wl_comment::Model, DbErr> {
+    self.create(COMMENT, &comment).await
+  }
+  async fn update_comment(
+    &self,
+    comment: wl_comment::ActiveModel,
+  ) -> Result<wl_comment::Model, DbErr> {
+    self.update(COMMENT, &comment).await
+  }
+  async fn delete_comment(&self, id: Id) -> Result<(), DbErr> {
+    match self.by_id(COMMENT, id).await? {
+      Some(filter) => self.delete(COMMENT, &filter).await,
+      None => Ok(()),
+    }
+  }
+  async fn delete_comments(&self) -> Result<(), DbErr> {
+    self.delete(COMMENT, &json!({})).await
+  }
+}
+
+#[async_trait]
+impl CounterRepository for LeanCloud {
+  async fn get_counter(&self, url: &str) -> Result<Option<wl_counter::Model>, DbErr> {
+    self.find_one(COUNTER, &json!({"url": url}), "").await
+  }
+  async fn get_counters(&self) -> Result<Vec<wl_counter::Model>, DbErr> {
+    self.find_all(COUNTER, &json!({}), "createdAt").await
+  }
+  async fn insert_counter(
+    &self,
+    counter: wl_counter::ActiveModel,
+  ) -> Result<wl_counter::Model, DbErr> {
+    self.create(COUNTER, &counter).await
+  }
+  async fn save_counter(
+    &self,
+    counter: wl_counter::ActiveModel,
+  ) -> Result<wl_counter::Model, DbErr> {
+    self.update(COUNTER, &counter).await
+  }
+  async fn delete_counters(&self) -> Result<(), DbErr> {
+    self.delete(COUNTER, &json!({})).await
+  }
+}
+
+/// LeanStorage has no transactions, an interrupted move is finished by moving again
+#[async_trait]
+impl PageRepository for LeanCloud {
+  async fn move_page(&self, from: &str, to: &str, prefix: bool) -> Result<PageMove, DbErr> {
+    let filter = url_below(from, prefix);
+    let mut comments = 0;
+    for object in self.query_all(COMMENT, &filter, "").await? {
+      let moved = object
+        .get("url")
+        .and_then(Json::as_str)
+        .and_then(|old| relocate(old, from, to, prefix));
+      let (Some(object_id), Some(moved)) = (object["objectId"].as_str(), moved) else {
+        continue;
+      };
+      let changes = Map::from_iter([("url".to_string(), json!(moved))]);
+      self.put(COMMENT, object_id, changes).await?;
+      comments += 1;
+    }
+    let moving: Vec<wl_counter::Model> = self.find_all(COUNTER, &filter, "createdAt").await?;
+    let targets = moving
+      .iter()
+      .filter_map(|counter| relocate(&counter.url, from, to, prefix))
+      .collect::<Vec<_>>();
+    let existing = self
+      .find_all::<wl_counter::Model>(COUNTER, &json!({"url": {"$in": targets}}), "createdAt")
+      .await?
+      .into_iter()
+      .filter(|counter| moving.iter().all(|moving| moving.id != counter.id))
+      .collect();
+    let counters = move_counters(moving, existing, from, to, prefix);
+    for counter in counters.save {
+      self
+        .update::<wl_counter::Model>(COUNTER, &counter.into_active_model().reset_all())
+        .await?;
+    }
+    for id in counters.delete {
+      if let Some(filter) = self.by_id(COUNTER, id).await? {
+        self.delete(COUNTER, &filter).await?;
+      }
+    }
+    Ok(PageMove {
+      comments,
+      counters: counters.moved,
+      merged: counters.merged,
+    })
+  }
+}
+
+#[async_trait]
+impl UserRepository for LeanCloud {
+  async fn get_users(&self) -> Result<Vec<wl_users::Model>, DbErr> {
+    self.find_all(USERS, &json!({}), "createdAt").await
+  }
+  async fn count_users(&self) -> Result<u64, DbErr> {
+    self.count(USERS, &json!({})).await
+  }
+  async fn paginate_users(
+    &self,
+    page_size: u64,
+    page: u64,
+  ) -> Result<Page<wl_users::Model>, DbErr> {
+    self
+      .paginate(USERS, &json!({}), "createdAt", page_size, page)
+      .await
+  }
+  async fn get_user_by_id(&self, id: Id) -> Result<Option<wl_users::Model>, DbErr> {
+    match self.by_id(USERS, id).await? {
+      Some(filter) => self.find_one(USERS, &filter, "").await,
+      None => Ok(None),
+    }
+  }
+  async fn get_user_by_email(&self, email: &str) -> Result<Option<wl_users::Model>, DbErr> {
+    self.find_one(USERS, &json!({"email": email}), "").await
+  }
+  async fn get_user_by_display_name(&self, name: &str) -> Result<Option<wl_users::Model>, DbErr> {
+    self
+      .find_one(USERS, &json!({"display_name": name}), "")
+      .await
+  }
+  async fn get_first_admin_user(&self) -> Result<Option<wl_users::Model>, DbErr> {
+    self
+      .find_one(USERS, &json!({"type": "administrator"}), "createdAt")
+      .await
+  }
+  async fn create_user(&self, user: wl_users::ActiveModel) -> Result<wl_users::Model, DbErr> {
+    self.create(USERS, &user).await
+  }
+  async fn update_user(&self, user: wl_users::ActiveModel) -> Result<wl_users::Model, DbErr> {
+    self.update(USERS, &user).await
+  }
+}
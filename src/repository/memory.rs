@@ -0,0 +1,270 @@
+//! Repositories kept in memory, for tests and instances that do not need to keep data
+
+use std::{
+  collections::BTreeMap,
+  sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use sea_orm::DbErr;
+
+use super::{
+  page::move_counters,
+  paginate,
+  row::{apply, Row},
+  CommentFilter, CommentOrder, CommentRepository, CounterRepository, Page, PageMove,
+  PageRepository, UserRepository,
+};
+use crate::{
+  entities::{wl_comment, wl_counter, wl_users, Id},
+  helpers::url::relocate,
+};
+
+/// Rows by id, new rows get the next id after the largest one
+#[derive(Debug)]
+struct Table<R> {
+  rows: Mutex<BTreeMap<Id, R>>,
+}
+
+impl<R> Default for Table<R> {
+  fn default() -> Self {
+    Table {
+      rows: Mutex::new(BTreeMap::new()),
+    }
+  }
+}
+
+impl<R: Row> Table<R> {
+  fn get(&self, id: Id) -> Option<R> {
+    self.rows.lock().unwrap().get(&id).cloned()
+  }
+
+  fn select(&self, filter: impl Fn(&R) -> bool) -> Vec<R> {
+    let rows = self.rows.lock().unwrap();
+    rows.values().filter(|row| filter(row)).cloned().collect()
+  }
+
+  fn insert(&self, changes: &R::Active) -> Result<R, DbErr> {
+    let mut rows = self.rows.lock().unwrap();
+    let id = rows.last_key_value().map_or(1, |(id, _)| id + 1);
+    let row = apply(R::blank(id), changes)?;
+    if rows.contains_key(&row.id()) {
+      return Err(DbErr::RecordNotInserted);
+    }
+    rows.insert(row.id(), row.clone());
+    Ok(row)
+  }
+
+  fn update(&self, changes: &R::Active) -> Result<R, DbErr> {
+    let mut rows = self.rows.lock().unwrap();
+    let row = R::active_id(changes)
+      .and_then(|id| rows.get(&id).cloned())
+      .ok_or(DbErr::RecordNotUpdated)?;
+    let row = apply(row, changes)?;
+    rows.insert(row.id(), row.clone());
+    Ok(row)
+  }
+
+  fn delete(&self, filter: impl Fn(&R) -> bool) {
+    self.rows.lock().unwrap().retain(|_, row| !filter(row));
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryCommentRepository {
+  comments: Table<wl_comment::Model>,
+}
+
+impl MemoryCommentRepository {
+  fn sorted(&self, filter: &CommentFilter, order: CommentOrder) -> Vec<wl_comment::Model> {
+    let mut comments = self.comments.select(|comment| filter.matches(comment));
+    comments.sort_by(|a, b| order.compare(a, b));
+    comments
+  }
+}
+
+#[async_trait]
+impl CommentRepository for MemoryCommentRepository {
+  async fn get_comment(&self, id: Id) -> Result<Option<wl_comment::Model>, DbErr> {
+    Ok(self.comments.get(id))
+  }
+  async fn first_comment(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+  ) -> Result<Option<wl_comment::Model>, DbErr> {
+    Ok(self.sorted(&filter, order).into_iter().next())
+  }
+  async fn get_comments(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+  ) -> Result<Vec<wl_comment::Model>, DbErr> {
+    Ok(self.sorted(&filter, order))
+  }
+  async fn count_comments(&self, filter: CommentFilter) -> Result<u64, DbErr> {
+    Ok(
+      self
+        .comments
+        .select(|comment| filter.matches(comment))
+        .len() as u64,
+    )
+  }
+  async fn paginate_comments(
+    &self,
+    filter: CommentFilter,
+    order: CommentOrder,
+    page_size: u64,
+    page: u64,
+  ) -> Result<Page<wl_comment::Model>, DbErr> {
+    Ok(paginate(self.sorted(&filter, order), page_size, page))
+  }
+  async fn create_comment(
+    &self,
+    comment: wl_comment::ActiveModel,
+  ) -> Result<wl_comment::Model, DbErr> {
+    self.comments.insert(&comment)
+  }
+  async fn update_comment(
+    &self,
+    comment: wl_comment::ActiveModel,
+  ) -> Result<wl_comment::Model, DbErr> {
+    self.comments.update(&comment)
+  }
+  async fn delete_comment(&self, id: Id) -> Result<(), DbErr> {
+    self.comments.delete(|comment| comment.id == id);
+    Ok(())
+  }
+  async fn delete_comments(&self) -> Result<(), DbErr> {
+    self.comments.delete(|_| true);
+    Ok(())
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryCounterRepository {
+  counters: Table<wl_counter::Model>,
+}
+
+#[async_trait]
+impl CounterRepository for MemoryCounterRepository {
+  async fn get_counter(&self, url: &str) -> Result<Option<wl_counter::Model>, DbErr> {
+    Ok(
+      self
+        .counters
+        .select(|counter| counter.url == url)
+        .into_iter()
+        .next(),
+    )
+  }
+  async fn get_counters(&self) -> Result<Vec<wl_counter::Model>, DbErr> {
+    Ok(self.counters.select(|_| true))
+  }
+  async fn insert_counter(
+    &self,
+    counter: wl_counter::ActiveModel,
+  ) -> Result<wl_counter::Model, DbErr> {
+    self.counters.insert(&counter)
+  }
+  async fn save_counter(
+    &self,
+    counter: wl_counter::ActiveModel,
+  ) -> Result<wl_counter::Model, DbErr> {
+    self.counters.update(&counter)
+  }
+  async fn delete_counters(&self) -> Result<(), DbErr> {
+    self.counters.delete(|_| true);
+    Ok(())
+  }
+}
+
+/// Moves pages between the comments and counters of the other memory repositories
+#[derive(Debug)]
+pub struct MemoryPageRepository {
+  pub comment: Arc<MemoryCommentRepository>,
+  pub counter: Arc<MemoryCounterRepository>,
+}
+
+#[async_trait]
+impl PageRepository for MemoryPageRepository {
+  async fn move_page(&self, from: &str, to: &str, prefix: bool) -> Result<PageMove, DbErr> {
+    let mut comments = 0;
+    for comment in self.comment.comments.rows.lock().unwrap().values_mut() {
+      if let Some(moved) = comment
+        .url
+        .as_deref()
+        .and_then(|old| relocate(old, from, to, prefix))
+      {
+        comment.url = Some(moved);
+        comments += 1;
+      }
+    }
+    let mut rows = self.counter.counters.rows.lock().unwrap();
+    let (moving, existing) = rows
+      .values()
+      .cloned()
+      .partition(|counter| relocate(&counter.url, from, to, prefix).is_some());
+    let counters = move_counters(moving, existing, from, to, prefix);
+    for counter in counters.save {
+      rows.insert(counter.id, counter);
+    }
+    rows.retain(|id, _| !counters.delete.contains(id));
+    Ok(PageMove {
+      comments,
+      counters: counters.moved,
+      merged: counters.merged,
+    })
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryUserRepository {
+  users: Table<wl_users::Model>,
+}
+
+impl MemoryUserRepository {
+  fn find(&self, filter: impl Fn(&wl_users::Model) -> bool) -> Option<wl_users::Model> {
+    self.users.select(filter).into_iter().next()
+  }
+}
+
+#[async_trait]
+impl UserRepository for MemoryUserRepository {
+  async fn get_users(&self) -> Result<Vec<wl_users::Model>, DbErr> {
+    Ok(self.users.select(|_| true))
+  }
+  async fn count_users(&self) -> Result<u64, DbErr> {
+    Ok(self.users.select(|_| true).len() as u64)
+  }
+  async fn paginate_users(
+    &self,
+    page_size: u64,
+    page: u64,
+  ) -> Result<Page<wl_users::Model>, DbErr> {
+    Ok(paginate(self.users.select(|_| true), page_size, page))
+  }
+  async fn get_user_by_id(&self, id: Id) -> Result<Option<wl_users::Model>, DbErr> {
+    Ok(self.users.get(id))
+  }
+  async fn get_user_by_email(&self, email: &str) -> Result<Option<wl_users::Model>, DbErr> {
+    Ok(self.find(|user| user.email == email))
+  }
+  async fn get_user_by_display_name(&self, name: &str) -> Result<Option<wl_users::Model>, DbErr> {
+    Ok(self.find(|user| user.display_name == name))
+  }
+  async fn get_first_admin_user(&self) -> Result<Option<wl_users::Model>, DbErr> {
+    Ok(
+      self
+        .users
+        .select(|user| user.user_type == "administrator")
+        .into_iter()
+        .min_by(|a, b| a.created_at.cmp(&b.created_at)),
+    )
+  }
+  async fn create_user(&self, user: wl_users::ActiveModel) -> Result<wl_users::Model, DbErr> {
+    self.users.insert(&user)
+  }
+  async fn update_user(&self, user: wl_users::ActiveModel) -> Result<wl_users::Model, DbErr> {
+    self.users.update(&user)
+  }
+}
@@ -1,32 +1,123 @@
+//! Data access for comments, users and counters
+//!
+//! Services only reach the Waline tables through these traits, backed either by the
+//! SQL database, by memory for tests and ephemeral instances, or by LeanStorage with the
+//! `leancloud` feature.
+
 mod comment;
 mod counter;
+#[cfg(feature = "leancloud")]
+mod leancloud;
+mod memory;
+mod page;
+mod row;
 mod user;
 
+use std::sync::Arc;
+
 use sea_orm::DatabaseConnection;
 
-pub use comment::CommentRepository;
-pub use counter::CounterRepository;
-pub use user::UserRepository;
+pub use comment::{
+  search_document, CommentFilter, CommentOrder, CommentRepository, DbCommentRepository,
+  SEARCH_COLUMNS,
+};
+pub use counter::{merge_counter, CounterRepository, DbCounterRepository};
+#[cfg(feature = "leancloud")]
+pub use leancloud::LeanCloud;
+pub use memory::{
+  MemoryCommentRepository, MemoryCounterRepository, MemoryPageRepository, MemoryUserRepository,
+};
+pub use page::{DbPageRepository, PageMove, PageRepository};
+pub use user::{DbUserRepository, UserRepository};
+
+/// One page of a listing, `total_pages` is computed from the page size
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+  pub items: Vec<T>,
+  pub total_items: u64,
+  pub total_pages: u64,
+}
+
+impl<T> Page<T> {
+  pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+    Page {
+      items: self.items.into_iter().map(f).collect(),
+      total_items: self.total_items,
+      total_pages: self.total_pages,
+    }
+  }
+}
+
+/// Slice one page out of all items
+fn paginate<T>(items: Vec<T>, page_size: u64, page: u64) -> Page<T> {
+  let page_size = page_size.max(1);
+  let total_items = items.len() as u64;
+  Page {
+    items: items
+      .into_iter()
+      .skip(page.saturating_mul(page_size) as usize)
+      .take(page_size as usize)
+      .collect(),
+    total_items,
+    total_pages: total_items.div_ceil(page_size),
+  }
+}
 
 #[derive(Debug, Clone)]
 pub struct RepositoryManager {
-  db: DatabaseConnection,
+  user: Arc<dyn UserRepository>,
+  comment: Arc<dyn CommentRepository>,
+  counter: Arc<dyn CounterRepository>,
+  page: Arc<dyn PageRepository>,
 }
 
 impl RepositoryManager {
   pub fn new(db: DatabaseConnection) -> Self {
-    Self { db }
+    Self {
+      user: Arc::new(DbUserRepository { db: db.clone() }),
+      comment: Arc::new(DbCommentRepository { db: db.clone() }),
+      counter: Arc::new(DbCounterRepository { db: db.clone() }),
+      page: Arc::new(DbPageRepository { db }),
+    }
+  }
+
+  /// Repositories that keep everything in memory and start out empty
+  pub fn memory() -> Self {
+    let comment = Arc::new(MemoryCommentRepository::default());
+    let counter = Arc::new(MemoryCounterRepository::default());
+    Self {
+      user: Arc::new(MemoryUserRepository::default()),
+      comment: comment.clone(),
+      counter: counter.clone(),
+      page: Arc::new(MemoryPageRepository { comment, counter }),
+    }
+  }
+
+  /// Repositories that share one LeanStorage app
+  #[cfg(feature = "leancloud")]
+  pub fn leancloud(lean: LeanCloud) -> Self {
+    let lean = Arc::new(lean);
+    Self {
+      user: lean.clone(),
+      comment: lean.clone(),
+      counter: lean.clone(),
+      page: lean,
+    }
+  }
+
+  pub fn user(&self) -> &dyn UserRepository {
+    self.user.as_ref()
   }
 
-  pub fn user(&self) -> UserRepository {
-    UserRepository { db: &self.db }
+  pub fn comment(&self) -> &dyn CommentRepository {
+    self.comment.as_ref()
   }
 
-  pub fn comment(&self) -> CommentRepository {
-    CommentRepository { db: &self.db }
+  pub fn counter(&self) -> &dyn CounterRepository {
+    self.counter.as_ref()
   }
 
-  pub fn counter(&self) -> CounterRepository {
-    CounterRepository { db: &self.db }
+  pub fn page(&self) -> &dyn PageRepository {
+    self.page.as_ref()
   }
 }
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use helpers::time::utc_now;
+use sea_orm::{
+  sea_query::{Expr, LikeExpr, SimpleExpr},
+  ActiveModelTrait,
+  ActiveValue::Unchanged,
+  ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, QueryFilter,
+  QueryOrder, Set, TransactionTrait,
+};
+
+use super::merge_counter;
+use crate::{
+  entities::{wl_comment, wl_counter, Id},
+  helpers::url::relocate,
+};
+
+/// What moving a page changed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageMove {
+  pub comments: u64,
+  pub counters: u64,
+  pub merged: u64,
+}
+
+#[async_trait]
+pub trait PageRepository: std::fmt::Debug + Send + Sync {
+  /// Move the comments and counters of the page at `from`, with `prefix` those of every
+  /// page below it as well, to `to`. Counters that meet an existing counter of the new url
+  /// are merged into it. All urls are normalized
+  async fn move_page(&self, from: &str, to: &str, prefix: bool) -> Result<PageMove, DbErr>;
+}
+
+/// The counters a move leaves behind
+pub(super) struct CounterMove {
+  /// Moved counters and the ones others were merged into, oldest first
+  pub save: Vec<wl_counter::Model>,
+  /// Counters merged into another one
+  pub delete: Vec<Id>,
+  pub moved: u64,
+  pub merged: u64,
+}
+
+/// Give the `moving` counters their new url, merging them into the `existing` counter of
+/// that url or into an older moving counter headed for the same url
+pub(super) fn move_counters(
+  mut moving: Vec<wl_counter::Model>,
+  existing: Vec<wl_counter::Model>,
+  from: &str,
+  to: &str,
+  prefix: bool,
+) -> CounterMove {
+  moving.sort_by_key(|counter| counter.id);
+  let mut by_url = existing
+    .into_iter()
+    .map(|counter| (counter.url.clone(), (counter, false)))
+    .collect::<HashMap<_, _>>();
+  let mut delete = vec![];
+  let mut moved = 0;
+  for mut counter in moving {
+    let Some(new_url) = relocate(&counter.url, from, to, prefix) else {
+      continue;
+    };
+    match by_url.get_mut(&new_url) {
+      Some((existing, changed)) => {
+        merge_counter(existing, &counter);
+        *changed = true;
+        delete.push(counter.id);
+      }
+      None => {
+        counter.url = new_url.clone();
+        counter.updated_at = Some(utc_now());
+        by_url.insert(new_url, (counter, true));
+        moved += 1;
+      }
+    }
+  }
+  let mut save = by_url
+    .into_values()
+    .filter(|(_, changed)| *changed)
+    .map(|(counter, _)| counter)
+    .collect::<Vec<_>>();
+  save.sort_by_key(|counter| counter.id);
+  CounterMove {
+    save,
+    merged: delete.len() as u64,
+    delete,
+    moved,
+  }
+}
+
+/// Urls a move of `from` affects, the page itself and with `prefix` the pages below it
+fn below(column: impl ColumnTrait, from: &str, prefix: bool) -> Condition {
+  if !prefix {
+    return Condition::all().add(column.eq(from));
+  }
+  if from == "/" {
+    return Condition::all();
+  }
+  let escaped = from
+    .replace('\\', "\\\\")
+    .replace('%', "\\%")
+    .replace('_', "\\_");
+  let like = |rest: &str| -> SimpleExpr {
+    Expr::col(column).like(LikeExpr::new(format!("{escaped}{rest}%")).escape('\\'))
+  };
+  Condition::any()
+    .add(column.eq(from))
+    .add(like("/"))
+    .add(like("?"))
+}
+
+#[derive(Debug, Clone)]
+pub struct DbPageRepository {
+  pub db: DatabaseConnection,
+}
+
+#[async_trait]
+impl PageRepository for DbPageRepository {
+  async fn move_page(&self, from: &str, to: &str, prefix: bool) -> Result<PageMove, DbErr> {
+    let txn = self.db.begin().await?;
+    let mut comments = 0;
+    for comment in wl_comment::Entity::find()
+      .filter(below(wl_comment::Column::Url, from, prefix))
+      .all(&txn)
+      .await?
+    {
+      let Some(moved) = comment
+        .url
+        .as_deref()
+        .and_then(|old| relocate(old, from, to, prefix))
+      else {
+        continue;
+      };
+      wl_comment::ActiveModel {
+        id: Unchanged(comment.id),
+        url: Set(Some(moved)),
+        ..Default::default()
+      }
+      .update(&txn)
+      .await?;
+      comments += 1;
+    }
+    let moving = wl_counter::Entity::find()
+      .filter(below(wl_counter::Column::Url, from, prefix))
+      .order_by_asc(wl_counter::Column::Id)
+      .all(&txn)
+      .await?;
+    let targets = moving
+      .iter()
+      .filter_map(|counter| relocate(&counter.url, from, to, prefix))
+      .collect::<Vec<_>>();
+    let existing = wl_counter::Entity::find()
+      .filter(wl_counter::Column::Url.is_in(targets))
+      .filter(wl_counter::Column::Id.is_not_in(moving.iter().map(|counter| counter.id)))
+      .all(&txn)
+      .await?;
+    let counters = move_counters(moving, existing, from, to, prefix);
+    for counter in counters.save {
+      counter.into_active_model().reset_all().update(&txn).await?;
+    }
+    wl_counter::Entity::delete_many()
+      .filter(wl_counter::Column::Id.is_in(counters.delete))
+      .exec(&txn)
+      .await?;
+    txn.commit().await?;
+    Ok(PageMove {
+      comments,
+      counters: counters.moved,
+      merged: counters.merged,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::repository::row::Row;
+
+  fn counter(id: Id, url: &str, time: i32) -> wl_counter::Model {
+    wl_counter::Model {
+      time: Some(time),
+      url: url.to_string(),
+      ..Row::blank(id)
+    }
+  }
+
+  #[test]
+  fn move_counters_merges_into_existing_and_older_counters() {
+    let moving = vec![
+      counter(4, "/blog/b", 5),
+      counter(1, "/blog/a", 2),
+      counter(3, "/blog/a", 3),
+    ];
+    let existing = vec![counter(2, "/posts/b", 7)];
+    let moved = move_counters(moving, existing, "/blog", "/posts", true);
+    let saved = moved
+      .save
+      .iter()
+      .map(|counter| (counter.id, counter.url.as_str(), counter.time))
+      .collect::<Vec<_>>();
+    assert_eq!(saved, [(1, "/posts/a", Some(5)), (2, "/posts/b", Some(12))]);
+    assert_eq!(moved.delete, [3, 4]);
+    assert_eq!((moved.moved, moved.merged), (1, 2));
+  }
+}
@@ -0,0 +1,125 @@
+use helpers::time::utc_now;
+use sea_orm::{
+  ActiveModelTrait, ActiveValue, DbErr, EntityTrait, IntoActiveModel, Iterable, ModelTrait,
+  TryIntoModel,
+};
+
+use crate::entities::{wl_comment, wl_counter, wl_users, Id};
+
+/// Column enum of the entity a row belongs to
+pub(super) type Column<R> =
+  <<<R as Row>::Active as ActiveModelTrait>::Entity as EntityTrait>::Column;
+
+/// A model of the Waline tables, as the backends without SQL store it
+pub(super) trait Row: ModelTrait + Clone + IntoActiveModel<Self::Active> {
+  type Active: ActiveModelTrait + TryIntoModel<Self>;
+
+  fn id(&self) -> Id;
+  fn active_id(active: &Self::Active) -> Option<Id>;
+  /// A row holding the column defaults of the table
+  fn blank(id: Id) -> Self;
+}
+
+impl Row for wl_comment::Model {
+  type Active = wl_comment::ActiveModel;
+
+  fn id(&self) -> Id {
+    self.id
+  }
+  fn active_id(active: &Self::Active) -> Option<Id> {
+    active.id.try_as_ref().copied()
+  }
+  fn blank(id: Id) -> Self {
+    wl_comment::Model {
+      id,
+      user_id: None,
+      comment: None,
+      inserted_at: Some(utc_now()),
+      ip: None,
+      link: None,
+      mail: None,
+      nick: None,
+      pid: None,
+      rid: None,
+      sticky: None,
+      status: String::new(),
+      like: None,
+      ua: None,
+      url: None,
+      created_at: Some(utc_now()),
+      updated_at: Some(utc_now()),
+    }
+  }
+}
+
+impl Row for wl_counter::Model {
+  type Active = wl_counter::ActiveModel;
+
+  fn id(&self) -> Id {
+    self.id
+  }
+  fn active_id(active: &Self::Active) -> Option<Id> {
+    active.id.try_as_ref().copied()
+  }
+  fn blank(id: Id) -> Self {
+    wl_counter::Model {
+      id,
+      time: None,
+      reaction0: None,
+      reaction1: None,
+      reaction2: None,
+      reaction3: None,
+      reaction4: None,
+      reaction5: None,
+      reaction6: None,
+      reaction7: None,
+      reaction8: None,
+      url: String::new(),
+      created_at: Some(utc_now()),
+      updated_at: Some(utc_now()),
+    }
+  }
+}
+
+impl Row for wl_users::Model {
+  type Active = wl_users::ActiveModel;
+
+  fn id(&self) -> Id {
+    self.id
+  }
+  fn active_id(active: &Self::Active) -> Option<Id> {
+    active.id.try_as_ref().copied()
+  }
+  fn blank(id: Id) -> Self {
+    wl_users::Model {
+      id,
+      display_name: String::new(),
+      email: String::new(),
+      password: String::new(),
+      user_type: String::new(),
+      label: None,
+      url: None,
+      avatar: None,
+      github: None,
+      twitter: None,
+      facebook: None,
+      google: None,
+      weibo: None,
+      qq: None,
+      two_factor_auth: None,
+      created_at: Some(utc_now()),
+      updated_at: Some(utc_now()),
+    }
+  }
+}
+
+/// Copy the `Set` fields of `changes` onto `row`, like an `UPDATE` would
+pub(super) fn apply<R: Row>(row: R, changes: &R::Active) -> Result<R, DbErr> {
+  let mut active = row.into_active_model();
+  for column in Column::<R>::iter() {
+    if let ActiveValue::Set(value) = changes.get(column) {
+      active.set(column, value);
+    }
+  }
+  active.try_into_model()
+}
@@ -1,38 +1,85 @@
-use crate::entities::wl_users;
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use async_trait::async_trait;
+use sea_orm::{
+  ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait,
+  QueryFilter, QueryOrder,
+};
+
+use super::Page;
+use crate::entities::{wl_users, Id};
+
+#[async_trait]
+pub trait UserRepository: std::fmt::Debug + Send + Sync {
+  async fn get_users(&self) -> Result<Vec<wl_users::Model>, DbErr>;
+  async fn count_users(&self) -> Result<u64, DbErr>;
+  /// `page` starts from 0
+  async fn paginate_users(&self, page_size: u64, page: u64)
+    -> Result<Page<wl_users::Model>, DbErr>;
+  async fn get_user_by_id(&self, id: Id) -> Result<Option<wl_users::Model>, DbErr>;
+  async fn get_user_by_email(&self, email: &str) -> Result<Option<wl_users::Model>, DbErr>;
+  async fn get_user_by_display_name(&self, name: &str) -> Result<Option<wl_users::Model>, DbErr>;
+  /// The administrator created first, who cannot be demoted
+  async fn get_first_admin_user(&self) -> Result<Option<wl_users::Model>, DbErr>;
+  async fn is_admin_user(&self, email: &str) -> Result<bool, DbErr> {
+    match self.get_user_by_email(email).await? {
+      Some(user) => Ok(user.user_type == "administrator"),
+      None => Ok(false),
+    }
+  }
+  async fn create_user(&self, user: wl_users::ActiveModel) -> Result<wl_users::Model, DbErr>;
+  async fn update_user(&self, user: wl_users::ActiveModel) -> Result<wl_users::Model, DbErr>;
+}
 
 #[derive(Debug, Clone)]
-pub struct UserRepository<'a> {
-  pub db: &'a DatabaseConnection,
+pub struct DbUserRepository {
+  pub db: DatabaseConnection,
 }
 
-impl<'a> UserRepository<'a> {
-  pub async fn get_users(&self) -> Result<Vec<wl_users::Model>, DbErr> {
-    wl_users::Entity::find().all(self.db).await
+#[async_trait]
+impl UserRepository for DbUserRepository {
+  async fn get_users(&self) -> Result<Vec<wl_users::Model>, DbErr> {
+    wl_users::Entity::find().all(&self.db).await
+  }
+  async fn count_users(&self) -> Result<u64, DbErr> {
+    wl_users::Entity::find().count(&self.db).await
+  }
+  async fn paginate_users(
+    &self,
+    page_size: u64,
+    page: u64,
+  ) -> Result<Page<wl_users::Model>, DbErr> {
+    let paginator = wl_users::Entity::find().paginate(&self.db, page_size);
+    Ok(Page {
+      total_pages: paginator.num_pages().await?,
+      items: paginator.fetch_page(page).await?,
+      total_items: paginator.num_items().await?,
+    })
   }
-  pub async fn get_user_by_id(&self, id: u32) -> Result<Option<wl_users::Model>, DbErr> {
-    wl_users::Entity::find_by_id(id).one(self.db).await
+  async fn get_user_by_id(&self, id: Id) -> Result<Option<wl_users::Model>, DbErr> {
+    wl_users::Entity::find_by_id(id).one(&self.db).await
   }
-  pub async fn get_user_by_email(&self, email: &str) -> Result<Option<wl_users::Model>, DbErr> {
+  async fn get_user_by_email(&self, email: &str) -> Result<Option<wl_users::Model>, DbErr> {
     wl_users::Entity::find()
       .filter(wl_users::Column::Email.eq(email))
-      .one(self.db)
+      .one(&self.db)
       .await
   }
-  pub async fn is_admin_user(&self, email: &str) -> Result<bool, DbErr> {
-    let user = wl_users::Entity::find()
-      .filter(wl_users::Column::Email.eq(email))
-      .one(self.db)
-      .await?;
-    match user {
-      Some(user) => Ok(user.user_type == "administrator"),
-      None => Ok(false),
-    }
+  async fn get_user_by_display_name(&self, name: &str) -> Result<Option<wl_users::Model>, DbErr> {
+    wl_users::Entity::find()
+      .filter(wl_users::Column::DisplayName.eq(name))
+      .one(&self.db)
+      .await
+  }
+  async fn get_first_admin_user(&self) -> Result<Option<wl_users::Model>, DbErr> {
+    wl_users::Entity::find()
+      .filter(wl_users::Column::UserType.eq("administrator"))
+      .order_by_asc(wl_users::Column::CreatedAt)
+      .one(&self.db)
+      .await
   }
-  pub async fn create_user(&self, user: wl_users::ActiveModel) -> Result<wl_users::Model, DbErr> {
-    user.insert(self.db).await
+  async fn create_user(&self, user: wl_users::ActiveModel) -> Result<wl_users::Model, DbErr> {
+    user.insert(&self.db).await
   }
-  pub async fn update_user(&self, user: wl_users::ActiveModel) -> Result<wl_users::Model, DbErr> {
-    user.update(self.db).await
+  async fn update_user(&self, user: wl_users::ActiveModel) -> Result<wl_users::Model, DbErr> {
+    user.update(&self.db).await
   }
 }
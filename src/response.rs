@@ -15,6 +15,10 @@ pub enum Code {
   FrequencyLimited,
   TokenExpired,
   Forbidden,
+  CaptchaFailed,
+  ForbiddenWord,
+  InvalidLink,
+  CommentClosed,
 }
 
 impl Code {
@@ -28,6 +32,10 @@ impl Code {
       Code::FrequencyLimited => get_translation(lang, "Comment too fast"),
       Code::TokenExpired => get_translation(lang, "TOKEN_EXPIRED"),
       Code::Forbidden => get_translation(lang, "FORBIDDEN"),
+      Code::CaptchaFailed => get_translation(lang, "CAPTCHA_FAILED"),
+      Code::ForbiddenWord => get_translation(lang, "FORBIDDEN_WORD"),
+      Code::InvalidLink => get_translation(lang, "INVALID_LINK"),
+      Code::CommentClosed => get_translation(lang, "COMMENT_CLOSED"),
     }
   }
 }
@@ -59,6 +67,10 @@ impl<T> Response<T> {
       Code::FrequencyLimited => 1000,
       Code::TokenExpired => 1000,
       Code::Forbidden => 403,
+      Code::CaptchaFailed => 1000,
+      Code::ForbiddenWord => 1000,
+      Code::InvalidLink => 1000,
+      Code::CommentClosed => 1000,
     };
     Response {
       data: None,
@@ -0,0 +1,410 @@
+//! schema
+
+use std::collections::HashSet;
+
+use sea_orm::{
+  sea_query::Index, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, EntityName,
+  EntityTrait, QueryFilter, QueryOrder, Schema, Statement, TransactionTrait,
+};
+
+use crate::{
+  entities::*,
+  error::AppError,
+  repository::{search_document, SEARCH_COLUMNS},
+};
+
+async fn create_table<E: EntityTrait>(
+  conn: &DatabaseConnection,
+  entity: E,
+) -> Result<(), AppError> {
+  let backend = conn.get_database_backend();
+  let statement = Schema::new(backend)
+    .create_table_from_entity(entity)
+    .if_not_exists()
+    .to_owned();
+  conn.execute(backend.build(&statement)).await?;
+  Ok(())
+}
+
+async fn sqlite_table_exists(conn: &DatabaseConnection, name: &str) -> Result<bool, AppError> {
+  let statement = Statement::from_sql_and_values(
+    DbBackend::Sqlite,
+    "SELECT name FROM sqlite_master WHERE name = ?",
+    [name.into()],
+  );
+  Ok(conn.query_one(statement).await?.is_some())
+}
+
+async fn index_exists(
+  conn: &DatabaseConnection,
+  table: &str,
+  name: &str,
+) -> Result<bool, AppError> {
+  let backend = conn.get_database_backend();
+  let sql = match backend {
+    DbBackend::Sqlite => {
+      "SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = ? AND name = ?"
+    }
+    DbBackend::MySql => {
+      "SELECT INDEX_NAME FROM information_schema.STATISTICS \
+       WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND INDEX_NAME = ?"
+    }
+    DbBackend::Postgres => {
+      "SELECT indexname FROM pg_indexes \
+       WHERE schemaname = current_schema() AND tablename = $1 AND indexname = $2"
+    }
+  };
+  let statement = Statement::from_sql_and_values(backend, sql, [table.into(), name.into()]);
+  Ok(conn.query_one(statement).await?.is_some())
+}
+
+/// Let a reader report a comment only once. Duplicates stored before the index existed
+/// are dropped, keeping the first report
+async fn create_report_index(conn: &DatabaseConnection) -> Result<(), AppError> {
+  if index_exists(conn, "wl_Report", "wl_report_visitor").await? {
+    return Ok(());
+  }
+  let mut seen = HashSet::new();
+  let duplicates = wl_report::Entity::find()
+    .order_by_asc(wl_report::Column::Id)
+    .all(conn)
+    .await?
+    .into_iter()
+    .filter(|report| !seen.insert((report.comment_id, report.visitor.clone())))
+    .map(|report| report.id)
+    .collect::<Vec<_>>();
+  if !duplicates.is_empty() {
+    wl_report::Entity::delete_many()
+      .filter(wl_report::Column::Id.is_in(duplicates))
+      .exec(conn)
+      .await?;
+  }
+  let backend = conn.get_database_backend();
+  let statement = Index::create()
+    .name("wl_report_visitor")
+    .table(wl_report::Entity)
+    .col(wl_report::Column::CommentId)
+    .col(wl_report::Column::Visitor)
+    .unique()
+    .to_owned();
+  conn.execute(backend.build(&statement)).await?;
+  Ok(())
+}
+
+/// Keep a full-text index over the searchable comment columns. SQLite gets an FTS5 table
+/// kept in sync by triggers, MySQL a `FULLTEXT` index and PostgreSQL a GIN index on the
+/// expression `search_comments` matches against
+async fn create_search_index(conn: &DatabaseConnection) -> Result<(), AppError> {
+  let columns = SEARCH_COLUMNS.join(", ");
+  match conn.get_database_backend() {
+    DbBackend::Sqlite => {
+      // The in-memory repositories leave no comment table to index
+      if !sqlite_table_exists(conn, "wl_Comment").await? {
+        return Ok(());
+      }
+      let exists = sqlite_table_exists(conn, "wl_comment_fts").await?;
+      let new = SEARCH_COLUMNS
+        .map(|column| format!("new.{column}"))
+        .join(", ");
+      let old = SEARCH_COLUMNS
+        .map(|column| format!("old.{column}"))
+        .join(", ");
+      conn
+        .execute_unprepared(&format!(
+          r#"CREATE VIRTUAL TABLE IF NOT EXISTS wl_comment_fts USING fts5({columns}, content="wl_Comment", content_rowid="id");
+CREATE TRIGGER IF NOT EXISTS wl_comment_fts_insert AFTER INSERT ON "wl_Comment" BEGIN
+  INSERT INTO wl_comment_fts(rowid, {columns}) VALUES (new.id, {new});
+END;
+CREATE TRIGGER IF NOT EXISTS wl_comment_fts_delete AFTER DELETE ON "wl_Comment" BEGIN
+  INSERT INTO wl_comment_fts(wl_comment_fts, rowid, {columns}) VALUES ('delete', old.id, {old});
+END;
+CREATE TRIGGER IF NOT EXISTS wl_comment_fts_update AFTER UPDATE ON "wl_Comment" BEGIN
+  INSERT INTO wl_comment_fts(wl_comment_fts, rowid, {columns}) VALUES ('delete', old.id, {old});
+  INSERT INTO wl_comment_fts(rowid, {columns}) VALUES (new.id, {new});
+END;"#
+        ))
+        .await?;
+      if !exists {
+        conn
+          .execute_unprepared("INSERT INTO wl_comment_fts(wl_comment_fts) VALUES ('rebuild')")
+          .await?;
+      }
+    }
+    DbBackend::MySql => {
+      let statement = Statement::from_string(
+        DbBackend::MySql,
+        "SELECT INDEX_NAME AS name FROM information_schema.STATISTICS \
+         WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'wl_Comment'",
+      );
+      let indexes = conn
+        .query_all(statement)
+        .await?
+        .iter()
+        .filter_map(|row| row.try_get::<String>("", "name").ok())
+        .collect::<Vec<_>>();
+      if !indexes.is_empty() && !indexes.iter().any(|name| name == "wl_comment_search") {
+        conn
+          .execute_unprepared(&format!(
+            "ALTER TABLE `wl_Comment` ADD FULLTEXT INDEX wl_comment_search ({columns})"
+          ))
+          .await?;
+      }
+    }
+    DbBackend::Postgres => {
+      conn
+        .execute_unprepared(&format!(
+          r#"CREATE INDEX IF NOT EXISTS wl_comment_search ON "{}" USING GIN (to_tsvector('simple', {}))"#,
+          wl_comment::Entity.table_name(),
+          search_document()
+        ))
+        .await?;
+    }
+  }
+  Ok(())
+}
+
+/// Bring the tables of an existing Waline PostgreSQL schema to the column types the
+/// entities read. Its dates are `timestamp` columns holding UTC and become `timestamptz`,
+/// `sticky` is a `boolean` and becomes a `smallint`. Columns already converted are left
+/// alone, so this runs on every start
+async fn migrate_postgres(conn: &DatabaseConnection) -> Result<(), AppError> {
+  let tables = [
+    wl_comment::Entity.table_name(),
+    wl_counter::Entity.table_name(),
+    wl_users::Entity.table_name(),
+  ];
+  let statement = Statement::from_sql_and_values(
+    DbBackend::Postgres,
+    "SELECT table_name, column_name, data_type FROM information_schema.columns \
+     WHERE table_schema = current_schema() AND table_name IN ($1, $2, $3)",
+    tables.map(Into::into),
+  );
+  let mut changes = vec![];
+  for row in conn.query_all(statement).await? {
+    let table = row.try_get::<String>("", "table_name")?;
+    let column = row.try_get::<String>("", "column_name")?;
+    let change = match (
+      column.as_str(),
+      row.try_get::<String>("", "data_type")?.as_str(),
+    ) {
+      (_, "timestamp without time zone") => {
+        format!(r#"TYPE timestamptz USING "{column}" AT TIME ZONE 'UTC'"#)
+      }
+      ("sticky", "boolean") => format!(r#"TYPE smallint USING "{column}"::int::smallint"#),
+      _ => continue,
+    };
+    changes.push(format!(
+      r#"ALTER TABLE "{table}" ALTER COLUMN "{column}" {change}"#
+    ));
+  }
+  if changes.is_empty() {
+    return Ok(());
+  }
+  let txn = conn.begin().await?;
+  for change in &changes {
+    txn.execute_unprepared(change).await?;
+  }
+  txn.commit().await?;
+  tracing::info!("Converted {} columns of the Waline schema", changes.len());
+  Ok(())
+}
+
+/// Create the tables waline-mini adds on top of the Waline schema. On PostgreSQL the
+/// Waline tables are created as well when missing, under the lower case names of the
+/// Waline PostgreSQL schema, and the columns of existing ones are converted. The comment
+/// search index is created last, indexing any comments already stored
+pub async fn sync(conn: &DatabaseConnection) -> Result<(), AppError> {
+  if conn.get_database_backend() == DbBackend::Postgres {
+    create_table(conn, wl_comment::Entity).await?;
+    create_table(conn, wl_counter::Entity).await?;
+    create_table(conn, wl_users::Entity).await?;
+    migrate_postgres(conn).await?;
+  }
+  create_table(conn, wl_store::Entity).await?;
+  create_table(conn, wl_bayes::Entity).await?;
+  create_table(conn, wl_bayes_trained::Entity).await?;
+  create_table(conn, wl_forbidden_word::Entity).await?;
+  #[cfg(feature = "leancloud")]
+  create_table(conn, wl_lean_id::Entity).await?;
+  create_table(conn, wl_page_alias::Entity).await?;
+  create_table(conn, wl_page_setting::Entity).await?;
+  create_table(conn, wl_user_trust::Entity).await?;
+  create_table(conn, wl_ban::Entity).await?;
+  create_table(conn, wl_report::Entity).await?;
+  create_report_index(conn).await?;
+  create_search_index(conn).await?;
+  Ok(())
+}
+
+/// Runs against the server in `POSTGRES_URL` with
+/// `POSTGRES_URL=postgres://... cargo test --features postgres`, in a schema of its own
+/// that is dropped afterwards
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+  use chrono::{TimeZone, Utc};
+  use sea_orm::Database;
+
+  use super::*;
+  use crate::{
+    components::comment::model::create_comment_model,
+    repository::{CommentFilter, CommentOrder, RepositoryManager},
+  };
+
+  /// The tables of `waline.pgsql` as Waline creates them
+  const WALINE_SCHEMA: &str = r#"
+CREATE TABLE wl_comment (
+  id SERIAL PRIMARY KEY,
+  user_id int DEFAULT NULL,
+  comment text,
+  insertedAt timestamp(0) without time zone NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  ip varchar(100) DEFAULT '',
+  link varchar(255) DEFAULT NULL,
+  mail varchar(255) DEFAULT NULL,
+  nick varchar(255) DEFAULT NULL,
+  pid int DEFAULT NULL,
+  rid int DEFAULT NULL,
+  sticky boolean DEFAULT NULL,
+  status varchar(50) NOT NULL DEFAULT '',
+  "like" int DEFAULT NULL,
+  ua text,
+  url varchar(255) DEFAULT NULL,
+  createdAt timestamp(0) without time zone NULL DEFAULT CURRENT_TIMESTAMP,
+  updatedAt timestamp(0) without time zone NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE wl_counter (
+  id SERIAL PRIMARY KEY,
+  time int DEFAULT NULL,
+  reaction0 int DEFAULT NULL,
+  reaction1 int DEFAULT NULL,
+  reaction2 int DEFAULT NULL,
+  reaction3 int DEFAULT NULL,
+  reaction4 int DEFAULT NULL,
+  reaction5 int DEFAULT NULL,
+  reaction6 int DEFAULT NULL,
+  reaction7 int DEFAULT NULL,
+  reaction8 int DEFAULT NULL,
+  url varchar(255) NOT NULL DEFAULT '',
+  createdAt timestamp(0) without time zone NULL DEFAULT CURRENT_TIMESTAMP,
+  updatedAt timestamp(0) without time zone NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE wl_users (
+  id SERIAL PRIMARY KEY,
+  display_name varchar(255) NOT NULL DEFAULT '',
+  email varchar(255) NOT NULL DEFAULT '',
+  password varchar(255) NOT NULL DEFAULT '',
+  type varchar(50) NOT NULL DEFAULT '',
+  label varchar(255) DEFAULT NULL,
+  url varchar(255) DEFAULT NULL,
+  avatar varchar(255) DEFAULT NULL,
+  github varchar(255) DEFAULT NULL,
+  twitter varchar(255) DEFAULT NULL,
+  facebook varchar(255) DEFAULT NULL,
+  google varchar(255) DEFAULT NULL,
+  weibo varchar(255) DEFAULT NULL,
+  qq varchar(255) DEFAULT NULL,
+  "2fa" varchar(32) DEFAULT NULL,
+  createdAt timestamp(0) without time zone NULL DEFAULT CURRENT_TIMESTAMP,
+  updatedAt timestamp(0) without time zone NULL DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO wl_users (display_name, email, password, type)
+  VALUES ('admin', 'admin@example.com', '', 'administrator');
+INSERT INTO wl_comment (comment, insertedAt, nick, sticky, status, url)
+  VALUES ('hello from node', '2020-01-02 03:04:05', 'node', true, 'approved', '/node');
+INSERT INTO wl_counter (time, url) VALUES (7, '/node');
+"#;
+
+  #[actix_web::test]
+  async fn postgres_sync_and_comment() {
+    let Ok(url) = std::env::var("POSTGRES_URL") else {
+      eprintln!("POSTGRES_URL is not set, skipping");
+      return;
+    };
+    let schema = format!("waline_{}", helpers::time::utc_now().timestamp_millis());
+    let admin = Database::connect(&url).await.unwrap();
+    admin
+      .execute_unprepared(&format!("CREATE SCHEMA {schema}"))
+      .await
+      .unwrap();
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let conn = Database::connect(format!(
+      "{url}{separator}options=-c%20search_path%3D{schema}"
+    ))
+    .await
+    .unwrap();
+    conn.execute_unprepared(WALINE_SCHEMA).await.unwrap();
+    // The Waline tables are converted once, syncing again is a no-op
+    sync(&conn).await.unwrap();
+    sync(&conn).await.unwrap();
+    let repo = RepositoryManager::new(conn.clone());
+    let node = repo
+      .comment()
+      .get_comments(
+        CommentFilter {
+          url: Some("/node".to_string()),
+          ..Default::default()
+        },
+        CommentOrder::IdAsc,
+      )
+      .await
+      .unwrap();
+    assert_eq!(node.len(), 1);
+    assert_eq!(node[0].comment.as_deref(), Some("hello from node"));
+    assert_eq!(node[0].sticky, Some(1));
+    assert_eq!(
+      node[0].inserted_at,
+      Some(Utc.with_ymd_and_hms(2020, 1, 2, 3, 4, 5).unwrap())
+    );
+    let counter = repo.counter().get_counter("/node").await.unwrap().unwrap();
+    assert_eq!(counter.time, Some(7));
+    assert!(repo
+      .user()
+      .is_admin_user("admin@example.com")
+      .await
+      .unwrap());
+    let page = "/smoke".to_string();
+    let created = repo
+      .comment()
+      .create_comment(create_comment_model(
+        None,
+        "hello postgres".to_string(),
+        String::new(),
+        "smoke@example.com".to_string(),
+        "smoke".to_string(),
+        "test".to_string(),
+        page.clone(),
+        "127.0.0.1".to_string(),
+        None,
+        None,
+      ))
+      .await
+      .unwrap();
+    let read = repo
+      .comment()
+      .get_comment(created.id)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(read, created);
+    let filter = CommentFilter {
+      url: Some(page),
+      ..Default::default()
+    };
+    let listed = repo
+      .comment()
+      .get_comments(filter.clone(), CommentOrder::IdAsc)
+      .await
+      .unwrap();
+    assert_eq!(listed, vec![created.clone()]);
+    let found = repo
+      .comment()
+      .search_comments("postgres", filter, 10, 0)
+      .await
+      .unwrap();
+    assert_eq!(found.items.len(), 1);
+    conn.close().await.unwrap();
+    admin
+      .execute_unprepared(&format!("DROP SCHEMA {schema} CASCADE"))
+      .await
+      .unwrap();
+  }
+}